@@ -0,0 +1,431 @@
+use crate::config;
+use crate::repository::*;
+
+const USAGE: &str = "Usage:
+    ragam_offline_reg [options]                 Start the registration desk
+    ragam_offline_reg [options] admin add <username> <name>
+    ragam_offline_reg [options] admin passwd <username>
+    ragam_offline_reg [options] admin disable <username>
+    ragam_offline_reg [options] admin enable <username>
+    ragam_offline_reg [options] admin promote <username>
+    ragam_offline_reg [options] admin demote <username>
+    ragam_offline_reg [options] admin list
+    ragam_offline_reg [options] event add <max participants|-> <name>
+    ragam_offline_reg [options] event add-group <min>-<max members> <max participants|-> <name>
+    ragam_offline_reg [options] category add <prefix> <fee in rupees> <hospitality,events|-> <name>
+    ragam_offline_reg [options] category fee <prefix> <fee in rupees>
+    ragam_offline_reg [options] category disable <prefix>
+    ragam_offline_reg [options] category enable <prefix>
+    ragam_offline_reg [options] category list
+    ragam_offline_reg [options] hostel tariff <rupees per night> <name>
+    ragam_offline_reg [options] college list
+    ragam_offline_reg [options] college details <id> <short code|-> <district> <city>
+    ragam_offline_reg [options] college alias <id> <alias>
+
+Passwords are read from standard input, without echo on a terminal.";
+
+/// Runs a headless subcommand and returns the process exit code.
+pub fn run(args: &[String], admins: &dyn IAdmins) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["admin", "add", username, name @ ..] if !name.is_empty() => {
+            let name = name.join(" ");
+            let password = match read_password() {
+                Some(password) => password,
+                None => return 1,
+            };
+            match admins.admin_add(&name, username, &password) {
+                Ok(admin) => {
+                    println!("Added {} ({}) with id {}", username, admin.name, admin.id);
+                    0
+                }
+                Err(err) => report(err, username),
+            }
+        }
+        ["admin", "passwd", username] => {
+            let password = match read_password() {
+                Some(password) => password,
+                None => return 1,
+            };
+            match admins.admin_set_password(username, &password) {
+                Ok(()) => {
+                    println!("Password changed for {}", username);
+                    0
+                }
+                Err(err) => report(err, username),
+            }
+        }
+        ["admin", "disable", username] => match admins.admin_set_disabled(username, true) {
+            Ok(()) => {
+                println!("Disabled {}", username);
+                0
+            }
+            Err(err) => report(err, username),
+        },
+        ["admin", "enable", username] => match admins.admin_set_disabled(username, false) {
+            Ok(()) => {
+                println!("Enabled {}", username);
+                0
+            }
+            Err(err) => report(err, username),
+        },
+        ["admin", "promote", username] => match admins.admin_set_supervisor(username, true) {
+            Ok(()) => {
+                println!("{} is now a supervisor", username);
+                0
+            }
+            Err(err) => report(err, username),
+        },
+        ["admin", "demote", username] => match admins.admin_set_supervisor(username, false) {
+            Ok(()) => {
+                println!("{} is no longer a supervisor", username);
+                0
+            }
+            Err(err) => report(err, username),
+        },
+        ["admin", "list"] => {
+            for account in admins.admin_list() {
+                let flags: Vec<&str> = [
+                    (account.supervisor, "supervisor"),
+                    (account.disabled, "disabled"),
+                ]
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, flag)| *flag)
+                .collect();
+                println!(
+                    "{:>6}  {:<20}  {:<30}  {}",
+                    account.admin.id,
+                    account.username,
+                    account.admin.name,
+                    flags.join(", ")
+                );
+            }
+            0
+        }
+        ["event", "add", max, name @ ..] if !name.is_empty() => {
+            add_event(admins, &name.join(" "), max, None)
+        }
+        ["event", "add-group", size, max, name @ ..] if !name.is_empty() => {
+            let team_size = match size.find('-').map(|i| (size[..i].parse(), size[i + 1..].parse())) {
+                Some((Ok(min), Ok(max))) if 0 < min && min <= max => (min, max),
+                _ => {
+                    eprintln!("Invalid team size \"{}\", use <min>-<max>", size);
+                    return 2;
+                }
+            };
+            add_event(admins, &name.join(" "), max, Some(team_size))
+        }
+        ["category", "add", prefix, fee, flags, name @ ..] if !name.is_empty() => {
+            if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_uppercase()) {
+                eprintln!("Invalid prefix \"{}\", use capital letters", prefix);
+                return 2;
+            }
+            let fee = match parse_fee(fee) {
+                Some(fee) => fee,
+                None => return 2,
+            };
+            let flags: Vec<&str> = match *flags {
+                "-" => Vec::new(),
+                flags => flags.split(',').collect(),
+            };
+            if let Some(flag) = flags.iter().find(|f| **f != "hospitality" && **f != "events") {
+                eprintln!("Unknown option \"{}\", use hospitality, events or -", flag);
+                return 2;
+            }
+            let name = name.join(" ");
+            let hospitality = flags.contains(&"hospitality");
+            let events = flags.contains(&"events");
+            match admins.category_add(&name, prefix, fee, hospitality, events) {
+                Ok(category) => {
+                    println!("Added category {} with id {}", category.name, category.id);
+                    0
+                }
+                Err(err) => report_category(err, prefix),
+            }
+        }
+        ["category", "fee", prefix, fee] => {
+            let fee = match parse_fee(fee) {
+                Some(fee) => fee,
+                None => return 2,
+            };
+            match admins.category_set_fee(prefix, fee) {
+                Ok(()) => {
+                    println!("Fee set to {}", fee);
+                    0
+                }
+                Err(err) => report_category(err, prefix),
+            }
+        }
+        ["category", "disable", prefix] => match admins.category_set_active(prefix, false) {
+            Ok(()) => {
+                println!("Disabled {}", prefix);
+                0
+            }
+            Err(err) => report_category(err, prefix),
+        },
+        ["category", "enable", prefix] => match admins.category_set_active(prefix, true) {
+            Ok(()) => {
+                println!("Enabled {}", prefix);
+                0
+            }
+            Err(err) => report_category(err, prefix),
+        },
+        ["category", "list"] => {
+            for category in admins.category_list() {
+                let mut flags = Vec::new();
+                if category.hospitality {
+                    flags.push("hospitality");
+                }
+                if category.events {
+                    flags.push("events");
+                }
+                if !category.active {
+                    flags.push("disabled");
+                }
+                println!(
+                    "{:<8}  {:<30}  {:>6}  {}",
+                    category.prefix,
+                    category.name,
+                    category.fee,
+                    flags.join(",")
+                );
+            }
+            0
+        }
+        ["hostel", "tariff", tariff, name @ ..] if !name.is_empty() => match tariff.parse() {
+            Ok(tariff) if tariff >= 0 => {
+                let name = name.join(" ");
+                admins.hostel_set_tariff(&name, tariff);
+                println!("Tariff for {} set to {} per night", name, tariff);
+                0
+            }
+            _ => {
+                eprintln!("Invalid tariff \"{}\"", tariff);
+                2
+            }
+        },
+        ["college", "list"] => {
+            for college in admins.college_list() {
+                println!(
+                    "{:>6}  {:<8}  {:<40}  {:<20}  {}",
+                    college.id(),
+                    college.short_code,
+                    college.name,
+                    college.city,
+                    college.aliases.join("; ")
+                );
+            }
+            0
+        }
+        ["college", "details", id, code, district, city @ ..] if !city.is_empty() => {
+            let id = match parse_college_id(id) {
+                Some(id) => id,
+                None => return 2,
+            };
+            let code = if *code == "-" { "" } else { code };
+            match admins.college_set_details(id, code, district, &city.join(" ")) {
+                Ok(()) => {
+                    println!("Details set for college {}", id);
+                    0
+                }
+                Err(err) => report_college(err, id),
+            }
+        }
+        ["college", "alias", id, alias @ ..] if !alias.is_empty() => {
+            let id = match parse_college_id(id) {
+                Some(id) => id,
+                None => return 2,
+            };
+            let alias = alias.join(" ");
+            match admins.college_add_alias(id, &alias) {
+                Ok(()) => {
+                    println!("Added alias {} to college {}", alias, id);
+                    0
+                }
+                Err(err) => report_college(err, id),
+            }
+        }
+        _ => {
+            eprintln!("{}\n\n{}", USAGE, config::FLAGS_USAGE);
+            2
+        }
+    }
+}
+
+fn add_event(
+    admins: &dyn IAdmins,
+    name: &str,
+    max: &str,
+    team_size: Option<(i32, i32)>,
+) -> i32 {
+    let max_participants = match max {
+        "-" => None,
+        max => match max.parse() {
+            Ok(max) if max > 0 => Some(max),
+            _ => {
+                eprintln!("Invalid maximum \"{}\", use a positive number or -", max);
+                return 2;
+            }
+        },
+    };
+    let event = admins.event_add(name, max_participants, team_size);
+    println!("Added event {} with id {}", event.name, event.id);
+    0
+}
+
+fn parse_fee(fee: &str) -> Option<i64> {
+    match fee.parse() {
+        Ok(fee) if fee >= 0 => Some(fee),
+        _ => {
+            eprintln!("Invalid amount \"{}\"", fee);
+            None
+        }
+    }
+}
+
+fn parse_college_id(id: &str) -> Option<i32> {
+    match id.parse() {
+        Ok(id) => Some(id),
+        Err(_) => {
+            eprintln!("Invalid college id \"{}\"", id);
+            None
+        }
+    }
+}
+
+fn read_password() -> Option<String> {
+    let password = match rpassword::prompt_password_stderr("Password: ") {
+        Ok(password) => password,
+        Err(err) => {
+            eprintln!("Could not read the password: {}", err);
+            return None;
+        }
+    };
+    if password.is_empty() {
+        eprintln!("Password must not be empty");
+        None
+    } else {
+        Some(password)
+    }
+}
+
+fn report(err: AdminError, username: &str) -> i32 {
+    match err {
+        AdminError::UsernameTaken => eprintln!("Username {} is already taken", username),
+        AdminError::UnknownUser => eprintln!("No admin with username {}", username),
+    }
+    1
+}
+
+fn report_category(err: CategoryError, prefix: &str) -> i32 {
+    match err {
+        CategoryError::PrefixTaken => eprintln!("Prefix {} is already taken", prefix),
+        CategoryError::UnknownCategory => eprintln!("No category with prefix {}", prefix),
+    }
+    1
+}
+
+fn report_college(err: CollegeError, id: i32) -> i32 {
+    match err {
+        CollegeError::UnknownCollege => eprintln!("No college with id {}", id),
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::impl_in_mem::Login;
+
+    /// Runs `command`, split on spaces, against `admins`.
+    fn cli(admins: &Login, command: &str) -> i32 {
+        let args: Vec<String> = command.split_whitespace().map(String::from).collect();
+        run(&args, admins)
+    }
+
+    #[test]
+    fn unknown_subcommand() {
+        let admins = Login::new();
+        assert_eq!(cli(&admins, ""), 2);
+        assert_eq!(cli(&admins, "admin"), 2);
+        assert_eq!(cli(&admins, "admin remove anjali"), 2);
+        // A name is required.
+        assert_eq!(cli(&admins, "event add -"), 2);
+    }
+
+    #[test]
+    fn category_add() {
+        let admins = Login::new();
+        let command = "category add K 100 hospitality,events Kerala Kalotsavam";
+        assert_eq!(cli(&admins, command), 0);
+        let category = &admins.category_list()[0];
+        assert_eq!(category.name, "Kerala Kalotsavam");
+        assert_eq!(category.prefix, "K");
+        assert_eq!(category.fee, 100);
+        assert!(category.hospitality && category.events && category.active);
+
+        assert_eq!(cli(&admins, "category add R 0 - Ragam"), 0);
+        let category = &admins.category_list()[1];
+        assert!(!category.hospitality && !category.events);
+    }
+
+    #[test]
+    fn category_add_bad_arguments() {
+        let admins = Login::new();
+        assert_eq!(cli(&admins, "category add r 100 - Ragam"), 2);
+        assert_eq!(cli(&admins, "category add R1 100 - Ragam"), 2);
+        assert_eq!(cli(&admins, "category add R -1 - Ragam"), 2);
+        assert_eq!(cli(&admins, "category add R ten - Ragam"), 2);
+        assert_eq!(cli(&admins, "category add R 100 meals Ragam"), 2);
+        assert!(admins.category_list().is_empty());
+
+        assert_eq!(cli(&admins, "category add R 100 - Ragam"), 0);
+        // The prefix is taken.
+        assert_eq!(cli(&admins, "category add R 100 - Ragam Pro"), 1);
+    }
+
+    #[test]
+    fn category_fee_and_active() {
+        let admins = Login::new();
+        cli(&admins, "category add R 100 - Ragam");
+        assert_eq!(cli(&admins, "category fee R 250"), 0);
+        assert_eq!(cli(&admins, "category disable R"), 0);
+        let category = &admins.category_list()[0];
+        assert_eq!(category.fee, 250);
+        assert!(!category.active);
+
+        assert_eq!(cli(&admins, "category fee R -5"), 2);
+        assert_eq!(cli(&admins, "category fee K 250"), 1);
+        assert_eq!(cli(&admins, "category enable K"), 1);
+    }
+
+    #[test]
+    fn event_add() {
+        let admins = Login::new();
+        assert_eq!(cli(&admins, "event add - Quiz"), 0);
+        assert_eq!(cli(&admins, "event add 40 Group Song"), 0);
+        assert_eq!(cli(&admins, "event add-group 2-5 - Drama"), 0);
+
+        assert_eq!(cli(&admins, "event add 0 Quiz"), 2);
+        assert_eq!(cli(&admins, "event add many Quiz"), 2);
+        assert_eq!(cli(&admins, "event add-group 5-2 - Drama"), 2);
+        assert_eq!(cli(&admins, "event add-group 0-2 - Drama"), 2);
+        assert_eq!(cli(&admins, "event add-group 3 - Drama"), 2);
+    }
+
+    #[test]
+    fn unknown_admin_college_and_bad_tariff() {
+        let admins = Login::new();
+        assert_eq!(cli(&admins, "admin disable nobody"), 1);
+        assert_eq!(cli(&admins, "admin promote nobody"), 1);
+        assert_eq!(cli(&admins, "admin list"), 0);
+
+        assert_eq!(cli(&admins, "college alias x REC"), 2);
+        assert_eq!(cli(&admins, "college alias 7 REC"), 1);
+        assert_eq!(cli(&admins, "college details 7 - Thrissur Thrissur"), 1);
+
+        assert_eq!(cli(&admins, "hostel tariff -1 A Block"), 2);
+        assert_eq!(cli(&admins, "hostel tariff 300 A Block"), 0);
+    }
+}
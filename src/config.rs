@@ -0,0 +1,376 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use toml::value::{Table, Value};
+
+const DEFAULT_CONFIG_PATH: &str = "regdesk.toml";
+
+/// Every setting, as (key in the config file, env var, command line flag).
+/// Later sources override earlier ones: file, then env, then flags.
+const KEYS: [(&str, &str, &str); 10] = [
+    ("backend", "REGDESK_BACKEND", "--backend"),
+    ("desk_id", "REGDESK_DESK_ID", "--desk-id"),
+    ("desk_name", "REGDESK_DESK_NAME", "--desk-name"),
+    ("edition", "REGDESK_EDITION", "--edition"),
+    ("idle_lock_minutes", "REGDESK_IDLE_LOCK_MINUTES", "--idle-lock-minutes"),
+    ("mysql.host", "MYSQL_HOST", "--mysql-host"),
+    ("mysql.port", "MYSQL_PORT", "--mysql-port"),
+    ("mysql.db", "MYSQL_DB", "--mysql-db"),
+    ("mysql.user", "MYSQL_USER", "--mysql-user"),
+    ("mysql.pass", "MYSQL_PASS", "--mysql-pass"),
+];
+
+/// Settings from `KEYS` that are numbers in the config file.
+const NUMBER_KEYS: [&str; 3] = ["edition", "idle_lock_minutes", "mysql.port"];
+
+pub const FLAGS_USAGE: &str = "Options:
+    --config <path>         Config file (default: regdesk.toml, or $REGDESK_CONFIG)
+    --backend <name>        mysql or in_memory
+    --desk-id <id>          Short code stored with everything this desk writes
+    --desk-name <name>
+    --edition <year>
+    --idle-lock-minutes <n> Lock an unattended desk after n minutes (0: never)
+    --mysql-host <host>
+    --mysql-port <port>
+    --mysql-db <name>
+    --mysql-user <user>
+    --mysql-pass <password>";
+
+#[derive(Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub mysql: MysqlConfig,
+    /// Stored with every session and registration, so records can be
+    /// traced back to the desk that made them.
+    #[serde(default = "default_desk_id")]
+    pub desk_id: String,
+    #[serde(default = "default_desk_name")]
+    pub desk_name: String,
+    /// Year of the fest, e.g. 2019. Participant IDs carry its last two digits.
+    #[serde(default = "default_edition")]
+    pub edition: u16,
+    /// Zero disables the idle lock.
+    #[serde(default = "default_idle_lock_minutes")]
+    pub idle_lock_minutes: u32,
+}
+
+#[derive(Copy, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    Mysql,
+    InMemory,
+}
+
+/// Host, db and user must be set for the `mysql` backend.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct MysqlConfig {
+    pub host: String,
+    pub port: u16,
+    pub db: String,
+    pub user: String,
+    pub pass: String,
+}
+
+impl Default for MysqlConfig {
+    fn default() -> Self {
+        MysqlConfig {
+            host: String::new(),
+            port: 3306,
+            db: String::new(),
+            user: String::new(),
+            pass: String::new(),
+        }
+    }
+}
+
+fn default_desk_id() -> String {
+    String::from("desk")
+}
+
+fn default_desk_name() -> String {
+    String::from("Desk")
+}
+
+fn default_edition() -> u16 {
+    2019
+}
+
+fn default_idle_lock_minutes() -> u32 {
+    5
+}
+
+/// Settings given on the command line. Kept apart from the config file so that
+/// a retry after a startup failure re-reads the file but keeps the flags.
+pub struct Overrides {
+    config_path: Option<String>,
+    values: HashMap<String, String>,
+}
+
+/// Splits leading `--flag value` pairs off the arguments.
+/// Returns the flags and the remaining (subcommand) arguments.
+pub fn parse_args<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<(Overrides, Vec<String>), String> {
+    let mut overrides = Overrides {
+        config_path: None,
+        values: HashMap::new(),
+    };
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            let mut rest = vec![arg];
+            rest.extend(args);
+            return Ok((overrides, rest));
+        }
+        let (flag, value) = match arg.find('=') {
+            Some(i) => (arg[..i].to_owned(), Some(arg[i + 1..].to_owned())),
+            None => (arg.clone(), None),
+        };
+        let value = match value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(format!("{} needs a value", flag)),
+        };
+        if flag == "--config" {
+            overrides.config_path = Some(value);
+        } else if let Some((key, _, _)) = KEYS.iter().find(|(_, _, f)| *f == flag) {
+            overrides.values.insert(key.to_string(), value);
+        } else {
+            return Err(format!("Unknown option {}", flag));
+        }
+    }
+    Ok((overrides, Vec::new()))
+}
+
+pub fn load(overrides: &Overrides) -> Result<Config, String> {
+    let explicit_path = overrides
+        .config_path
+        .clone()
+        .or_else(|| std::env::var("REGDESK_CONFIG").ok());
+    let path = explicit_path
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_owned());
+
+    let file = match fs::read_to_string(&path) {
+        Ok(src) => toml::from_str(&src).map_err(|err| format!("{}: {}", path, err))?,
+        // Without a config file, env vars alone are enough.
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound && explicit_path.is_none() => {
+            Table::new()
+        }
+        Err(err) => return Err(format!("Could not read {}: {}", path, err)),
+    };
+    let mut values = HashMap::new();
+    for (key, env, _) in KEYS.iter() {
+        if let Ok(value) = std::env::var(env) {
+            values.insert(key.to_string(), value);
+        }
+    }
+    for (key, value) in overrides.values.iter() {
+        values.insert(key.clone(), value.clone());
+    }
+    build(file, &values)
+}
+
+/// Applies `values`, keyed as in the config file, over the `file` settings.
+fn build(mut file: Table, values: &HashMap<String, String>) -> Result<Config, String> {
+    for (key, value) in values.iter() {
+        let value = if NUMBER_KEYS.contains(&key.as_str()) {
+            let number = value
+                .parse()
+                .map_err(|_| format!("Invalid {} \"{}\"", key, value))?;
+            Value::Integer(number)
+        } else {
+            Value::String(value.clone())
+        };
+        set(&mut file, key, value)?;
+    }
+    let config: Config = Value::Table(file)
+        .try_into()
+        .map_err(|err| format!("Invalid configuration: {}", err))?;
+
+    if config.desk_id.is_empty() || config.desk_id.len() > 32 {
+        return Err(format!(
+            "desk_id \"{}\" must be 1 to 32 characters",
+            config.desk_id
+        ));
+    }
+    if let Backend::Mysql = config.backend {
+        let mysql = &config.mysql;
+        for (key, value) in [
+            ("mysql.host", &mysql.host),
+            ("mysql.db", &mysql.db),
+            ("mysql.user", &mysql.user),
+        ]
+        .iter()
+        {
+            if value.is_empty() {
+                return Err(not_set(key));
+            }
+        }
+    }
+    Ok(config)
+}
+
+/// Sets `key`, which may be dotted like `mysql.host`, creating tables on the way.
+fn set(table: &mut Table, key: &str, value: Value) -> Result<(), String> {
+    match key.find('.') {
+        Some(dot) => {
+            let inner = table
+                .entry(&key[..dot])
+                .or_insert_with(|| Value::Table(Table::new()));
+            match inner {
+                Value::Table(inner) => set(inner, &key[dot + 1..], value),
+                _ => Err(format!("{} must be a table", &key[..dot])),
+            }
+        }
+        None => {
+            table.insert(key.to_owned(), value);
+            Ok(())
+        }
+    }
+}
+
+fn not_set(key: &str) -> String {
+    let (_, env, flag) = KEYS.iter().find(|(k, _, _)| *k == key).unwrap();
+    format!(
+        "{} is not set. Set it in the config file, ${} or {}.",
+        key, env, flag
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The arguments in `line`, split on spaces.
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    fn parse(src: &str, values: &[(&str, &str)]) -> Result<Config, String> {
+        let file = toml::from_str(src).map_err(|err| err.to_string())?;
+        let values = values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        build(file, &values)
+    }
+
+    const MYSQL: &str = "[mysql]\nhost = \"db\"\ndb = \"ragam\"\nuser = \"regdesk\"\n";
+
+    #[test]
+    fn example_file() {
+        let config = parse(include_str!("../regdesk.example.toml"), &[]).unwrap();
+        assert!(matches!(config.backend, Backend::Mysql));
+        assert_eq!(config.desk_id, "D1");
+        assert_eq!(config.desk_name, "Desk 1");
+        assert_eq!(config.edition, 2019);
+        assert_eq!(config.idle_lock_minutes, 5);
+        assert_eq!(config.mysql.host, "localhost");
+        assert_eq!(config.mysql.port, 3306);
+        assert_eq!(config.mysql.db, "ragam");
+        assert_eq!(config.mysql.user, "regdesk");
+        assert_eq!(config.mysql.pass, "");
+    }
+
+    #[test]
+    fn defaults() {
+        let config = parse("backend = \"in_memory\"", &[]).unwrap();
+        assert!(matches!(config.backend, Backend::InMemory));
+        assert_eq!(config.desk_id, "desk");
+        assert_eq!(config.desk_name, "Desk");
+        assert_eq!(config.edition, 2019);
+        assert_eq!(config.idle_lock_minutes, 5);
+
+        let config = parse(MYSQL, &[]).unwrap();
+        assert!(matches!(config.backend, Backend::Mysql));
+        assert_eq!(config.mysql.port, 3306);
+    }
+
+    #[test]
+    fn toml_syntax() {
+        let src = r#"
+            desk_name = 'Desk "A"' # literal string
+            mysql = { host = "db", db = "ragam", user = "regdesk", pass = '''p#\ss''' }
+            unused = [1.5, 2.5]
+        "#;
+        let config = parse(src, &[]).unwrap();
+        assert_eq!(config.desk_name, "Desk \"A\"");
+        assert_eq!(config.mysql.host, "db");
+        assert_eq!(config.mysql.pass, "p#\\ss");
+    }
+
+    #[test]
+    fn values_override_file() {
+        let values = [
+            ("desk_id", "D2"),
+            ("mysql.port", "3307"),
+            // Stays a string though it looks like a number.
+            ("mysql.pass", "1234"),
+        ];
+        let config = parse(&format!("desk_id = \"D1\"\n{}", MYSQL), &values).unwrap();
+        assert_eq!(config.desk_id, "D2");
+        assert_eq!(config.mysql.port, 3307);
+        assert_eq!(config.mysql.pass, "1234");
+        assert_eq!(config.mysql.host, "db");
+
+        // Everything may come from the values alone.
+        let values = [("mysql.host", "h"), ("mysql.db", "d"), ("mysql.user", "u")];
+        assert_eq!(parse("", &values).unwrap().mysql.host, "h");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src: &str, values: &[(&str, &str)]| parse(src, values).err().unwrap();
+        assert!(err("desk_id = D1", &[]).contains("line 1"));
+        assert_eq!(
+            err("", &[("mysql.host", "db"), ("mysql.db", "ragam")]),
+            "mysql.user is not set. Set it in the config file, $MYSQL_USER or --mysql-user."
+        );
+        assert_eq!(
+            err(MYSQL, &[("mysql.port", "33.06")]),
+            "Invalid mysql.port \"33.06\""
+        );
+        assert_eq!(
+            err(MYSQL, &[("desk_id", "")]),
+            "desk_id \"\" must be 1 to 32 characters"
+        );
+        assert!(err("backend = \"sqlite\"", &[]).contains("unknown variant `sqlite`"));
+        assert!(err(MYSQL, &[("edition", "100000")]).starts_with("Invalid configuration"));
+        assert!(err(&format!("{}port = 33.06", MYSQL), &[]).starts_with("Invalid configuration"));
+        assert_eq!(
+            err("mysql = 5", &[("mysql.host", "db")]),
+            "mysql must be a table"
+        );
+    }
+
+    #[test]
+    fn flags_then_subcommand() {
+        let line = "--config a.toml --desk-id=d1 seed --backend x";
+        let (overrides, rest) = parse_args(args(line)).unwrap();
+        assert_eq!(overrides.config_path, Some(String::from("a.toml")));
+        assert_eq!(overrides.values.len(), 1);
+        assert_eq!(overrides.values["desk_id"], "d1");
+        // Everything from the first non-flag on belongs to the subcommand.
+        assert_eq!(rest, vec!["seed", "--backend", "x"]);
+    }
+
+    #[test]
+    fn flags_only() {
+        let (overrides, rest) = parse_args(args("--mysql-pass=a=b --mysql-host db")).unwrap();
+        assert_eq!(overrides.config_path, None);
+        assert_eq!(overrides.values["mysql.pass"], "a=b");
+        assert_eq!(overrides.values["mysql.host"], "db");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn bad_flags() {
+        let err = |line: &str| parse_args(args(line)).err().unwrap();
+        assert_eq!(err("--desk-id"), "--desk-id needs a value");
+        assert_eq!(err("--desk d1"), "Unknown option --desk");
+    }
+}
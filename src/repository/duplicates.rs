@@ -0,0 +1,119 @@
+use super::CollegeUsage;
+
+/// Groups colleges whose names probably name the same college, such as
+/// "NIT Calicut", "NITC" and "N.I.T Calicut". Colleges with no likely
+/// duplicate are left out. Each group lists the most used college first.
+pub fn group_duplicates(colleges: Vec<CollegeUsage>) -> Vec<Vec<CollegeUsage>> {
+    let names: Vec<Name> = colleges.iter().map(|c| Name::new(&c.college.name)).collect();
+
+    // Union-find over the colleges, joining every similar pair.
+    let mut parent: Vec<usize> = (0..colleges.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..names.len() {
+        for j in i + 1..names.len() {
+            if names[i].probably_same(&names[j]) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<CollegeUsage>> = Vec::new();
+    let mut group_of: Vec<Option<usize>> = vec![None; colleges.len()];
+    for (i, college) in colleges.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        match group_of[r] {
+            Some(g) => groups[g].push(college),
+            None => {
+                group_of[r] = Some(groups.len());
+                groups.push(vec![college]);
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    for group in groups.iter_mut() {
+        group.sort_by(|a, b| {
+            b.participants
+                .cmp(&a.participants)
+                .then(a.college.id().cmp(&b.college.id()))
+        });
+    }
+    groups.sort_by(|a, b| a[0].college.name.cmp(&b[0].college.name));
+    groups
+}
+
+/// A college name broken up for comparison: lower case, dots dropped so that
+/// "N.I.T" reads as "nit", and split into words.
+struct Name {
+    words: Vec<String>,
+    compact: String,
+}
+
+impl Name {
+    fn new(name: &str) -> Self {
+        let words: Vec<String> = name
+            .to_lowercase()
+            .replace('.', "")
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let compact = words.concat();
+        Name { words, compact }
+    }
+
+    fn probably_same(&self, other: &Name) -> bool {
+        if self.compact.is_empty() || other.compact.is_empty() {
+            return false;
+        }
+        if self.compact == other.compact
+            || abbreviates(&self.compact, &other.words)
+            || abbreviates(&other.compact, &self.words)
+        {
+            return true;
+        }
+        // A typo or two, allowing one edit for every ten letters.
+        let longest = self.compact.chars().count().max(other.compact.chars().count());
+        edit_distance(&self.compact, &other.compact) * 10 <= longest
+    }
+}
+
+/// Whether `short` spells `words` by taking a prefix of each word in turn, as
+/// "nitc" does "nit calicut" and "gecthrissur" does "government engineering
+/// college thrissur". Words in the middle, such as "of", may be skipped.
+fn abbreviates(short: &str, words: &[String]) -> bool {
+    fn spell(short: &[u8], words: &[String], i: usize) -> bool {
+        if i == words.len() {
+            return short.is_empty();
+        }
+        let word = words[i].as_bytes();
+        let middle = i > 0 && i + 1 < words.len();
+        if middle && spell(short, words, i + 1) {
+            return true;
+        }
+        (1..=word.len().min(short.len()))
+            .any(|n| short[..n] == word[..n] && spell(&short[n..], words, i + 1))
+    }
+    words.len() > 1 && short.len() > 1 && spell(short.as_bytes(), words, 0)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
@@ -1,5 +1,10 @@
+use super::duplicates::group_duplicates;
+use super::undo::UndoRecord;
+use super::validation::{check_participant, normalise_phone};
 use super::*;
-use std::collections::HashMap;
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 use std::{thread, time};
 
 static DELAY: u64 = 100;
@@ -9,141 +14,797 @@ struct AdminEx {
     id: i32,
     username: String,
     password: String,
+    disabled: bool,
+    supervisor: bool,
+    failed_attempts: u32,
+    locked_until: Option<time::Instant>,
     info: Admin,
 }
 
-pub struct Login {
-    admins: HashMap<Username, AdminEx>,
-}
-
-impl Login {
-    pub fn new() -> Login {
-        Login {
-            admins: HashMap::new(),
+impl AdminEx {
+    /// Refuses disabled and locked accounts. A wrong password counts towards
+    /// the lock, and a right one clears the count.
+    fn check_password(&mut self, password: &str) -> Result<(), LoginError> {
+        if self.disabled {
+            return Err(LoginError::Disabled);
         }
-    }
-
-    pub fn generate_dummy_values(&mut self) {
-        self.admins.insert(
-            String::from("admin"),
-            AdminEx {
-                id: 1000,
-                username: String::from("admin"),
-                password: String::from("admin"),
-                info: Admin {
-                    id: 1000,
-                    name: String::from("Admin"),
-                },
-            },
-        );
-    }
-}
-
-impl ILogin for Login {
-    fn login_reg_desk(&self, username: &str, password: &str) -> Result<Box<dyn IRegDesk>, ()> {
-        thread::sleep(time::Duration::from_millis(DELAY));
-
-        if let Some(admin) = self.admins.get(username) {
-            if admin.password == password {
-                let mut reg_desk = RegDesk::new(admin.info.clone());
-                reg_desk.generate_dummy_values();
-                return Ok(Box::from(reg_desk));
+        if let Some(until) = self.locked_until {
+            let now = time::Instant::now();
+            if until > now {
+                return Err(LoginError::Locked(until - now));
             }
         }
-        Err(())
+        if self.password != password {
+            self.failed_attempts += 1;
+            if self.failed_attempts >= LOGIN_MAX_FAILED_ATTEMPTS {
+                self.failed_attempts = 0;
+                self.locked_until = Some(time::Instant::now() + LOGIN_LOCKOUT);
+                return Err(LoginError::Locked(LOGIN_LOCKOUT));
+            }
+            return Err(LoginError::WrongPassword {
+                attempts_left: LOGIN_MAX_FAILED_ATTEMPTS - self.failed_attempts,
+            });
+        }
+        self.failed_attempts = 0;
+        self.locked_until = None;
+        Ok(())
     }
 }
 
-struct RegDesk {
-    logged_in_admin: Admin,
+/// Everything the in-memory backend stores, shared by all sessions.
+struct Store {
+    admins: HashMap<Username, AdminEx>,
     participants: HashMap<i32, Participant>,
     colleges: HashMap<i32, College>,
+    events: HashMap<i32, Event>,
+    // (participant id, event id) pairs.
+    enrolments: HashSet<(i32, i32)>,
+    teams: HashMap<i32, Team>,
+    categories: Vec<Category>,
+    photos: HashMap<i32, Photo>,
+    tariffs: HashMap<String, i64>,
+    payments: Vec<Payment>,
+    college_merges: Vec<CollegeMerge>,
+    verified_at: HashMap<i32, DateTime<Local>>,
+    // Desk each participant was created, verified and allocated a room at.
+    created_desk: HashMap<i32, String>,
+    created_at: HashMap<i32, DateTime<Local>>,
+    verified_desk: HashMap<i32, String>,
+    hospitality_desk: HashMap<i32, String>,
+    hospitality_at: HashMap<i32, DateTime<Local>>,
+    // Session each payment, verification and allocation was made in, by
+    // payment or participant id.
+    payment_session: HashMap<i32, i32>,
+    verified_session: HashMap<i32, i32>,
+    hospitality_session: HashMap<i32, i32>,
+    // Session id to (admin id, whether it has ended).
+    sessions: HashMap<i32, (i32, bool)>,
+    session_started: HashMap<i32, DateTime<Local>>,
+    counted_cash: HashMap<i32, i64>,
+    admin_last_id: i32,
+    session_last_id: i32,
     participant_last_id: i32,
     college_last_id: i32,
+    event_last_id: i32,
+    team_last_id: i32,
+    payment_last_id: i32,
+}
+
+pub struct Login {
+    store: Arc<Mutex<Store>>,
 }
 
-impl RegDesk {
-    fn new(logged_in_admin: Admin) -> RegDesk {
-        RegDesk {
-            logged_in_admin,
-            participants: HashMap::new(),
-            colleges: HashMap::new(),
-            participant_last_id: 1000,
-            college_last_id: 1000,
+impl Login {
+    pub fn new() -> Login {
+        Login {
+            store: Arc::from(Mutex::from(Store {
+                admins: HashMap::new(),
+                participants: HashMap::new(),
+                colleges: HashMap::new(),
+                events: HashMap::new(),
+                enrolments: HashSet::new(),
+                teams: HashMap::new(),
+                categories: Vec::new(),
+                photos: HashMap::new(),
+                tariffs: HashMap::new(),
+                payments: Vec::new(),
+                college_merges: Vec::new(),
+                verified_at: HashMap::new(),
+                created_desk: HashMap::new(),
+                created_at: HashMap::new(),
+                verified_desk: HashMap::new(),
+                hospitality_desk: HashMap::new(),
+                hospitality_at: HashMap::new(),
+                payment_session: HashMap::new(),
+                verified_session: HashMap::new(),
+                hospitality_session: HashMap::new(),
+                sessions: HashMap::new(),
+                session_started: HashMap::new(),
+                counted_cash: HashMap::new(),
+                admin_last_id: 1000,
+                session_last_id: 1000,
+                participant_last_id: 1000,
+                college_last_id: 1000,
+                event_last_id: 1000,
+                team_last_id: 1000,
+                payment_last_id: 1000,
+            })),
         }
     }
 
-    fn generate_dummy_values(&mut self) {
-        let c1 = self.college_add(String::from("NIT Calicut"));
-        let c2 = self.college_add(String::from("GEC Kannur"));
-        self.college_add(String::from("GEC Thrissur"));
-        self.college_add(String::from("CET Trivandrum"));
-        self.college_add(String::from("TKM Kollam"));
-        self.college_add(String::from("Amrita Coimbatore"));
+    pub fn generate_dummy_values(&mut self) {
+        let admin = match self.admin_add("Admin", "admin", "admin") {
+            Ok(admin) => admin,
+            Err(_) => return,
+        };
+        // So that every screen can be tried out.
+        let _ = self.admin_set_supervisor("admin", true);
+        let mut reg_desk = RegDesk {
+            store: self.store.clone(),
+            session: Session {
+                id: 0,
+                admin,
+                desk: String::new(),
+                supervisor: true,
+            },
+            last_action: None,
+        };
+
+        let college = |name: &str, city: &str, district: &str, short_code: &str| NewCollege {
+            name: name.to_owned(),
+            city: city.to_owned(),
+            district: district.to_owned(),
+            short_code: short_code.to_owned(),
+        };
+        let c1 = reg_desk.college_add(college("NIT Calicut", "Kozhikode", "Kozhikode", "NITC"));
+        let c2 = reg_desk.college_add(college("GEC Kannur", "Kannur", "Kannur", "GECK"));
+        reg_desk.college_add(college("GEC Thrissur", "Thrissur", "Thrissur", "GECT"));
+        reg_desk.college_add(college("CET Trivandrum", "Thiruvananthapuram", "Thiruvananthapuram", "CET"));
+        reg_desk.college_add(college("TKM Kollam", "Kollam", "Kollam", "TKMCE"));
+        reg_desk.college_add(college("Amrita Coimbatore", "Coimbatore", "", ""));
+        reg_desk.college_add(college("Amrita Amritapuri", "Kollam", "Kollam", ""));
+        reg_desk.college_add(college("N.I.T Calicut", "", "", ""));
+        reg_desk.college_add(college("NITC", "", "", ""));
+        self.college_add_alias(c1.id, "National Institute of Technology Calicut").ok();
+        self.college_add_alias(c1.id, "REC Calicut").ok();
 
-        self.participant_new(
+        self.event_add("Bharatanatyam", Some(20), None);
+        self.event_add("Light Music", None, None);
+        self.event_add("Mime", Some(16), Some((6, 8)));
+        self.event_add("Group Song", None, Some((3, 7)));
+        let (ragam, kalotsavam) = match (
+            self.category_add("Ragam", "R", 300, true, false),
+            self.category_add("Kalotsavam", "K", 100, true, true),
+        ) {
+            (Ok(ragam), Ok(kalotsavam)) => (ragam, kalotsavam),
+            _ => return,
+        };
+        let _ = self.category_add("Workshop", "W", 500, false, false);
+        self.hostel_set_tariff("Mega Hostel", 150);
+
+        let _ = reg_desk.participant_new(
             ParticipantInfo {
                 name: String::from("Test"),
                 gender: Gender::Male,
                 email: String::from("test@gmail.com"),
-                phone: String::from("9876567891")
+                phone: String::from("9876567891"),
+                category: ragam,
+                kalotsavam: None,
             },
             c1,
         );
 
-        let p2 = self.participant_new(
+        let p2 = match reg_desk.participant_new(
             ParticipantInfo {
                 name: String::from("Test 2"),
                 gender: Gender::Female,
                 email: String::from("test2@gmail.com"),
-                phone: String::from("1234512346")
+                phone: String::from("8234512346"),
+                category: kalotsavam,
+                kalotsavam: Some(KalotsavamDetails {
+                    institution_type: InstitutionType::HigherSecondary,
+                    class: String::from("12"),
+                    district: String::from("Kozhikode"),
+                    date_of_birth: NaiveDate::from_ymd(2002, 5, 14),
+                }),
             },
             c2,
+        ) {
+            Ok(p2) => p2,
+            Err(_) => return,
+        };
+
+        reg_desk
+            .payment_add(
+                p2.id,
+                PaymentInfo {
+                    amount: 100,
+                    mode: PaymentMode::Cash,
+                    reference: String::new(),
+                },
+            )
+            .ok();
+        reg_desk.participant_verify_reg(p2.reg_status.err().unwrap()).ok();
+    }
+}
+
+impl ILogin for Login {
+    fn login_reg_desk(
+        &self,
+        desk: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Box<dyn IRegDesk>, LoginError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+
+        let mut store = self.store.lock().unwrap();
+        let admin = store
+            .admins
+            .get_mut(username)
+            .ok_or(LoginError::UnknownUser)?;
+        admin.check_password(password)?;
+        let supervisor = admin.supervisor;
+        let admin = admin.info.clone();
+
+        store.session_last_id += 1;
+        let session_id = store.session_last_id;
+        store.sessions.insert(session_id, (admin.id, false));
+        store.session_started.insert(session_id, Local::now());
+        Ok(Box::from(RegDesk {
+            store: self.store.clone(),
+            session: Session {
+                id: session_id,
+                admin,
+                desk: desk.to_owned(),
+                supervisor,
+            },
+            last_action: None,
+        }))
+    }
+
+    fn last_call(&self) -> Option<LastCall> {
+        Some(LastCall::Done(time::Duration::from_millis(DELAY)))
+    }
+
+    fn ping(&self) -> bool {
+        true
+    }
+
+    fn reconnect(&self) -> bool {
+        true
+    }
+
+    fn session_unlock(&self, session_id: i32, password: &str) -> Result<(), LoginError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let admin_id = match store.sessions.get(&session_id) {
+            Some(&(admin_id, false)) => admin_id,
+            _ => return Err(LoginError::UnknownUser),
+        };
+        match store.admins.values_mut().find(|a| a.id == admin_id) {
+            Some(admin) => admin.check_password(password),
+            None => Err(LoginError::UnknownUser),
+        }
+    }
+}
+
+impl IAdmins for Login {
+    fn admin_add(&self, name: &str, username: &str, password: &str) -> Result<Admin, AdminError> {
+        let mut store = self.store.lock().unwrap();
+        if store.admins.contains_key(username) {
+            return Err(AdminError::UsernameTaken);
+        }
+        store.admin_last_id += 1;
+        let info = Admin {
+            id: store.admin_last_id,
+            name: name.to_owned(),
+        };
+        store.admins.insert(
+            username.to_owned(),
+            AdminEx {
+                id: info.id,
+                username: username.to_owned(),
+                password: password.to_owned(),
+                disabled: false,
+                supervisor: false,
+                failed_attempts: 0,
+                locked_until: None,
+                info: info.clone(),
+            },
         );
+        Ok(info)
+    }
+
+    fn admin_set_password(&self, username: &str, password: &str) -> Result<(), AdminError> {
+        let mut store = self.store.lock().unwrap();
+        let admin = store
+            .admins
+            .get_mut(username)
+            .ok_or(AdminError::UnknownUser)?;
+        admin.password = password.to_owned();
+        Ok(())
+    }
+
+    fn admin_set_disabled(&self, username: &str, disabled: bool) -> Result<(), AdminError> {
+        let mut store = self.store.lock().unwrap();
+        let admin = store
+            .admins
+            .get_mut(username)
+            .ok_or(AdminError::UnknownUser)?;
+        admin.disabled = disabled;
+        if !disabled {
+            admin.failed_attempts = 0;
+            admin.locked_until = None;
+        }
+        Ok(())
+    }
+
+    fn admin_set_supervisor(&self, username: &str, supervisor: bool) -> Result<(), AdminError> {
+        let mut store = self.store.lock().unwrap();
+        let admin = store
+            .admins
+            .get_mut(username)
+            .ok_or(AdminError::UnknownUser)?;
+        admin.supervisor = supervisor;
+        Ok(())
+    }
+
+    fn admin_list(&self) -> Vec<AdminAccount> {
+        let store = self.store.lock().unwrap();
+        let mut admins: Vec<AdminAccount> = store
+            .admins
+            .values()
+            .map(|admin| AdminAccount {
+                admin: admin.info.clone(),
+                username: admin.username.clone(),
+                disabled: admin.disabled,
+                supervisor: admin.supervisor,
+            })
+            .collect();
+        admins.sort_by(|a, b| a.username.cmp(&b.username));
+        admins
+    }
+
+    fn event_add(
+        &self,
+        name: &str,
+        max_participants: Option<i32>,
+        team_size: Option<(i32, i32)>,
+    ) -> Event {
+        let mut store = self.store.lock().unwrap();
+        store.event_last_id += 1;
+        let event = Event {
+            id: store.event_last_id,
+            name: name.to_owned(),
+            max_participants,
+            team_size,
+            enrolled: 0,
+        };
+        store.events.insert(event.id, event.clone());
+        event
+    }
+
+    fn category_list(&self) -> Vec<Category> {
+        self.store.lock().unwrap().categories.clone()
+    }
+
+    fn category_add(
+        &self,
+        name: &str,
+        prefix: &str,
+        fee: i64,
+        hospitality: bool,
+        events: bool,
+    ) -> Result<Category, CategoryError> {
+        let mut store = self.store.lock().unwrap();
+        if store.categories.iter().any(|c| c.prefix == prefix) {
+            return Err(CategoryError::PrefixTaken);
+        }
+        let category = Category {
+            id: store.categories.len() as i32,
+            name: name.to_owned(),
+            prefix: prefix.to_owned(),
+            fee,
+            hospitality,
+            events,
+            active: true,
+        };
+        store.categories.push(category.clone());
+        Ok(category)
+    }
+
+    fn category_set_fee(&self, prefix: &str, fee: i64) -> Result<(), CategoryError> {
+        let mut store = self.store.lock().unwrap();
+        let category = store.category_mut(prefix)?;
+        category.fee = fee;
+        Ok(())
+    }
+
+    fn category_set_active(&self, prefix: &str, active: bool) -> Result<(), CategoryError> {
+        let mut store = self.store.lock().unwrap();
+        let category = store.category_mut(prefix)?;
+        category.active = active;
+        Ok(())
+    }
+
+    fn hostel_set_tariff(&self, hostel: &str, tariff: i64) {
+        self.store
+            .lock()
+            .unwrap()
+            .tariffs
+            .insert(hostel.to_owned(), tariff);
+    }
+
+    fn college_list(&self) -> Vec<College> {
+        let mut colleges: Vec<College> =
+            self.store.lock().unwrap().colleges.values().cloned().collect();
+        colleges.sort_by_key(College::id);
+        colleges
+    }
 
-        self.participant_verify_reg(p2.reg_status.err().unwrap());
+    fn college_set_details(
+        &self,
+        college_id: i32,
+        short_code: &str,
+        district: &str,
+        city: &str,
+    ) -> Result<(), CollegeError> {
+        let mut store = self.store.lock().unwrap();
+        let college = store
+            .colleges
+            .get_mut(&college_id)
+            .ok_or(CollegeError::UnknownCollege)?;
+        college.short_code = short_code.trim().to_owned();
+        college.district = district.trim().to_owned();
+        college.city = city.trim().to_owned();
+        store.refresh_college(college_id);
+        Ok(())
     }
+
+    fn college_add_alias(&self, college_id: i32, alias: &str) -> Result<(), CollegeError> {
+        let mut store = self.store.lock().unwrap();
+        let college = store
+            .colleges
+            .get_mut(&college_id)
+            .ok_or(CollegeError::UnknownCollege)?;
+        let alias = alias.trim().to_owned();
+        if !college.aliases.contains(&alias) {
+            college.aliases.push(alias);
+        }
+        store.refresh_college(college_id);
+        Ok(())
+    }
+}
+
+struct RegDesk {
+    store: Arc<Mutex<Store>>,
+    session: Session,
+    last_action: Option<UndoRecord>,
 }
 
 impl IRegDesk for RegDesk {
-    fn participant_new(&mut self, info: ParticipantInfo, college: College) -> Participant {
+    fn session(&self) -> &Session {
+        &self.session
+    }
+
+    fn category_list(&self) -> Vec<Category> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        self.store.lock().unwrap().categories.clone()
+    }
+
+    fn logout(&mut self) {
         thread::sleep(time::Duration::from_millis(DELAY));
-        self.participant_last_id += 1;
+        if let Some(session) = self.store.lock().unwrap().sessions.get_mut(&self.session.id) {
+            session.1 = true;
+        }
+    }
+
+    fn participant_new(
+        &mut self,
+        mut info: ParticipantInfo,
+        college: College,
+    ) -> Result<Participant, Vec<InvalidField>> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let mut invalid = check_participant(&info, store.colleges.contains_key(&college.id))
+            .err()
+            .unwrap_or_default();
+        if !store.category(info.category.id).is_some_and(|c| c.active) {
+            invalid.push(InvalidField::InactiveCategory);
+        }
+        if !invalid.is_empty() {
+            return Err(invalid);
+        }
+        info.phone = normalise_phone(&info.phone).unwrap_or(info.phone);
+        store.participant_last_id += 1;
+        let id = store.participant_last_id;
         let participant = Participant {
-            id: self.participant_last_id,
+            id,
+            version: 0,
             info,
             college,
-            reg_status: Err(ParticipantRegNotVerified {
-                id: self.participant_last_id,
-            }),
+            reg_status: Err(ParticipantRegNotVerified { id }),
             hospitality: None,
-            category: ParticipantCategory::Ragam
         };
 
-        self.participants
-            .insert(self.participant_last_id, participant.clone());
-        participant
+        store.participants.insert(id, participant.clone());
+        store.created_desk.insert(id, self.session.desk.clone());
+        store.created_at.insert(id, Local::now());
+        self.last_action = None;
+        Ok(participant)
     }
 
     fn participant_get(&self, id: i32) -> Option<Participant> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        self.participants.get(&id).cloned()
+        self.store.lock().unwrap().participants.get(&id).cloned()
+    }
+
+    fn participant_find_by_phone(&self, phone: &str) -> Vec<Participant> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let phone = match normalise_phone(phone) {
+            Some(phone) => phone,
+            None => return Vec::new(),
+        };
+        let mut found: Vec<Participant> = self
+            .store
+            .lock()
+            .unwrap()
+            .participants
+            .values()
+            .filter(|p| p.info.phone == phone)
+            .cloned()
+            .collect();
+        found.sort_by_key(Participant::id);
+        found
+    }
+
+    fn participant_update(
+        &mut self,
+        participant: &Participant,
+    ) -> Result<Participant, UpdateError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let college_exists = store.colleges.contains_key(&participant.college.id);
+        check_participant(&participant.info, college_exists).map_err(UpdateError::Invalid)?;
+        let current = match store.participants.get_mut(&participant.id) {
+            Some(current) => current,
+            None => return Err(UpdateError::UnknownParticipant),
+        };
+        if current.version != participant.version {
+            return Err(UpdateError::Conflict(Box::new(UpdateConflict {
+                current: current.clone(),
+            })));
+        }
+        let before = current.clone();
+        current.info = participant.info.clone();
+        current.info.phone = normalise_phone(&participant.info.phone)
+            .unwrap_or_else(|| participant.info.phone.clone());
+        current.college = participant.college.clone();
+        current.version += 1;
+        let updated = current.clone();
+        self.last_action = Some(UndoRecord::new(UndoableAction::Update, before, updated.clone()));
+        Ok(updated)
+    }
+
+    fn participant_verify_reg(
+        &mut self,
+        p: ParticipantRegNotVerified,
+    ) -> Result<Participant, VerifyError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let before = store.participants[&p.id].clone();
+        // Another desk verified the participant first.
+        if before.reg_status.is_ok() {
+            self.last_action = None;
+            return Ok(before);
+        }
+        let due = store.amount_due(p.id);
+        if due > 0 {
+            return Err(VerifyError::AmountDue(due));
+        }
+        let verified = store.verify_reg(p.id, &self.session);
+        self.last_action = Some(UndoRecord::new(UndoableAction::Verify, before, verified.clone()));
+        Ok(verified)
+    }
+
+    fn participant_amount_due(&self, participant_id: i32) -> i64 {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        self.store.lock().unwrap().amount_due(participant_id)
+    }
+
+    fn participant_set_photo(
+        &mut self,
+        participant_id: i32,
+        photo: Photo,
+    ) -> Result<(), PhotoError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        if photo.image.len() > PHOTO_MAX_SIZE {
+            return Err(PhotoError::TooLarge);
+        }
+        let mut store = self.store.lock().unwrap();
+        if !store.participants.contains_key(&participant_id) {
+            return Err(PhotoError::UnknownParticipant);
+        }
+        store.photos.insert(participant_id, photo);
+        Ok(())
     }
 
-    fn participant_update(&mut self, participant: &Participant) {
+    fn participant_thumbnail(&self, participant_id: i32) -> Option<Vec<u8>> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        self.participants
-            .insert(participant.id, participant.clone());
+        self.store
+            .lock()
+            .unwrap()
+            .photos
+            .get(&participant_id)
+            .map(|photo| photo.thumbnail.clone())
     }
 
-    fn participant_verify_reg(&mut self, p: ParticipantRegNotVerified) -> Participant {
+    fn participant_payments(&self, participant_id: i32) -> Vec<Payment> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        let admin = self.logged_in_admin.clone();
-        if let Some(participant) = self.participants.get_mut(&p.id) {
-            participant.reg_status = Ok(ParticipantRegVerified { admin });
+        self.store
+            .lock()
+            .unwrap()
+            .payments
+            .iter()
+            .filter(|p| p.participant_id == participant_id)
+            .cloned()
+            .collect()
+    }
+
+    fn payment_add(
+        &mut self,
+        participant_id: i32,
+        info: PaymentInfo,
+    ) -> Result<Payment, PaymentError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        if !store.participants.contains_key(&participant_id) {
+            return Err(PaymentError::UnknownParticipant);
         }
+        info.check(store.amount_due(participant_id))?;
+        Ok(store.add_payment(
+            &self.session,
+            participant_id,
+            PaymentPurpose::Registration,
+            info,
+        ))
+    }
 
-        self.participant_get(p.id).unwrap()
+    fn last_action(&self) -> Option<LastAction> {
+        self.last_action.as_ref().and_then(UndoRecord::last_action)
+    }
+
+    fn undo_last(&mut self) -> Result<Participant, UndoError> {
+        let record = match self.last_action.take() {
+            Some(record) if record.last_action().is_some() => record,
+            _ => return Err(UndoError::NothingToUndo),
+        };
+        let id = record.after.id;
+        let current = self.participant_get(id).ok_or(UndoError::NothingToUndo)?;
+        if !record.unchanged(&current) {
+            return Err(UndoError::Changed(Box::from(current)));
+        }
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        match record.action {
+            UndoableAction::Update => {
+                // The details as they were, under a new version as for any
+                // other save.
+                let participant = store.participants.get_mut(&id).unwrap();
+                participant.info = record.before.info;
+                participant.college = record.before.college;
+                participant.version += 1;
+            }
+            UndoableAction::Verify => {
+                store.verified_at.remove(&id);
+                store.verified_desk.remove(&id);
+                store.verified_session.remove(&id);
+                store.participants.get_mut(&id).unwrap().reg_status = record.before.reg_status;
+            }
+            _ => {
+                // A first allocation is removed; a changed one is put back.
+                if record.before.hospitality.is_none() {
+                    store.hospitality_at.remove(&id);
+                    store.hospitality_desk.remove(&id);
+                    store.hospitality_session.remove(&id);
+                }
+                if let Some((desk, session_id)) = record.allocated_by {
+                    store.hospitality_desk.insert(id, desk);
+                    if let Some(session_id) = session_id {
+                        store.hospitality_session.insert(id, session_id);
+                    }
+                }
+                store.participants.get_mut(&id).unwrap().hospitality = record.before.hospitality;
+            }
+        }
+        Ok(store.participants[&id].clone())
+    }
+
+    fn desk_recent_activity(&self, limit: usize) -> Vec<DeskActivity> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let store = self.store.lock().unwrap();
+        let desk = &self.session.desk;
+        let actions = [
+            (DeskAction::Created, &store.created_desk, &store.created_at),
+            (DeskAction::Verified, &store.verified_desk, &store.verified_at),
+            (DeskAction::Allocated, &store.hospitality_desk, &store.hospitality_at),
+        ];
+        // The latest action on each participant.
+        let mut latest: HashMap<i32, (DateTime<Local>, DeskAction)> = HashMap::new();
+        for (action, desks, times) in actions.iter() {
+            for (id, at) in times.iter() {
+                if desks.get(id) != Some(desk) {
+                    continue;
+                }
+                let entry = latest.entry(*id).or_insert((*at, *action));
+                if *at >= entry.0 {
+                    *entry = (*at, *action);
+                }
+            }
+        }
+        let mut latest: Vec<(i32, DateTime<Local>, DeskAction)> = latest
+            .into_iter()
+            .map(|(id, (at, action))| (id, at, action))
+            .collect();
+        latest.sort_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+        latest
+            .into_iter()
+            .filter_map(|(id, at, action)| {
+                Some(DeskActivity {
+                    participant: store.participants.get(&id)?.clone(),
+                    action,
+                    at: at.format("%Y-%m-%d %H:%M").to_string(),
+                })
+            })
+            .take(limit)
+            .collect()
+    }
+
+    fn shift_summary(&self) -> ShiftSummary {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let store = self.store.lock().unwrap();
+        let session = &self.session;
+        // The dummy data is entered outside any session.
+        let started_at = store
+            .session_started
+            .get(&session.id)
+            .cloned()
+            .unwrap_or_else(Local::now);
+        let in_session =
+            |sessions: &HashMap<i32, i32>, id: i32| sessions.get(&id) == Some(&session.id);
+        let payments = store
+            .payments
+            .iter()
+            .filter(|p| in_session(&store.payment_session, p.id))
+            .cloned()
+            .collect();
+        let verifications = store
+            .participants
+            .keys()
+            .filter(|&&id| in_session(&store.verified_session, id))
+            .count() as i64;
+        let hospitality_allocations = store
+            .participants
+            .keys()
+            .filter(|&&id| in_session(&store.hospitality_session, id))
+            .count() as i64;
+        ShiftSummary {
+            session: session.clone(),
+            started_at: started_at.format("%Y-%m-%d %H:%M").to_string(),
+            payments,
+            verifications,
+            hospitality_allocations,
+            counted_cash: store.counted_cash.get(&session.id).cloned(),
+        }
+    }
+
+    fn shift_record_cash(&mut self, counted_cash: i64) -> ShiftSummary {
+        self.store
+            .lock()
+            .unwrap()
+            .counted_cash
+            .insert(self.session.id, counted_cash);
+        self.shift_summary()
     }
 
     fn participant_update_hospi(
@@ -151,39 +812,823 @@ impl IRegDesk for RegDesk {
         p: Participant,
         hostel: &str,
         room: &str,
-    ) -> Participant {
+        arrival: NaiveDate,
+        departure: NaiveDate,
+    ) -> Result<Participant, HospitalityError> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        let admin = self.logged_in_admin.clone();
-        if let Some(participant) = self.participants.get_mut(&p.id) {
-            participant.hospitality = Some(HospitalityVerified {
-                admin,
-                hostel: hostel.to_owned(),
-                room: room.to_owned(),
+        let admin = self.session.admin.clone();
+        let mut store = self.store.lock().unwrap();
+        let category = store.category(store.participants[&p.id].info.category.id);
+        if !category.is_some_and(|c| c.hospitality) {
+            return Err(HospitalityError::NoHospitality);
+        }
+        let allocated_by = store.hospitality_desk.get(&p.id).cloned().map(|desk| {
+            (desk, store.hospitality_session.get(&p.id).cloned())
+        });
+        store
+            .hospitality_desk
+            .insert(p.id, self.session.desk.clone());
+        store.hospitality_session.insert(p.id, self.session.id);
+        store.hospitality_at.entry(p.id).or_insert_with(Local::now);
+        let tariff = store.tariffs.get(hostel).cloned().unwrap_or(0);
+        let participant = store.participants.get_mut(&p.id).unwrap();
+        let before = participant.clone();
+        // Check-in, checkout and payments carry over to the new allocation.
+        let (checked_in, checked_out, paid) = match participant.hospitality {
+            Some(ref h) => (h.checked_in, h.checked_out, h.paid),
+            None => (None, None, 0),
+        };
+        participant.hospitality = Some(HospitalityVerified {
+            admin,
+            hostel: hostel.to_owned(),
+            room: room.to_owned(),
+            arrival,
+            departure,
+            checked_in,
+            checked_out,
+            tariff,
+            paid,
+        });
+        let allocated = participant.clone();
+        let mut record = UndoRecord::new(UndoableAction::Allocate, before, allocated.clone());
+        if record.before.hospitality.is_some() {
+            record.allocated_by = allocated_by;
+        }
+        self.last_action = Some(record);
+        Ok(allocated)
+    }
+
+    fn hospitality_check_in(
+        &mut self,
+        participant_id: i32,
+    ) -> Result<Participant, HospitalityError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let participant = store
+            .participants
+            .get_mut(&participant_id)
+            .ok_or(HospitalityError::NotAllocated)?;
+        let stay = participant
+            .hospitality
+            .as_mut()
+            .ok_or(HospitalityError::NotAllocated)?;
+        if stay.checked_in.is_some() {
+            return Err(HospitalityError::AlreadyCheckedIn);
+        }
+        stay.checked_in = Some(Local::today().naive_local());
+        Ok(participant.clone())
+    }
+
+    fn hospitality_check_out(
+        &mut self,
+        participant_id: i32,
+    ) -> Result<Participant, HospitalityError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let participant = store
+            .participants
+            .get_mut(&participant_id)
+            .ok_or(HospitalityError::NotAllocated)?;
+        let stay = participant
+            .hospitality
+            .as_mut()
+            .ok_or(HospitalityError::NotAllocated)?;
+        if stay.checked_in.is_none() {
+            return Err(HospitalityError::NotCheckedIn);
+        }
+        if stay.checked_out.is_some() {
+            return Err(HospitalityError::AlreadyCheckedOut);
+        }
+        stay.checked_out = Some(Local::today().naive_local());
+        Ok(participant.clone())
+    }
+
+    fn hospitality_collect(
+        &mut self,
+        participant_id: i32,
+        info: PaymentInfo,
+    ) -> Result<Payment, PaymentError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        let due = store
+            .participants
+            .get(&participant_id)
+            .and_then(|p| p.hospitality.as_ref())
+            .map(HospitalityVerified::amount_due)
+            .ok_or(PaymentError::UnknownParticipant)?;
+        info.check(due)?;
+        let payment = store.add_payment(
+            &self.session,
+            participant_id,
+            PaymentPurpose::Hospitality,
+            info,
+        );
+        let stay = store
+            .participants
+            .get_mut(&participant_id)
+            .and_then(|p| p.hospitality.as_mut())
+            .unwrap();
+        stay.paid += payment.info.amount;
+        Ok(payment)
+    }
+
+    fn college_get_filtered(&self, key: &str) -> Vec<College> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut colleges: Vec<College> = self
+            .store
+            .lock()
+            .unwrap()
+            .colleges
+            .values()
+            .filter(|c| c.matches(key))
+            .cloned()
+            .collect();
+        colleges.sort_by_key(College::id);
+        colleges
+    }
+
+    fn college_add(&mut self, college: NewCollege) -> College {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        store.college_last_id += 1;
+        let college = College {
+            id: store.college_last_id,
+            name: college.name.trim().to_owned(),
+            city: college.city.trim().to_owned(),
+            district: college.district.trim().to_owned(),
+            short_code: college.short_code.trim().to_owned(),
+            aliases: Vec::new(),
+        };
+        store.colleges.insert(college.id, college.clone());
+        college
+    }
+
+    fn college_duplicates(&self) -> Vec<Vec<CollegeUsage>> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let store = self.store.lock().unwrap();
+        let usage = store
+            .colleges
+            .values()
+            .map(|college| CollegeUsage {
+                college: college.clone(),
+                participants: store
+                    .participants
+                    .values()
+                    .filter(|p| p.college.id == college.id)
+                    .count() as i64,
+            })
+            .collect();
+        group_duplicates(usage)
+    }
+
+    fn college_merge(
+        &mut self,
+        into: i32,
+        merged: &[i32],
+    ) -> Result<Vec<CollegeMerge>, MergeError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        if merged.contains(&into) {
+            return Err(MergeError::MergeIntoSelf);
+        }
+        let supervisor = store
+            .admins
+            .values()
+            .any(|a| a.id == self.session.admin.id && a.supervisor);
+        if !supervisor {
+            return Err(MergeError::NotSupervisor);
+        }
+        if !store.colleges.contains_key(&into)
+            || !merged.iter().all(|id| store.colleges.contains_key(id))
+        {
+            return Err(MergeError::UnknownCollege);
+        }
+        for id in merged {
+            if let Some(college) = store.colleges.get(id).cloned() {
+                let kept = store.colleges.get_mut(&into).unwrap();
+                for alias in Some(college.name).into_iter().chain(college.aliases) {
+                    if alias != kept.name && !kept.aliases.contains(&alias) {
+                        kept.aliases.push(alias);
+                    }
+                }
+            }
+        }
+        let into = store.colleges[&into].clone();
+
+        let merged_at = Local::now().format("%Y-%m-%d %H:%M").to_string();
+        let mut merges = Vec::new();
+        for id in merged {
+            let college = match store.colleges.remove(id) {
+                Some(college) => college,
+                // Listed twice.
+                None => continue,
+            };
+            let mut participants = 0;
+            for participant in store.participants.values_mut() {
+                if participant.college.id == college.id {
+                    participant.college = into.clone();
+                    participant.version += 1;
+                    participants += 1;
+                }
+            }
+            for team in store.teams.values_mut() {
+                if team.college.id == college.id {
+                    team.college = into.clone();
+                }
+            }
+            merges.push(CollegeMerge {
+                merged: college.name,
+                into: into.clone(),
+                participants,
+                admin: self.session.admin.clone(),
+                desk: self.session.desk.clone(),
+                merged_at: merged_at.clone(),
             });
         }
+        store.refresh_college(into.id);
+        store.college_merges.extend(merges.iter().cloned());
+        Ok(merges)
+    }
+
+    fn college_merge_history(&self) -> Vec<CollegeMerge> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let store = self.store.lock().unwrap();
+        store.college_merges.iter().rev().cloned().collect()
+    }
+
+    fn statistics(&self) -> Statistics {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let store = self.store.lock().unwrap();
+        let verified: Vec<&Participant> = store
+            .participants
+            .values()
+            .filter(|p| p.reg_status.is_ok())
+            .collect();
+
+        let mut by_category = HashMap::new();
+        let mut by_gender = HashMap::new();
+        let mut by_college = HashMap::new();
+        let mut by_admin = HashMap::new();
+        let mut by_desk = HashMap::new();
+        let mut by_hour = HashMap::new();
+        for p in verified.iter() {
+            *by_category.entry(p.info.category.name.clone()).or_insert(0) += 1;
+            *by_gender.entry(p.info.gender).or_insert(0) += 1;
+            *by_college.entry(p.college.name.clone()).or_insert(0) += 1;
+            if let Ok(ref verified) = p.reg_status {
+                *by_admin.entry(verified.admin.name.clone()).or_insert(0) += 1;
+            }
+            if let Some(desk) = store.verified_desk.get(&p.id) {
+                *by_desk.entry(desk.clone()).or_insert(0) += 1;
+            }
+            if let Some(at) = store.verified_at.get(&p.id) {
+                *by_hour.entry(at.hour()).or_insert(0) += 1;
+            }
+        }
+
+        let mut hostels: HashMap<String, (i64, Vec<String>)> = HashMap::new();
+        for hospitality in store.participants.values().filter_map(|p| p.hospitality.as_ref()) {
+            let hostel = hostels
+                .entry(hospitality.hostel.clone())
+                .or_insert((0, Vec::new()));
+            hostel.0 += 1;
+            if !hostel.1.contains(&hospitality.room) {
+                hostel.1.push(hospitality.room.clone());
+            }
+        }
+        let mut hospitality: Vec<HostelOccupancy> = hostels
+            .into_iter()
+            .map(|(hostel, (participants, rooms))| HostelOccupancy {
+                hostel,
+                participants,
+                rooms: rooms.len() as i64,
+            })
+            .collect();
+        hospitality.sort_by(|a, b| a.hostel.cmp(&b.hostel));
 
-        self.participant_get(p.id).unwrap()
+        Statistics {
+            total: verified.len() as i64,
+            by_category: sorted_by_count(by_category),
+            by_gender: sorted_by_key(by_gender),
+            by_college: sorted_by_count(by_college),
+            by_admin: sorted_by_count(by_admin),
+            by_desk: sorted_by_count(by_desk),
+            by_hour: sorted_by_key(by_hour),
+            hospitality,
+        }
     }
 
-    fn college_get_filtered(&self, name: &str) -> Vec<College> {
+    fn event_list(&self) -> Vec<Event> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        self.colleges
-            .iter()
-            .filter(|(_, c)| c.name.starts_with(name))
-            .map(|(_, c)| c.clone())
-            .collect()
+        let store = self.store.lock().unwrap();
+        store.events_with_counts(|_| true)
     }
 
-    fn college_add(&mut self, name: String) -> College {
+    fn participant_events(&self, participant_id: i32) -> Vec<Event> {
         thread::sleep(time::Duration::from_millis(DELAY));
-        self.college_last_id += 1;
-        self.colleges.insert(
-            self.college_last_id,
-            College {
-                id: self.college_last_id,
-                name,
+        let store = self.store.lock().unwrap();
+        store.events_with_counts(|event| store.enrolments.contains(&(participant_id, event.id)))
+    }
+
+    fn participant_enrol(&mut self, participant_id: i32, event_id: i32) -> Result<(), EnrolError> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        let mut store = self.store.lock().unwrap();
+        if store.enrolments.contains(&(participant_id, event_id)) {
+            return Ok(());
+        }
+        match store.participants.get(&participant_id) {
+            Some(p) if p.info.category.events => {}
+            _ => return Err(EnrolError::NoEvents),
+        }
+        let event = store
+            .events_with_counts(|event| event.id == event_id)
+            .pop()
+            .ok_or(EnrolError::UnknownEvent)?;
+        if event.is_full() {
+            return Err(EnrolError::EventFull);
+        }
+        store.enrolments.insert((participant_id, event_id));
+        Ok(())
+    }
+
+    fn participant_withdraw(&mut self, participant_id: i32, event_id: i32) {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        self.store
+            .lock()
+            .unwrap()
+            .enrolments
+            .remove(&(participant_id, event_id));
+    }
+
+    fn team_create(
+        &mut self,
+        name: &str,
+        event_id: i32,
+        leader: i32,
+        members: &[i32],
+    ) -> Result<Team, TeamError> {
+        let event = self
+            .event_list()
+            .into_iter()
+            .find(|e| e.id == event_id)
+            .ok_or(TeamError::UnknownEvent)?;
+        let mut all = vec![leader];
+        for &member in members {
+            if !all.contains(&member) {
+                all.push(member);
+            }
+        }
+        check_team_size(&event, all.len())?;
+
+        let college = self
+            .participant_get(leader)
+            .ok_or(TeamError::UnknownParticipant(leader))?
+            .college;
+        {
+            let store = self.store.lock().unwrap();
+            for &member in all.iter() {
+                match store.participants.get(&member) {
+                    None => return Err(TeamError::UnknownParticipant(member)),
+                    // Checked before anyone is enrolled.
+                    Some(p) if !p.info.category.events => return Err(TeamError::NoEvents(member)),
+                    Some(_) => {}
+                }
+                if store.team_of(member, event_id).is_some() {
+                    return Err(TeamError::AlreadyInTeam(member));
+                }
+            }
+            if let Some(max) = event.max_participants {
+                let new = all
+                    .iter()
+                    .filter(|&&p| !store.enrolments.contains(&(p, event_id)))
+                    .count();
+                if event.enrolled + new as i64 > i64::from(max) {
+                    return Err(TeamError::EventFull);
+                }
+            }
+        }
+
+        for &member in all.iter() {
+            self.participant_enrol(member, event_id)
+                .map_err(|err| team_error(err, member))?;
+        }
+        let mut store = self.store.lock().unwrap();
+        store.team_last_id += 1;
+        let team = Team {
+            id: store.team_last_id,
+            name: name.to_owned(),
+            event,
+            college,
+            leader,
+            members: all,
+        };
+        store.teams.insert(team.id, team);
+        Ok(store.team(store.team_last_id).unwrap())
+    }
+
+    fn team_get(&self, id: i32) -> Option<Team> {
+        thread::sleep(time::Duration::from_millis(DELAY));
+        self.store.lock().unwrap().team(id)
+    }
+
+    fn team_add_member(&mut self, team_id: i32, participant_id: i32) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        if team.members.contains(&participant_id) {
+            return Ok(team);
+        }
+        check_team_size(&team.event, team.members.len() + 1)?;
+        {
+            let store = self.store.lock().unwrap();
+            if !store.participants.contains_key(&participant_id) {
+                return Err(TeamError::UnknownParticipant(participant_id));
+            }
+            if store.team_of(participant_id, team.event.id).is_some() {
+                return Err(TeamError::AlreadyInTeam(participant_id));
+            }
+        }
+        self.participant_enrol(participant_id, team.event.id)
+            .map_err(|err| team_error(err, participant_id))?;
+        let mut store = self.store.lock().unwrap();
+        store
+            .teams
+            .get_mut(&team_id)
+            .unwrap()
+            .members
+            .push(participant_id);
+        Ok(store.team(team_id).unwrap())
+    }
+
+    fn team_remove_member(
+        &mut self,
+        team_id: i32,
+        participant_id: i32,
+    ) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        if team.leader == participant_id {
+            return Err(TeamError::RemoveLeader);
+        }
+        if !team.members.contains(&participant_id) {
+            return Ok(team);
+        }
+        check_team_size(&team.event, team.members.len() - 1)?;
+        self.participant_withdraw(participant_id, team.event.id);
+        let mut store = self.store.lock().unwrap();
+        store
+            .teams
+            .get_mut(&team_id)
+            .unwrap()
+            .members
+            .retain(|&m| m != participant_id);
+        Ok(store.team(team_id).unwrap())
+    }
+
+    fn team_verify(&mut self, team_id: i32) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        let mut store = self.store.lock().unwrap();
+        let not_verified: Vec<i32> = team
+            .members
+            .iter()
+            .cloned()
+            .filter(|member| match store.participants.get(member) {
+                Some(participant) => participant.reg_status.is_err(),
+                None => false,
+            })
+            .collect();
+        if let Some(&member) = not_verified.iter().find(|&&m| store.amount_due(m) > 0) {
+            return Err(TeamError::AmountDue(member));
+        }
+        for member in not_verified {
+            store.verify_reg(member, &self.session);
+        }
+        self.last_action = None;
+        Ok(team)
+    }
+}
+
+fn team_error(err: EnrolError, participant_id: i32) -> TeamError {
+    match err {
+        EnrolError::EventFull => TeamError::EventFull,
+        EnrolError::UnknownEvent => TeamError::UnknownEvent,
+        EnrolError::NoEvents => TeamError::NoEvents(participant_id),
+    }
+}
+
+impl Store {
+    /// Marks the registration as verified at the desk of `session`.
+    /// Returns the verified participant.
+    fn verify_reg(&mut self, participant_id: i32, session: &Session) -> Participant {
+        self.verified_at.insert(participant_id, Local::now());
+        self.verified_desk.insert(participant_id, session.desk.clone());
+        self.verified_session.insert(participant_id, session.id);
+        let participant = self.participants.get_mut(&participant_id).unwrap();
+        participant.reg_status = Ok(ParticipantRegVerified {
+            admin: session.admin.clone(),
+        });
+        participant.clone()
+    }
+
+    /// Participants and teams hold a copy of their college.
+    fn refresh_college(&mut self, college_id: i32) {
+        let college = match self.colleges.get(&college_id) {
+            Some(college) => college.clone(),
+            None => return,
+        };
+        for participant in self.participants.values_mut() {
+            if participant.college.id == college_id {
+                participant.college = college.clone();
+            }
+        }
+        for team in self.teams.values_mut() {
+            if team.college.id == college_id {
+                team.college = college.clone();
+            }
+        }
+    }
+
+    fn category(&self, category_id: i32) -> Option<&Category> {
+        self.categories.iter().find(|c| c.id == category_id)
+    }
+
+    fn category_mut(&mut self, prefix: &str) -> Result<&mut Category, CategoryError> {
+        self.categories
+            .iter_mut()
+            .find(|c| c.prefix == prefix)
+            .ok_or(CategoryError::UnknownCategory)
+    }
+
+    fn amount_due(&self, participant_id: i32) -> i64 {
+        let participant = match self.participants.get(&participant_id) {
+            Some(participant) => participant,
+            None => return 0,
+        };
+        let fee = self
+            .categories
+            .iter()
+            .find(|c| c.id == participant.info.category.id)
+            .map_or(0, |c| c.fee);
+        let paid: i64 = self
+            .payments
+            .iter()
+            .filter(|p| p.participant_id == participant_id)
+            .filter(|p| p.purpose == PaymentPurpose::Registration)
+            .map(|p| p.info.amount)
+            .sum();
+        fee - paid
+    }
+
+    fn add_payment(
+        &mut self,
+        session: &Session,
+        participant_id: i32,
+        purpose: PaymentPurpose,
+        info: PaymentInfo,
+    ) -> Payment {
+        self.payment_last_id += 1;
+        let id = self.payment_last_id;
+        let payment = Payment {
+            id,
+            participant_id,
+            purpose,
+            info: PaymentInfo {
+                reference: info.reference.trim().to_owned(),
+                ..info
             },
-        );
-        self.colleges[&self.college_last_id].clone()
+            receipt_no: receipt_no(&session.desk, id),
+            admin: session.admin.clone(),
+            desk: session.desk.clone(),
+        };
+        self.payments.push(payment.clone());
+        self.payment_session.insert(id, session.id);
+        payment
+    }
+
+    /// The team with its event's enrolment count brought up to date.
+    fn team(&self, id: i32) -> Option<Team> {
+        let team = self.teams.get(&id)?;
+        let event = self
+            .events_with_counts(|e| e.id == team.event.id)
+            .pop()?;
+        Some(Team {
+            event,
+            ..team.clone()
+        })
+    }
+
+    fn team_of(&self, participant_id: i32, event_id: i32) -> Option<i32> {
+        self.teams
+            .values()
+            .find(|t| t.event.id == event_id && t.members.contains(&participant_id))
+            .map(|t| t.id)
+    }
+
+    fn events_with_counts<F: Fn(&Event) -> bool>(&self, filter: F) -> Vec<Event> {
+        let mut events: Vec<Event> = self
+            .events
+            .values()
+            .filter(|event| filter(event))
+            .map(|event| Event {
+                enrolled: self
+                    .enrolments
+                    .iter()
+                    .filter(|(_, e)| *e == event.id)
+                    .count() as i64,
+                ..event.clone()
+            })
+            .collect();
+        events.sort_by(|a, b| a.name.cmp(&b.name));
+        events
+    }
+}
+
+fn sorted_by_key<K: Ord>(counts: HashMap<K, i64>) -> Vec<(K, i64)> {
+    let mut counts: Vec<(K, i64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| a.0.cmp(&b.0));
+    counts
+}
+
+fn sorted_by_count(counts: HashMap<String, i64>) -> Vec<(String, i64)> {
+    let mut counts: Vec<(String, i64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login_with_admin() -> Login {
+        let login = Login::new();
+        login.admin_add("Anjali", "anjali", "secret").ok().unwrap();
+        login
+    }
+
+    fn desk(login: &Login, desk: &str) -> Box<dyn IRegDesk> {
+        match login.login_reg_desk(desk, "anjali", "secret") {
+            Ok(reg_desk) => reg_desk,
+            Err(_) => panic!("login failed"),
+        }
+    }
+
+    /// A participant in a free category with no hospitality or events.
+    fn participant(reg_desk: &mut dyn IRegDesk, login: &Login) -> Participant {
+        let category = login.category_add("Ragam", "R", 0, false, false).ok().unwrap();
+        let college = reg_desk.college_add(NewCollege {
+            name: String::from("GEC Thrissur"),
+            city: String::from("Thrissur"),
+            district: String::from("Thrissur"),
+            short_code: String::from("GECT"),
+        });
+        let info = ParticipantInfo {
+            name: String::from("Anjali Menon"),
+            gender: Gender::Female,
+            email: String::new(),
+            phone: String::from("9876543210"),
+            category,
+            kalotsavam: None,
+        };
+        reg_desk.participant_new(info, college).ok().unwrap()
+    }
+
+    fn session_id(login: &Login) -> i32 {
+        match login.login_reg_desk("D1", "anjali", "secret") {
+            Ok(reg_desk) => reg_desk.session().id,
+            Err(_) => panic!("login failed"),
+        }
+    }
+
+    #[test]
+    fn unlock() {
+        let login = login_with_admin();
+        let session_id = session_id(&login);
+        assert!(login.session_unlock(session_id, "secret").is_ok());
+        assert!(matches!(
+            login.session_unlock(session_id + 1, "secret"),
+            Err(LoginError::UnknownUser)
+        ));
+    }
+
+    #[test]
+    fn unlock_counts_towards_the_lock() {
+        let login = login_with_admin();
+        let session_id = session_id(&login);
+        for attempts_left in (1..LOGIN_MAX_FAILED_ATTEMPTS).rev() {
+            assert!(matches!(
+                login.session_unlock(session_id, "guess"),
+                Err(LoginError::WrongPassword { attempts_left: left }) if left == attempts_left
+            ));
+        }
+        assert!(matches!(
+            login.session_unlock(session_id, "guess"),
+            Err(LoginError::Locked(_))
+        ));
+        // Locked for the right password too, at the lock screen and at login.
+        assert!(matches!(
+            login.session_unlock(session_id, "secret"),
+            Err(LoginError::Locked(_))
+        ));
+        assert!(matches!(
+            login.login_reg_desk("D1", "anjali", "secret"),
+            Err(LoginError::Locked(_))
+        ));
+    }
+
+    #[test]
+    fn unlock_clears_failed_attempts() {
+        let login = login_with_admin();
+        let session_id = session_id(&login);
+        login.session_unlock(session_id, "guess").err().unwrap();
+        assert!(login.session_unlock(session_id, "secret").is_ok());
+        let full = LOGIN_MAX_FAILED_ATTEMPTS - 1;
+        assert!(matches!(
+            login.session_unlock(session_id, "guess"),
+            Err(LoginError::WrongPassword { attempts_left }) if attempts_left == full
+        ));
+    }
+
+    #[test]
+    fn unlock_refuses_disabled_admin() {
+        let login = login_with_admin();
+        let session_id = session_id(&login);
+        login.admin_set_disabled("anjali", true).ok().unwrap();
+        assert!(matches!(
+            login.session_unlock(session_id, "secret"),
+            Err(LoginError::Disabled)
+        ));
+    }
+
+    #[test]
+    fn verify_twice_keeps_the_first_verification() {
+        let login = login_with_admin();
+        let mut d1 = desk(&login, "D1");
+        let mut d2 = desk(&login, "D2");
+        let participant = participant(d1.as_mut(), &login);
+        let reg = participant.reg_status.err().unwrap();
+
+        assert!(d1.participant_verify_reg(reg).is_ok());
+        assert!(d1.last_action().is_some());
+        // The second desk still holds the unverified participant.
+        assert!(d2.participant_verify_reg(reg).is_ok());
+        assert!(d2.last_action().is_none());
+        let store = login.store.lock().unwrap();
+        assert_eq!(store.verified_desk[&participant.id], "D1");
+    }
+
+    #[test]
+    fn inactive_category_takes_no_new_participants() {
+        let login = login_with_admin();
+        let mut reg_desk = desk(&login, "D1");
+        let participant = participant(reg_desk.as_mut(), &login);
+        login.category_set_active("R", false).ok().unwrap();
+
+        let invalid = reg_desk
+            .participant_new(participant.info.clone(), participant.college.clone())
+            .err()
+            .unwrap();
+        assert_eq!(invalid, vec![InvalidField::InactiveCategory]);
+        // Existing participants are kept.
+        assert!(reg_desk.participant_get(participant.id).is_some());
+    }
+
+    #[test]
+    fn room_only_for_category_with_hospitality() {
+        let login = login_with_admin();
+        let mut reg_desk = desk(&login, "D1");
+        let participant = participant(reg_desk.as_mut(), &login);
+        let arrival = NaiveDate::from_ymd(2019, 3, 1);
+        let departure = NaiveDate::from_ymd(2019, 3, 3);
+
+        let refused =
+            reg_desk.participant_update_hospi(participant.clone(), "H1", "101", arrival, departure);
+        assert!(matches!(refused, Err(HospitalityError::NoHospitality)));
+        assert!(reg_desk.last_action().is_none());
+
+        let mut info = participant.info.clone();
+        info.category = login.category_add("Guests", "G", 0, true, false).ok().unwrap();
+        let guest = reg_desk.participant_new(info, participant.college).ok().unwrap();
+        let allocated = reg_desk
+            .participant_update_hospi(guest, "H1", "101", arrival, departure)
+            .ok()
+            .unwrap();
+        assert_eq!(allocated.hospitality.unwrap().room, "101");
+    }
+
+    #[test]
+    fn undone_update_gets_a_new_version() {
+        let login = login_with_admin();
+        let mut reg_desk = desk(&login, "D1");
+        let participant = participant(reg_desk.as_mut(), &login);
+        let mut edited = participant.clone();
+        edited.info.name = String::from("Anjali M");
+        let updated = reg_desk.participant_update(&edited).ok().unwrap();
+
+        let undone = reg_desk.undo_last().ok().unwrap();
+        assert_eq!(undone.info.name, "Anjali Menon");
+        assert_eq!(undone.version, updated.version + 1);
+        // A copy from before the update is as stale as one from before any
+        // other save.
+        assert!(matches!(
+            reg_desk.participant_update(&participant),
+            Err(UpdateError::Conflict(_))
+        ));
     }
 }
@@ -1,19 +1,51 @@
+use super::duplicates::group_duplicates;
+use super::undo::UndoRecord;
+use super::validation::{check_participant, normalise_phone};
 use super::*;
+use chrono::NaiveDate;
 use mysql::prelude::*;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// MySQL's error code for a duplicate value in a unique key.
+const ER_DUP_ENTRY: u16 = 1062;
 
 pub struct Login {
-    conn: Arc<Mutex<mysql::Conn>>,
+    conn: Arc<SharedConn>,
 }
 
 impl Login {
-    pub fn new(mut conn: mysql::Conn) -> Self {
+    /// `UPDATE` reports no affected rows when nothing changed, so a miss is
+    /// checked against the table.
+    fn category_found(&self, prefix: &str, affected_rows: u64) -> Result<(), CategoryError> {
+        if affected_rows > 0 || self.category_list().iter().any(|c| c.prefix == prefix) {
+            Ok(())
+        } else {
+            Err(CategoryError::UnknownCategory)
+        }
+    }
+
+    pub fn new(mut conn: mysql::Conn) -> Result<Self, mysql::Error> {
         let setup_sql = [
             r"CREATE TABLE IF NOT EXISTS `admin` (
                 `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
                 `name` varchar(255) NOT NULL,
-                `username` varchar(255) NOT NULL,
-                `password` varchar(255) NOT NULL
+                `username` varchar(255) NOT NULL UNIQUE,
+                `password` varchar(255) NOT NULL,
+                `disabled` TINYINT NOT NULL DEFAULT 0,
+                `supervisor` TINYINT NOT NULL DEFAULT 0,
+                `failed_attempts` INT NOT NULL DEFAULT 0,
+                `locked_until` TIMESTAMP NULL DEFAULT NULL
+            );",
+            r"CREATE TABLE IF NOT EXISTS `session` (
+                `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `started_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                `ended_at` TIMESTAMP NULL DEFAULT NULL,
+                `counted_cash` INT NULL DEFAULT NULL
             );",
             r"CREATE TABLE IF NOT EXISTS `participant` (
                 `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
@@ -23,102 +55,552 @@ impl Login {
                 `name` VARCHAR(255) NOT NULL,
                 `phone` VARCHAR(255) NOT NULL,
                 `gender` INT NOT NULL,
-                `category` INT NOT NULL
+                `category` INT NOT NULL,
+                `version` INT NOT NULL DEFAULT 0,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `created_at` TIMESTAMP NULL DEFAULT NULL
             );",
             r"CREATE TABLE IF NOT EXISTS `college` (
                 `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
-                `name` VARCHAR(255) NOT NULL
+                `name` VARCHAR(255) NOT NULL,
+                `city` VARCHAR(64) NOT NULL DEFAULT '',
+                `district` VARCHAR(64) NOT NULL DEFAULT '',
+                `short_code` VARCHAR(16) NOT NULL DEFAULT ''
+            );",
+            r"CREATE TABLE IF NOT EXISTS `college_alias` (
+                `college_id` INT NOT NULL,
+                `alias` VARCHAR(255) NOT NULL,
+                PRIMARY KEY (`college_id`, `alias`)
+            );",
+            r"CREATE TABLE IF NOT EXISTS `college_merge` (
+                `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                `merged_id` INT NOT NULL,
+                `merged_name` VARCHAR(255) NOT NULL,
+                `into_id` INT NOT NULL,
+                `into_name` VARCHAR(255) NOT NULL,
+                `participants` INT NOT NULL,
+                `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
             );",
             r"CREATE TABLE IF NOT EXISTS `offline_reg` (
                 `participant_id` int PRIMARY KEY NOT NULL,
-                `admin_id` int NOT NULL
+                `admin_id` int NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `session_id` INT NULL DEFAULT NULL,
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
             );",
             r"CREATE TABLE IF NOT EXISTS `hospitality_reg` (
                 `participant_id` INT PRIMARY KEY NOT NULL,
                 `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `session_id` INT NULL DEFAULT NULL,
                 `room` VARCHAR(255) NOT NULL,
-                `hostel` VARCHAR(255) NOT NULL
+                `hostel` VARCHAR(255) NOT NULL,
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                `arrival` DATE NULL DEFAULT NULL,
+                `departure` DATE NULL DEFAULT NULL,
+                `checked_in` DATE NULL DEFAULT NULL,
+                `checked_out` DATE NULL DEFAULT NULL,
+                `tariff` INT NOT NULL DEFAULT 0
+            );",
+            r"CREATE TABLE IF NOT EXISTS `hostel` (
+                `name` VARCHAR(255) PRIMARY KEY NOT NULL,
+                `tariff` INT NOT NULL
+            );",
+            r"CREATE TABLE IF NOT EXISTS `event` (
+                `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                `name` VARCHAR(255) NOT NULL,
+                `max_participants` INT NULL DEFAULT NULL,
+                `min_team_size` INT NULL DEFAULT NULL,
+                `max_team_size` INT NULL DEFAULT NULL
+            );",
+            r"CREATE TABLE IF NOT EXISTS `team` (
+                `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                `name` VARCHAR(255) NOT NULL,
+                `event_id` INT NOT NULL,
+                `college_id` INT NOT NULL,
+                `leader_id` INT NOT NULL,
+                `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+            );",
+            r"CREATE TABLE IF NOT EXISTS `team_member` (
+                `team_id` INT NOT NULL,
+                `participant_id` INT NOT NULL,
+                PRIMARY KEY (`team_id`, `participant_id`)
+            );",
+            r"CREATE TABLE IF NOT EXISTS `participant_event` (
+                `participant_id` INT NOT NULL,
+                `event_id` INT NOT NULL,
+                `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (`participant_id`, `event_id`)
+            );",
+            r"CREATE TABLE IF NOT EXISTS `kalotsavam_details` (
+                `participant_id` INT PRIMARY KEY NOT NULL,
+                `institution_type` INT NOT NULL,
+                `class` VARCHAR(32) NOT NULL,
+                `district` VARCHAR(64) NOT NULL,
+                `date_of_birth` DATE NOT NULL
+            );",
+            r"CREATE TABLE IF NOT EXISTS `participant_photo` (
+                `participant_id` INT PRIMARY KEY NOT NULL,
+                `image` MEDIUMBLOB NOT NULL,
+                `thumbnail` BLOB NOT NULL,
+                `updated_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+                    ON UPDATE CURRENT_TIMESTAMP
+            );",
+            r"CREATE TABLE IF NOT EXISTS `category` (
+                `id` INT PRIMARY KEY NOT NULL,
+                `name` VARCHAR(255) NOT NULL,
+                `prefix` VARCHAR(8) NOT NULL UNIQUE,
+                `fee` INT NOT NULL DEFAULT 0,
+                `hospitality` TINYINT NOT NULL DEFAULT 1,
+                `events` TINYINT NOT NULL DEFAULT 0,
+                `active` TINYINT NOT NULL DEFAULT 1
+            );",
+            // Participants from before categories were stored have 0 for
+            // Ragam and 1 for Kalotsavam.
+            r"INSERT IGNORE INTO `category`(id, name, prefix, events)
+              VALUES (0, 'Ragam', 'R', 0), (1, 'Kalotsavam', 'K', 1);",
+            r"CREATE TABLE IF NOT EXISTS `payment` (
+                `id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+                `participant_id` INT NOT NULL,
+                `purpose` INT NOT NULL DEFAULT 0,
+                `amount` INT NOT NULL,
+                `mode` INT NOT NULL,
+                `reference` VARCHAR(255) NOT NULL,
+                `receipt_no` VARCHAR(64) NOT NULL DEFAULT '',
+                `admin_id` INT NOT NULL,
+                `desk` VARCHAR(32) NOT NULL DEFAULT '',
+                `session_id` INT NULL DEFAULT NULL,
+                `created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
             );",
         ];
         for stmt in setup_sql.iter() {
-            conn.prep_exec(stmt, ()).unwrap();
+            conn.prep_exec(stmt, ())?;
+        }
+        fold_category_fees(&mut conn)?;
+        normalise_phones(&mut conn)?;
+        add_column_if_missing(&mut conn, "admin", "disabled", "TINYINT NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&mut conn, "admin", "supervisor", "TINYINT NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&mut conn, "admin", "failed_attempts", "INT NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&mut conn, "admin", "locked_until", "TIMESTAMP NULL DEFAULT NULL")?;
+        add_unique_key_if_missing(&mut conn, "admin", "username")?;
+        add_column_if_missing(&mut conn, "participant", "version", "INT NOT NULL DEFAULT 0")?;
+        // Participants created before this was recorded have no time.
+        add_column_if_missing(&mut conn, "participant", "created_at", "TIMESTAMP NULL DEFAULT NULL")?;
+        add_column_if_missing(&mut conn, "college", "city", "VARCHAR(64) NOT NULL DEFAULT ''")?;
+        add_column_if_missing(&mut conn, "college", "district", "VARCHAR(64) NOT NULL DEFAULT ''")?;
+        add_column_if_missing(&mut conn, "college", "short_code", "VARCHAR(16) NOT NULL DEFAULT ''")?;
+        add_column_if_missing(
+            &mut conn,
+            "offline_reg",
+            "created_at",
+            "TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP",
+        )?;
+        add_column_if_missing(&mut conn, "session", "counted_cash", "INT NULL DEFAULT NULL")?;
+        add_column_if_missing(
+            &mut conn,
+            "hospitality_reg",
+            "created_at",
+            "TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP",
+        )?;
+        for column in ["arrival", "departure", "checked_in", "checked_out"].iter() {
+            add_column_if_missing(&mut conn, "hospitality_reg", column, "DATE NULL DEFAULT NULL")?;
+        }
+        add_column_if_missing(&mut conn, "hospitality_reg", "tariff", "INT NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&mut conn, "payment", "purpose", "INT NOT NULL DEFAULT 0")?;
+        add_column_if_missing(&mut conn, "event", "min_team_size", "INT NULL DEFAULT NULL")?;
+        add_column_if_missing(&mut conn, "event", "max_team_size", "INT NULL DEFAULT NULL")?;
+        for table in ["session", "participant", "offline_reg", "hospitality_reg"].iter() {
+            add_column_if_missing(&mut conn, table, "desk", "VARCHAR(32) NOT NULL DEFAULT ''")?;
         }
-        Self {
-            conn: Arc::from(Mutex::from(conn)),
+        // Rows from before sessions were recorded on them belong to none.
+        for table in ["payment", "offline_reg", "hospitality_reg"].iter() {
+            add_column_if_missing(&mut conn, table, "session_id", "INT NULL DEFAULT NULL")?;
         }
+        Ok(Self {
+            conn: Arc::from(SharedConn {
+                conn: Mutex::from(conn),
+                last_call: Mutex::from(None),
+            }),
+        })
     }
 }
 
 impl ILogin for Login {
-    fn login_reg_desk(&self, username: &str, password: &str) -> Result<Box<dyn IRegDesk>, ()> {
-        let admins: Vec<Admin> = self
+    fn login_reg_desk(
+        &self,
+        desk: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Box<dyn IRegDesk>, LoginError> {
+        let mut lock = lock_conn(&self.conn);
+        let unreachable = |_| LoginError::Unreachable;
+        let row: Option<(i32, String, bool)> = lock
+            .first_exec(
+                r"SELECT id, name, supervisor FROM `admin` WHERE `username`=?",
+                (username,),
+            )
+            .map_err(unreachable)?;
+        let (id, name, supervisor) = row.ok_or(LoginError::UnknownUser)?;
+        check_password(&mut lock, id, password)?;
+        let session_id = lock
+            .prep_exec(
+                r"INSERT INTO `session`(admin_id, desk) VALUES(?,?)",
+                (id, desk),
+            )
+            .map_err(unreachable)?
+            .last_insert_id() as i32;
+        Ok(Box::from(RegDesk {
+            conn: self.conn.clone(),
+            session: Session {
+                id: session_id,
+                admin: Admin { id, name },
+                desk: desk.to_owned(),
+                supervisor,
+            },
+            last_action: None,
+        }))
+    }
+
+    fn last_call(&self) -> Option<LastCall> {
+        *self
             .conn
+            .last_call
             .lock()
-            .unwrap()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn ping(&self) -> bool {
+        let mut lock = lock_conn(&self.conn);
+        let alive = lock.ping();
+        if !alive {
+            lock.failed = true;
+        }
+        alive
+    }
+
+    fn reconnect(&self) -> bool {
+        // Falls back to opening a new connection with the original options
+        // when the old one is gone.
+        let mut lock = lock_conn(&self.conn);
+        let reconnected = lock.reset().is_ok();
+        if !reconnected {
+            lock.failed = true;
+        }
+        reconnected
+    }
+
+    fn session_unlock(&self, session_id: i32, password: &str) -> Result<(), LoginError> {
+        let mut lock = lock_conn(&self.conn);
+        let admin_id: Option<i32> = lock
+            .first_exec(
+                r"SELECT admin_id FROM `session` WHERE id=? AND ended_at IS NULL",
+                (session_id,),
+            )
+            .map_err(|_| LoginError::Unreachable)?;
+        let admin_id = admin_id.ok_or(LoginError::UnknownUser)?;
+        check_password(&mut lock, admin_id, password)
+    }
+}
+
+impl IAdmins for Login {
+    fn admin_add(&self, name: &str, username: &str, password: &str) -> Result<Admin, AdminError> {
+        // The unique key on `username` settles two desks adding the same
+        // admin at once.
+        let mut lock = lock_conn(&self.conn);
+        let res = lock.prep_exec(
+            r"INSERT INTO `admin`(name, username, password) VALUES(?,?,?)",
+            (name, username, password),
+        );
+        match res {
+            Ok(res) => Ok(Admin {
+                id: res.last_insert_id() as i32,
+                name: name.to_owned(),
+            }),
+            Err(mysql::Error::MySqlError(ref e)) if e.code == ER_DUP_ENTRY => {
+                Err(AdminError::UsernameTaken)
+            }
+            Err(e) => panic!("admin_add: {}", e),
+        }
+    }
+
+    fn admin_set_password(&self, username: &str, password: &str) -> Result<(), AdminError> {
+        let mut lock = lock_conn(&self.conn);
+        if !admin_exists(&mut lock, username) {
+            return Err(AdminError::UnknownUser);
+        }
+        lock.prep_exec(
+            r"UPDATE `admin` SET `password`=? WHERE `username`=?",
+            (password, username),
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn admin_set_disabled(&self, username: &str, disabled: bool) -> Result<(), AdminError> {
+        let mut lock = lock_conn(&self.conn);
+        if !admin_exists(&mut lock, username) {
+            return Err(AdminError::UnknownUser);
+        }
+        lock.prep_exec(
+            r"UPDATE `admin` SET `disabled`=?,
+                failed_attempts=IF(?, failed_attempts, 0),
+                locked_until=IF(?, locked_until, NULL)
+              WHERE `username`=?",
+            (disabled, disabled, disabled, username),
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn admin_set_supervisor(&self, username: &str, supervisor: bool) -> Result<(), AdminError> {
+        let mut lock = lock_conn(&self.conn);
+        if !admin_exists(&mut lock, username) {
+            return Err(AdminError::UnknownUser);
+        }
+        lock.prep_exec(
+            r"UPDATE `admin` SET `supervisor`=? WHERE `username`=?",
+            (supervisor, username),
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn admin_list(&self) -> Vec<AdminAccount> {
+        lock_conn(&self.conn)
             .prep_exec(
-                r"SELECT id,name from `admin` WHERE `username`=? and `password`=?",
-                (username, password),
+                r"SELECT id, name, username, disabled, supervisor FROM `admin` ORDER BY username",
+                (),
             )
             .map(|result| {
                 result
                     .map(|x| x.unwrap())
                     .map(|row| {
-                        let (id, name): (i32, String) = mysql::from_row(row);
-                        Admin { id, name }
+                        let (id, name, username, disabled, supervisor) = mysql::from_row(row);
+                        AdminAccount {
+                            admin: Admin { id, name },
+                            username,
+                            disabled,
+                            supervisor,
+                        }
                     })
                     .collect()
             })
+            .unwrap()
+    }
+
+    fn event_add(
+        &self,
+        name: &str,
+        max_participants: Option<i32>,
+        team_size: Option<(i32, i32)>,
+    ) -> Event {
+        let id = lock_conn(&self.conn)
+            .prep_exec(
+                r"INSERT INTO `event`(name, max_participants, min_team_size, max_team_size)
+                  VALUES(?,?,?,?)",
+                (
+                    name,
+                    max_participants,
+                    team_size.map(|(min, _)| min),
+                    team_size.map(|(_, max)| max),
+                ),
+            )
+            .unwrap()
+            .last_insert_id() as i32;
+        Event {
+            id,
+            name: name.to_owned(),
+            max_participants,
+            team_size,
+            enrolled: 0,
+        }
+    }
+
+    fn category_list(&self) -> Vec<Category> {
+        category_list(&mut lock_conn(&self.conn))
+    }
+
+    fn category_add(
+        &self,
+        name: &str,
+        prefix: &str,
+        fee: i64,
+        hospitality: bool,
+        events: bool,
+    ) -> Result<Category, CategoryError> {
+        let mut lock = lock_conn(&self.conn);
+        let taken: Option<i64> = lock
+            .first_exec(r"SELECT COUNT(*) FROM `category` WHERE prefix=?", (prefix,))
             .unwrap();
-        if admins.len() > 0 {
-            Ok(Box::from(RegDesk {
-                conn: self.conn.clone(),
-                admin: admins[0].clone(),
-            }))
-        } else {
-            Err(())
+        if taken.unwrap_or(0) > 0 {
+            return Err(CategoryError::PrefixTaken);
         }
+        lock.prep_exec(
+            r"INSERT INTO `category`(id, name, prefix, fee, hospitality, events)
+              SELECT COALESCE(MAX(id), -1) + 1, ?, ?, ?, ?, ? FROM `category`",
+            (name, prefix, fee, hospitality, events),
+        )
+        .unwrap();
+        Ok(category_list(&mut lock)
+            .into_iter()
+            .find(|c| c.prefix == prefix)
+            .unwrap())
     }
+
+    fn category_set_fee(&self, prefix: &str, fee: i64) -> Result<(), CategoryError> {
+        let affected_rows = lock_conn(&self.conn)
+            .prep_exec(r"UPDATE `category` SET fee=? WHERE prefix=?", (fee, prefix))
+            .unwrap()
+            .affected_rows();
+        self.category_found(prefix, affected_rows)
+    }
+
+    fn category_set_active(&self, prefix: &str, active: bool) -> Result<(), CategoryError> {
+        let affected_rows = lock_conn(&self.conn)
+            .prep_exec(
+                r"UPDATE `category` SET active=? WHERE prefix=?",
+                (active, prefix),
+            )
+            .unwrap()
+            .affected_rows();
+        self.category_found(prefix, affected_rows)
+    }
+
+    fn hostel_set_tariff(&self, hostel: &str, tariff: i64) {
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"INSERT INTO `hostel`(name, tariff) VALUES(?,?)
+                  ON DUPLICATE KEY UPDATE tariff=VALUES(tariff)",
+                (hostel, tariff),
+            )
+            .unwrap();
+    }
+
+    fn college_list(&self) -> Vec<College> {
+        college_list(&mut lock_conn(&self.conn))
+    }
+
+    fn college_set_details(
+        &self,
+        college_id: i32,
+        short_code: &str,
+        district: &str,
+        city: &str,
+    ) -> Result<(), CollegeError> {
+        let mut lock = lock_conn(&self.conn);
+        college_get(&mut lock, college_id).ok_or(CollegeError::UnknownCollege)?;
+        lock.prep_exec(
+            r"UPDATE `college` SET short_code=?, district=?, city=? WHERE id=?",
+            (short_code.trim(), district.trim(), city.trim(), college_id),
+        )
+        .unwrap();
+        Ok(())
+    }
+
+    fn college_add_alias(&self, college_id: i32, alias: &str) -> Result<(), CollegeError> {
+        let mut lock = lock_conn(&self.conn);
+        college_get(&mut lock, college_id).ok_or(CollegeError::UnknownCollege)?;
+        lock.prep_exec(
+            r"INSERT IGNORE INTO college_alias(college_id, alias) VALUES(?,?)",
+            (college_id, alias.trim()),
+        )
+        .unwrap();
+        Ok(())
+    }
+}
+
+fn admin_exists(conn: &mut mysql::Conn, username: &str) -> bool {
+    let existing: Option<i32> = conn
+        .first_exec(r"SELECT id FROM `admin` WHERE `username`=?", (username,))
+        .unwrap();
+    existing.is_some()
 }
 
 struct RegDesk {
-    conn: Arc<Mutex<mysql::Conn>>,
-    admin: Admin,
+    conn: Arc<SharedConn>,
+    session: Session,
+    last_action: Option<UndoRecord>,
 }
 
 impl IRegDesk for RegDesk {
-    fn participant_new_verified(&mut self, info: ParticipantInfo, college: College) -> Participant {
-        let last_insert_id = {
-        let info_ = info.clone();
-        let mut lock = self.conn.lock().unwrap();
-        let res = lock
+    fn session(&self) -> &Session {
+        &self.session
+    }
+
+    fn category_list(&self) -> Vec<Category> {
+        category_list(&mut lock_conn(&self.conn))
+    }
+
+    fn logout(&mut self) {
+        lock_conn(&self.conn)
             .prep_exec(
-                r"
-            INSERT INTO participant(college_id, email, password, name, phone, gender, category) VALUES(
-                ?,?,?,?,?,?,?
-            )",
-                (
-                    college.id(),
-                    info_.email,
-                    String::from("password"),
-                    info_.name,
-                    info_.phone,
-                    gender_to_i32(info_.gender),
-                    category_to_i32(info_.category),
-                ),
+                r"UPDATE `session` SET ended_at=CURRENT_TIMESTAMP WHERE id=?",
+                (self.session.id,),
             )
             .unwrap();
-            res.last_insert_id() as i32
+    }
+
+    fn participant_new(
+        &mut self,
+        mut info: ParticipantInfo,
+        college: College,
+    ) -> Result<Participant, Vec<InvalidField>> {
+        let mut invalid = check_participant(&info, self.college_exists(college.id()))
+            .err()
+            .unwrap_or_default();
+        if !self.category_active(info.category.id) {
+            invalid.push(InvalidField::InactiveCategory);
+        }
+        if !invalid.is_empty() {
+            return Err(invalid);
+        }
+        info.phone = normalise_phone(&info.phone).unwrap_or(info.phone);
+        let last_insert_id = {
+            let info_ = info.clone();
+            let mut lock = lock_conn(&self.conn);
+            // A participant is saved with their Kalotsavam details or not at all.
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            let id = tx
+                .prep_exec(
+                    r"
+            INSERT INTO participant(college_id, email, password, name, phone, gender, category, desk, created_at) VALUES(
+                ?,?,?,?,?,?,?,?,NOW()
+            )",
+                    (
+                        college.id(),
+                        info_.email,
+                        String::from("password"),
+                        info_.name,
+                        info_.phone,
+                        gender_to_i32(info_.gender),
+                        info_.category.id,
+                        self.session.desk.clone(),
+                    ),
+                )
+                .unwrap()
+                .last_insert_id() as i32;
+            save_kalotsavam_details(&mut tx, id, info.kalotsavam.as_ref());
+            tx.commit().unwrap();
+            id
         };
-        
-        return self.participant_verify_reg(ParticipantRegNotVerified{id: last_insert_id});
+        self.last_action = None;
+
+        Ok(self.participant_get(last_insert_id).unwrap())
     }
 
     fn participant_get(&self, id: i32) -> Option<Participant> {
-        let mut lock = self.conn.lock().unwrap();
+        let mut lock = lock_conn(&self.conn);
         let mut stmt = lock
             .prepare(
                 r"
                 SELECT participant.id,
+                       participant.version,
 
                        participant.name,
                        gender,
@@ -135,38 +617,73 @@ impl IRegDesk for RegDesk {
                        h_admin.id,
                        h_admin.name,
                        hospitality_reg.hostel,
-                       hospitality_reg.room
+                       hospitality_reg.room,
+                       COALESCE(hospitality_reg.arrival, DATE(hospitality_reg.created_at)),
+                       COALESCE(hospitality_reg.departure,
+                                DATE(hospitality_reg.created_at) + INTERVAL 1 DAY),
+                       hospitality_reg.checked_in,
+                       hospitality_reg.checked_out,
+                       hospitality_reg.tariff,
+                       (SELECT CAST(COALESCE(SUM(amount), 0) AS SIGNED) FROM payment
+                        WHERE payment.participant_id=participant.id AND purpose=1),
+                       kalotsavam_details.institution_type,
+                       kalotsavam_details.class,
+                       kalotsavam_details.district,
+                       kalotsavam_details.date_of_birth,
+                       category.name,
+                       category.prefix,
+                       category.fee,
+                       category.hospitality,
+                       category.events,
+                       category.active
 
                 FROM `participant` 
                 JOIN college ON participant.college_id=college.id
+                JOIN category ON participant.category=category.id
                 LEFT JOIN offline_reg on participant.id=offline_reg.participant_id
                 LEFT JOIN admin as r_admin on r_admin.id=offline_reg.admin_id
                 LEFT JOIN hospitality_reg on participant.id=hospitality_reg.participant_id
                 LEFT JOIN admin as h_admin on h_admin.id=hospitality_reg.admin_id
+                LEFT JOIN kalotsavam_details
+                       on participant.id=kalotsavam_details.participant_id
                 WHERE participant.id=?",
             )
             .unwrap();
-        let row = stmt.execute((id,)).unwrap().last();
-        if row.is_none() {
-            return None;
-        }
-        let row = row.unwrap().unwrap();
+        let row = stmt.execute((id,)).unwrap().last()?.unwrap();
 
-        let (r_admin_id, r_admin_name) = (row.get(8).unwrap(), row.get(9).unwrap());
-        let (h_admin_id, h_admin_name) = (row.get(10).unwrap(), row.get(11).unwrap());
+        let (r_admin_id, r_admin_name) = (row.get(9).unwrap(), row.get(10).unwrap());
+        let (h_admin_id, h_admin_name) = (row.get(11).unwrap(), row.get(12).unwrap());
+        let college_id: i32 = row.get_opt(7).unwrap().expect("7");
+        drop(stmt);
+        let college = college_get(&mut lock, college_id)?;
         Some(Participant {
             id: row.get_opt(0).unwrap().expect("0"),
+            version: row.get_opt(1).unwrap().expect("1"),
             info: ParticipantInfo {
-                name: row.get_opt(1).unwrap().expect("1"),
-                gender: gender_from_i32(row.get_opt(2).unwrap().expect("2")),
-                email: row.get_opt(3).unwrap().expect("3"),
-                phone: row.get_opt(4).unwrap().expect("4"),
-                category: category_from_i32(row.get_opt(5).unwrap().expect("5")),
-            },
-            college: College {
-                id: row.get_opt(6).unwrap().expect("6"),
-                name: row.get_opt(7).unwrap().expect("7"),
+                name: row.get_opt(2).unwrap().expect("2"),
+                gender: gender_from_i32(row.get_opt(3).unwrap().expect("3")),
+                email: row.get_opt(4).unwrap().expect("4"),
+                phone: row.get_opt(5).unwrap().expect("5"),
+                category: Category {
+                    id: row.get_opt(6).unwrap().expect("6"),
+                    name: row.get_opt(25).unwrap().expect("25"),
+                    prefix: row.get_opt(26).unwrap().expect("26"),
+                    fee: row.get_opt(27).unwrap().expect("27"),
+                    hospitality: row.get_opt(28).unwrap().expect("28"),
+                    events: row.get_opt(29).unwrap().expect("29"),
+                    active: row.get_opt(30).unwrap().expect("30"),
+                },
+                kalotsavam: row
+                    .get::<Option<i32>, _>(21)
+                    .unwrap()
+                    .map(|institution_type| KalotsavamDetails {
+                        institution_type: institution_type_from_i32(institution_type),
+                        class: row.get_opt(22).unwrap().expect("22"),
+                        district: row.get_opt(23).unwrap().expect("23"),
+                        date_of_birth: row.get_opt(24).unwrap().expect("24"),
+                    }),
             },
+            college,
             reg_status: match (r_admin_id, r_admin_name) {
                 (Some(id), Some(name)) => Ok(ParticipantRegVerified {
                     admin: Admin { id, name },
@@ -176,43 +693,336 @@ impl IRegDesk for RegDesk {
             hospitality: match (h_admin_id, h_admin_name) {
                 (Some(id), Some(name)) => Some(HospitalityVerified {
                     admin: Admin { id, name },
-                    hostel: row.get_opt(12).unwrap().expect("12"),
-                    room: row.get_opt(13).unwrap().expect("13"),
+                    hostel: row.get_opt(13).unwrap().expect("13"),
+                    room: row.get_opt(14).unwrap().expect("14"),
+                    arrival: row.get_opt(15).unwrap().expect("15"),
+                    departure: row.get_opt(16).unwrap().expect("16"),
+                    checked_in: row.get(17).unwrap(),
+                    checked_out: row.get(18).unwrap(),
+                    tariff: row.get_opt(19).unwrap().expect("19"),
+                    paid: row.get_opt(20).unwrap().expect("20"),
                 }),
                 _ => None,
             },
         })
     }
-    fn participant_update(&mut self, participant: &Participant) {
-        let mut lock = self.conn.lock().unwrap();
-        let res = lock
+
+    fn participant_find_by_phone(&self, phone: &str) -> Vec<Participant> {
+        let phone = match normalise_phone(phone) {
+            Some(phone) => phone,
+            None => return Vec::new(),
+        };
+        let ids: Vec<i32> = lock_conn(&self.conn)
+            .prep_exec(r"SELECT id FROM participant WHERE phone=? ORDER BY id", (phone,))
+            .map(|result| result.map(|x| mysql::from_row(x.unwrap())).collect())
+            .unwrap();
+        ids.into_iter()
+            .filter_map(|id| self.participant_get(id))
+            .collect()
+    }
+
+    fn participant_update(
+        &mut self,
+        participant: &Participant,
+    ) -> Result<Participant, UpdateError> {
+        let college_exists = self.college_exists(participant.college.id());
+        check_participant(&participant.info, college_exists).map_err(UpdateError::Invalid)?;
+        let before = self
+            .participant_get(participant.id)
+            .ok_or(UpdateError::UnknownParticipant)?;
+        let affected_rows = {
+            let mut lock = lock_conn(&self.conn);
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            let affected_rows = tx
+                .prep_exec(
+                    r"UPDATE participant SET college_id=?, email=?, password=?, name=?, phone=?, gender=?, category=?, version=version+1 WHERE id=? AND version=?",
+                    (
+                        participant.college.id(),
+                        participant.info.email.clone(),
+                        String::from("password"),
+                        participant.info.name.clone(),
+                        normalise_phone(&participant.info.phone)
+                            .unwrap_or_else(|| participant.info.phone.clone()),
+                        gender_to_i32(participant.info.gender),
+                        participant.info.category.id,
+                        participant.id,
+                        participant.version,
+                    ),
+                )
+                .unwrap()
+                .affected_rows();
+            if affected_rows != 0 {
+                save_kalotsavam_details(&mut tx, participant.id, participant.info.kalotsavam.as_ref());
+            }
+            tx.commit().unwrap();
+            affected_rows
+        };
+
+        let current = self
+            .participant_get(participant.id)
+            .ok_or(UpdateError::UnknownParticipant)?;
+        if affected_rows == 0 {
+            return Err(UpdateError::Conflict(Box::new(UpdateConflict { current })));
+        }
+        self.last_action = Some(UndoRecord::new(UndoableAction::Update, before, current.clone()));
+        Ok(current)
+    }
+
+    fn participant_verify_reg(
+        &mut self,
+        p: ParticipantRegNotVerified,
+    ) -> Result<Participant, VerifyError> {
+        let before = self.participant_get(p.id).unwrap();
+        let newly_verified = {
+            let mut lock = lock_conn(&self.conn);
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            lock_participant(&mut tx, p.id);
+            // Another desk verified the participant first.
+            if reg_verified(&mut tx, p.id) {
+                false
+            } else {
+                let due = amount_due(&mut tx, p.id);
+                if due > 0 {
+                    return Err(VerifyError::AmountDue(due));
+                }
+                insert_offline_reg(&mut tx, &self.session, p.id);
+                tx.commit().unwrap();
+                true
+            }
+        };
+        let verified = self.participant_get(p.id).unwrap();
+        self.last_action = if newly_verified {
+            Some(UndoRecord::new(UndoableAction::Verify, before, verified.clone()))
+        } else {
+            None
+        };
+        Ok(verified)
+    }
+
+    fn participant_amount_due(&self, participant_id: i32) -> i64 {
+        amount_due(&mut *lock_conn(&self.conn), participant_id)
+    }
+
+    fn participant_set_photo(
+        &mut self,
+        participant_id: i32,
+        photo: Photo,
+    ) -> Result<(), PhotoError> {
+        if photo.image.len() > PHOTO_MAX_SIZE {
+            return Err(PhotoError::TooLarge);
+        }
+        if self.participant_get(participant_id).is_none() {
+            return Err(PhotoError::UnknownParticipant);
+        }
+        lock_conn(&self.conn)
             .prep_exec(
-                r"UPDATE participant SET college_id=?, email=?, password=?, name=?, phone=?, gender=?, category=? WHERE id=?",
-                (
-                    participant.college.id(),
-                    participant.info.email.clone(),
-                    String::from("password"),
-                    participant.info.name.clone(),
-                    participant.info.phone.clone(),
-                    gender_to_i32(participant.info.gender),
-                    category_to_i32(participant.info.category),
-                    participant.id
-                ),
+                r"INSERT INTO participant_photo(participant_id, image, thumbnail) VALUES(?,?,?)
+                  ON DUPLICATE KEY UPDATE image=VALUES(image), thumbnail=VALUES(thumbnail)",
+                (participant_id, photo.image, photo.thumbnail),
             )
             .unwrap();
+        Ok(())
     }
 
-    fn participant_verify_reg(&mut self, p: ParticipantRegNotVerified) -> Participant {
-        {
-            let mut lock = self.conn.lock().unwrap();
-            let res = lock
-                .prep_exec(
-                    r"INSERT INTO offline_reg(participant_id, admin_id) VALUES(?,?)",
-                    (p.id, self.admin.id),
+    fn participant_thumbnail(&self, participant_id: i32) -> Option<Vec<u8>> {
+        lock_conn(&self.conn)
+            .first_exec(
+                r"SELECT thumbnail FROM participant_photo WHERE participant_id=?",
+                (participant_id,),
+            )
+            .unwrap()
+    }
+
+    fn participant_payments(&self, participant_id: i32) -> Vec<Payment> {
+        self.payments_where("participant_id=?", mysql::Params::from((participant_id,)))
+    }
+
+    fn last_action(&self) -> Option<LastAction> {
+        self.last_action.as_ref().and_then(UndoRecord::last_action)
+    }
+
+    fn undo_last(&mut self) -> Result<Participant, UndoError> {
+        let record = match self.last_action.take() {
+            Some(record) if record.last_action().is_some() => record,
+            _ => return Err(UndoError::NothingToUndo),
+        };
+        let id = record.after.id;
+        let current = self.participant_get(id).ok_or(UndoError::NothingToUndo)?;
+        if !record.unchanged(&current) {
+            return Err(UndoError::Changed(Box::from(current)));
+        }
+        match (record.action, &record.before.hospitality) {
+            (UndoableAction::Update, _) => {
+                // Puts the details back as they were, without checking them
+                // again: they were valid when saved. The version moves on, so
+                // that a desk still holding the details from before the update
+                // cannot save over the undo.
+                let before = &record.before;
+                let mut lock = lock_conn(&self.conn);
+                let mut tx = lock.start_transaction(false, None, None).unwrap();
+                let affected_rows = tx
+                    .prep_exec(
+                        r"UPDATE participant SET college_id=?, email=?, name=?, phone=?, gender=?, category=?, version=version+1
+                          WHERE id=? AND version=?",
+                        (
+                            before.college.id(),
+                            &before.info.email,
+                            &before.info.name,
+                            &before.info.phone,
+                            gender_to_i32(before.info.gender),
+                            before.info.category.id,
+                            id,
+                            current.version,
+                        ),
+                    )
+                    .unwrap()
+                    .affected_rows();
+                if affected_rows == 0 {
+                    return Err(UndoError::Changed(Box::from(current)));
+                }
+                save_kalotsavam_details(&mut tx, id, before.info.kalotsavam.as_ref());
+                tx.commit().unwrap();
+            }
+            (UndoableAction::Verify, _) => {
+                lock_conn(&self.conn)
+                    .prep_exec(r"DELETE FROM offline_reg WHERE participant_id=?", (id,))
+                    .unwrap();
+            }
+            (UndoableAction::Allocate, None) => {
+                lock_conn(&self.conn)
+                    .prep_exec(r"DELETE FROM hospitality_reg WHERE participant_id=?", (id,))
+                    .unwrap();
+            }
+            (UndoableAction::Allocate, Some(stay)) => {
+                lock_conn(&self.conn)
+                    .prep_exec(
+                        r"UPDATE hospitality_reg SET admin_id=?, desk=?, session_id=?, hostel=?, room=?,
+                            arrival=?, departure=?, checked_in=?, checked_out=?, tariff=?
+                          WHERE participant_id=?",
+                        (
+                            stay.admin.id,
+                            record
+                                .allocated_by
+                                .as_ref()
+                                .map(|(desk, _)| desk.clone())
+                                .unwrap_or_default(),
+                            record.allocated_by.as_ref().and_then(|(_, session)| *session),
+                            &stay.hostel,
+                            &stay.room,
+                            stay.arrival,
+                            stay.departure,
+                            stay.checked_in,
+                            stay.checked_out,
+                            stay.tariff,
+                            id,
+                        ),
+                    )
+                    .unwrap();
+            }
+        }
+        Ok(self.participant_get(id).unwrap())
+    }
+
+    fn desk_recent_activity(&self, limit: usize) -> Vec<DeskActivity> {
+        let desk = self.session.desk.clone();
+        // Enough rows for `limit` participants even if each was created,
+        // verified and allocated here.
+        let rows: Vec<(i32, i32, String)> = lock_conn(&self.conn)
+            .prep_exec(
+                r"SELECT participant_id, action, DATE_FORMAT(at, '%Y-%m-%d %H:%i') FROM (
+                    SELECT id AS participant_id, 0 AS action, created_at AS at FROM participant
+                    WHERE desk=? AND created_at IS NOT NULL
+                    UNION ALL
+                    SELECT participant_id, 1, created_at FROM offline_reg WHERE desk=?
+                    UNION ALL
+                    SELECT participant_id, 2, created_at FROM hospitality_reg WHERE desk=?
+                  ) activity
+                  ORDER BY at DESC, action DESC, participant_id DESC
+                  LIMIT ?",
+                (desk.clone(), desk.clone(), desk, (limit * 3) as u64),
+            )
+            .map(|result| result.map(|x| mysql::from_row(x.unwrap())).collect())
+            .unwrap();
+
+        let mut seen = HashSet::new();
+        rows.into_iter()
+            .filter(|(id, _, _)| seen.insert(*id))
+            .take(limit)
+            .filter_map(|(id, action, at)| {
+                Some(DeskActivity {
+                    participant: self.participant_get(id)?,
+                    action: match action {
+                        0 => DeskAction::Created,
+                        1 => DeskAction::Verified,
+                        _ => DeskAction::Allocated,
+                    },
+                    at,
+                })
+            })
+            .collect()
+    }
+
+    fn shift_summary(&self) -> ShiftSummary {
+        let (started_at, counted_cash): (String, Option<i64>) = lock_conn(&self.conn)
+            .first_exec(
+                r"SELECT DATE_FORMAT(started_at, '%Y-%m-%d %H:%i'), counted_cash
+                  FROM `session` WHERE id=?",
+                (self.session.id,),
+            )
+            .unwrap()
+            .unwrap();
+        let count = |table: &str| -> i64 {
+            lock_conn(&self.conn)
+                .first_exec(
+                    format!("SELECT COUNT(*) FROM `{}` WHERE session_id=?", table),
+                    (self.session.id,),
                 )
-                .unwrap();
+                .unwrap()
+                .unwrap_or(0)
+        };
+        ShiftSummary {
+            session: self.session.clone(),
+            started_at,
+            payments: self.payments_where("session_id=?", mysql::Params::from((self.session.id,))),
+            verifications: count("offline_reg"),
+            hospitality_allocations: count("hospitality_reg"),
+            counted_cash,
         }
-        self.participant_get(p.id).unwrap()
+    }
+
+    fn shift_record_cash(&mut self, counted_cash: i64) -> ShiftSummary {
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"UPDATE `session` SET counted_cash=? WHERE id=?",
+                (counted_cash, self.session.id),
+            )
+            .unwrap();
+        self.shift_summary()
+    }
+
+
+    fn payment_add(
+        &mut self,
+        participant_id: i32,
+        info: PaymentInfo,
+    ) -> Result<Payment, PaymentError> {
+        let mut lock = lock_conn(&self.conn);
+        // The participant stays locked until the payment is in, so that two
+        // desks cannot both take the amount due.
+        let mut tx = lock.start_transaction(false, None, None).unwrap();
+        if !lock_participant(&mut tx, participant_id) {
+            return Err(PaymentError::UnknownParticipant);
+        }
+        info.check(amount_due(&mut tx, participant_id))?;
+        let payment = add_payment(
+            &mut tx,
+            &self.session,
+            participant_id,
+            PaymentPurpose::Registration,
+            info,
+        );
+        tx.commit().unwrap();
+        Ok(payment)
     }
 
     fn participant_update_hospi(
@@ -220,50 +1030,1272 @@ impl IRegDesk for RegDesk {
         p: Participant,
         hostel: &str,
         room: &str,
-    ) -> Participant {
-        {
-            let mut lock = self.conn.lock().unwrap();
-            let res = lock
+        arrival: NaiveDate,
+        departure: NaiveDate,
+    ) -> Result<Participant, HospitalityError> {
+        let before = self.participant_get(p.id).unwrap();
+        let allocated_by = {
+            let mut lock = lock_conn(&self.conn);
+            // Locked so that the allocation kept for undo is the one replaced.
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            lock_participant(&mut tx, p.id);
+            let hospitality: Option<bool> = tx
+                .first_exec(
+                    r"SELECT category.hospitality FROM participant
+                      JOIN `category` ON category.id=participant.category
+                      WHERE participant.id=?",
+                    (p.id,),
+                )
+                .unwrap();
+            if hospitality != Some(true) {
+                return Err(HospitalityError::NoHospitality);
+            }
+            let allocated_by: Option<(String, Option<i32>)> = tx
+                .first_exec(
+                    r"SELECT desk, session_id FROM hospitality_reg WHERE participant_id=?",
+                    (p.id,),
+                )
+                .unwrap();
+            tx.prep_exec(
+                r"INSERT INTO hospitality_reg(participant_id, admin_id, desk, session_id, hostel, room, arrival, departure, tariff)
+                  VALUES(?,?,?,?,?,?,?,?, COALESCE((SELECT tariff FROM hostel WHERE name=?), 0))
+                  ON DUPLICATE KEY UPDATE admin_id=VALUES(admin_id), desk=VALUES(desk),
+                    session_id=VALUES(session_id),
+                    hostel=VALUES(hostel), room=VALUES(room),
+                    arrival=VALUES(arrival), departure=VALUES(departure), tariff=VALUES(tariff)",
+                (
+                    p.id,
+                    self.session.admin.id,
+                    &self.session.desk,
+                    self.session.id,
+                    hostel,
+                    room,
+                    arrival,
+                    departure,
+                    hostel,
+                ),
+            )
+            .unwrap();
+            tx.commit().unwrap();
+            allocated_by
+        };
+        let allocated = self.participant_get(p.id).unwrap();
+        let mut record = UndoRecord::new(UndoableAction::Allocate, before, allocated.clone());
+        record.allocated_by = allocated_by;
+        self.last_action = Some(record);
+        Ok(allocated)
+    }
+
+    fn hospitality_check_in(
+        &mut self,
+        participant_id: i32,
+    ) -> Result<Participant, HospitalityError> {
+        let stay = self.stay(participant_id)?;
+        if stay.checked_in.is_some() {
+            return Err(HospitalityError::AlreadyCheckedIn);
+        }
+        lock_conn(&self.conn)
             .prep_exec(
-                r"INSERT INTO hospitality_reg(participant_id, admin_id, hostel, room) VALUES(?,?,?,?) ON DUPLICATE KEY UPDATE hostel=VALUES(hostel), room=VALUES(room);",
-                (p.id, self.admin.id, hostel, room),
+                r"UPDATE hospitality_reg SET checked_in=CURDATE() WHERE participant_id=?",
+                (participant_id,),
             )
             .unwrap();
+        Ok(self.participant_get(participant_id).unwrap())
+    }
+
+    fn hospitality_check_out(
+        &mut self,
+        participant_id: i32,
+    ) -> Result<Participant, HospitalityError> {
+        let stay = self.stay(participant_id)?;
+        if stay.checked_in.is_none() {
+            return Err(HospitalityError::NotCheckedIn);
+        }
+        if stay.checked_out.is_some() {
+            return Err(HospitalityError::AlreadyCheckedOut);
         }
-        self.participant_get(p.id).unwrap()
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"UPDATE hospitality_reg SET checked_out=CURDATE() WHERE participant_id=?",
+                (participant_id,),
+            )
+            .unwrap();
+        Ok(self.participant_get(participant_id).unwrap())
     }
 
-    fn college_get_filtered(&self, _name: &str) -> Vec<College> {
-        let colleges: Vec<College> = self
-            .conn
-            .lock()
+    fn hospitality_collect(
+        &mut self,
+        participant_id: i32,
+        info: PaymentInfo,
+    ) -> Result<Payment, PaymentError> {
+        let mut lock = lock_conn(&self.conn);
+        // As in `payment_add`, the participant stays locked until the payment
+        // is in.
+        let mut tx = lock.start_transaction(false, None, None).unwrap();
+        if !lock_participant(&mut tx, participant_id) {
+            return Err(PaymentError::UnknownParticipant);
+        }
+        let stay =
+            hospitality_stay(&mut tx, participant_id).ok_or(PaymentError::UnknownParticipant)?;
+        info.check(stay.amount_due())?;
+        let payment = add_payment(
+            &mut tx,
+            &self.session,
+            participant_id,
+            PaymentPurpose::Hospitality,
+            info,
+        );
+        tx.commit().unwrap();
+        Ok(payment)
+    }
+
+    fn college_get_filtered(&self, key: &str) -> Vec<College> {
+        college_list(&mut lock_conn(&self.conn))
+            .into_iter()
+            .filter(|c| c.matches(key))
+            .collect()
+    }
+
+    fn college_add(&mut self, college: NewCollege) -> College {
+        let mut lock = lock_conn(&self.conn);
+        let res = lock
+            .prep_exec(
+                r"INSERT INTO `college`(name, city, district, short_code) VALUES(?,?,?,?)",
+                (
+                    college.name.trim(),
+                    college.city.trim(),
+                    college.district.trim(),
+                    college.short_code.trim(),
+                ),
+            )
+            .unwrap();
+        let id = res.last_insert_id() as i32;
+        drop(res);
+        college_get(&mut lock, id).unwrap()
+    }
+
+    fn college_duplicates(&self) -> Vec<Vec<CollegeUsage>> {
+        let mut lock = lock_conn(&self.conn);
+        let counts: HashMap<i32, i64> = lock
+            .prep_exec(
+                r"SELECT college_id, COUNT(*) FROM participant GROUP BY college_id",
+                (),
+            )
+            .map(|result| result.map(|x| mysql::from_row(x.unwrap())).collect())
+            .unwrap();
+        let usage = college_list(&mut lock)
+            .into_iter()
+            .map(|college| CollegeUsage {
+                participants: counts.get(&college.id).cloned().unwrap_or(0),
+                college,
+            })
+            .collect();
+        group_duplicates(usage)
+    }
+
+    fn college_merge(
+        &mut self,
+        into: i32,
+        merged: &[i32],
+    ) -> Result<Vec<CollegeMerge>, MergeError> {
+        if merged.contains(&into) {
+            return Err(MergeError::MergeIntoSelf);
+        }
+        let mut lock = lock_conn(&self.conn);
+        let into = college_get(&mut lock, into).ok_or(MergeError::UnknownCollege)?;
+
+        // Returning early drops the transaction, which rolls it back.
+        let mut tx = lock.start_transaction(false, None, None).unwrap();
+        // Checked against the table, as the admin may have been demoted since
+        // logging in.
+        let supervisor: Option<bool> = tx
+            .first_exec(r"SELECT supervisor FROM `admin` WHERE id=?", (self.session.admin.id,))
+            .unwrap();
+        if supervisor != Some(true) {
+            return Err(MergeError::NotSupervisor);
+        }
+        // Locked, so that another desk cannot merge or rename them meanwhile.
+        let mut names = HashMap::new();
+        for id in merged.iter().chain(std::iter::once(&into.id)) {
+            let name: Option<String> = tx
+                .first_exec(r"SELECT name FROM `college` WHERE id=? FOR UPDATE", (id,))
+                .unwrap();
+            names.insert(*id, name.ok_or(MergeError::UnknownCollege)?);
+        }
+        let into = College {
+            name: names[&into.id].clone(),
+            ..into
+        };
+
+        let mut merges = Vec::new();
+        for id in merged {
+            let name = match names.get(id) {
+                Some(name) => name.clone(),
+                None => continue,
+            };
+            let participants = tx
+                .prep_exec(
+                    r"UPDATE participant SET college_id=?, version=version+1 WHERE college_id=?",
+                    (into.id, id),
+                )
+                .unwrap()
+                .affected_rows() as i64;
+            tx.prep_exec(r"UPDATE team SET college_id=? WHERE college_id=?", (into.id, id))
+                .unwrap();
+            // The merged name and aliases live on as aliases of the college kept.
+            tx.prep_exec(
+                r"INSERT IGNORE INTO college_alias(college_id, alias)
+                  SELECT ?, alias FROM college_alias WHERE college_id=? AND alias<>?",
+                (into.id, id, into.name.clone()),
+            )
+            .unwrap();
+            if name != into.name {
+                tx.prep_exec(
+                    r"INSERT IGNORE INTO college_alias(college_id, alias) VALUES(?,?)",
+                    (into.id, name.clone()),
+                )
+                .unwrap();
+            }
+            tx.prep_exec(r"DELETE FROM college_alias WHERE college_id=?", (id,))
+                .unwrap();
+            let deleted = tx
+                .prep_exec(r"DELETE FROM `college` WHERE id=?", (id,))
+                .unwrap()
+                .affected_rows();
+            // Listed twice.
+            if deleted == 0 {
+                continue;
+            }
+            tx.prep_exec(
+                r"INSERT INTO college_merge(merged_id, merged_name, into_id, into_name, participants, admin_id, desk)
+                  VALUES(?,?,?,?,?,?,?)",
+                (
+                    id,
+                    name.clone(),
+                    into.id,
+                    into.name.clone(),
+                    participants,
+                    self.session.admin.id,
+                    self.session.desk.clone(),
+                ),
+            )
+            .unwrap();
+            merges.push(CollegeMerge {
+                merged: name,
+                into: into.clone(),
+                participants,
+                admin: self.session.admin.clone(),
+                desk: self.session.desk.clone(),
+                merged_at: String::new(),
+            });
+        }
+        let merged_at: Option<String> = tx
+            .first_exec(r"SELECT DATE_FORMAT(NOW(), '%Y-%m-%d %H:%i')", ())
+            .unwrap();
+        tx.commit().unwrap();
+        // Now with the merged names among its aliases.
+        let into = college_get(&mut lock, into.id).unwrap_or(into);
+        for merge in merges.iter_mut() {
+            merge.into = into.clone();
+            merge.merged_at = merged_at.clone().unwrap_or_default();
+        }
+        Ok(merges)
+    }
+
+    fn college_merge_history(&self) -> Vec<CollegeMerge> {
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"SELECT merged_name, into_id, into_name, participants, admin.id, admin.name,
+                         college_merge.desk, DATE_FORMAT(college_merge.created_at, '%Y-%m-%d %H:%i')
+                  FROM college_merge JOIN admin ON admin.id=college_merge.admin_id
+                  ORDER BY college_merge.id DESC",
+                (),
+            )
+            .map(|result| {
+                result
+                    .map(|x| x.unwrap())
+                    .map(|row| {
+                        let (merged, into_id, into_name, participants, admin_id, admin_name, desk, merged_at) =
+                            mysql::from_row(row);
+                        CollegeMerge {
+                            merged,
+                            into: College {
+                                id: into_id,
+                                name: into_name,
+                                city: String::new(),
+                                district: String::new(),
+                                short_code: String::new(),
+                                aliases: Vec::new(),
+                            },
+                            participants,
+                            admin: Admin {
+                                id: admin_id,
+                                name: admin_name,
+                            },
+                            desk,
+                            merged_at,
+                        }
+                    })
+                    .collect()
+            })
             .unwrap()
-            .prep_exec(r"SELECT id,name from `college`", ())
+    }
+
+    fn statistics(&self) -> Statistics {
+        let by_category: Vec<(String, i64)> = self.count_by(
+            r"SELECT category.name, COUNT(*) FROM participant
+              JOIN offline_reg ON participant.id=offline_reg.participant_id
+              JOIN category ON participant.category=category.id
+              GROUP BY category.id, category.name ORDER BY category.id",
+        );
+        let by_gender: Vec<(i32, i64)> = self.count_by(
+            r"SELECT gender, COUNT(*) FROM participant
+              JOIN offline_reg ON participant.id=offline_reg.participant_id
+              GROUP BY gender ORDER BY gender",
+        );
+        let by_college = self.count_by(
+            r"SELECT college.name, COUNT(*) FROM participant
+              JOIN offline_reg ON participant.id=offline_reg.participant_id
+              JOIN college ON participant.college_id=college.id
+              GROUP BY college.id, college.name ORDER BY COUNT(*) DESC, college.name",
+        );
+        let by_admin = self.count_by(
+            r"SELECT admin.name, COUNT(*) FROM offline_reg
+              JOIN admin ON offline_reg.admin_id=admin.id
+              GROUP BY admin.id, admin.name ORDER BY COUNT(*) DESC, admin.name",
+        );
+        let by_desk = self.count_by(
+            r"SELECT desk, COUNT(*) FROM offline_reg
+              GROUP BY desk ORDER BY COUNT(*) DESC, desk",
+        );
+        let by_hour = self.count_by(
+            r"SELECT HOUR(created_at), COUNT(*) FROM offline_reg
+              GROUP BY HOUR(created_at) ORDER BY HOUR(created_at)",
+        );
+        let hospitality = lock_conn(&self.conn)
+            .prep_exec(
+                r"SELECT hostel, COUNT(*), COUNT(DISTINCT room) FROM hospitality_reg
+                  GROUP BY hostel ORDER BY hostel",
+                (),
+            )
             .map(|result| {
                 result
                     .map(|x| x.unwrap())
                     .map(|row| {
-                        let (id, name): (i32, String) = mysql::from_row(row);
-                        College { id, name }
+                        let (hostel, participants, rooms) = mysql::from_row(row);
+                        HostelOccupancy {
+                            hostel,
+                            participants,
+                            rooms,
+                        }
                     })
                     .collect()
             })
             .unwrap();
-        colleges
+
+        Statistics {
+            total: by_category.iter().map(|(_, count)| count).sum(),
+            by_category,
+            by_gender: by_gender
+                .into_iter()
+                .map(|(gender, count)| (gender_from_i32(gender), count))
+                .collect(),
+            by_college,
+            by_admin,
+            by_desk,
+            by_hour,
+            hospitality,
+        }
     }
 
-    fn college_add(&mut self, name: String) -> College {
-        let mut lock = self.conn.lock().unwrap();
-        let res = lock
-            .prep_exec(r"INSERT INTO `college`(name) VALUES(?)", (name.clone(),))
+    fn event_list(&self) -> Vec<Event> {
+        self.events_where(None)
+    }
+
+    fn participant_events(&self, participant_id: i32) -> Vec<Event> {
+        self.events_where(Some((
+            "SELECT event_id FROM participant_event WHERE participant_id=?",
+            participant_id,
+        )))
+    }
+
+    fn participant_enrol(&mut self, participant_id: i32, event_id: i32) -> Result<(), EnrolError> {
+        enrol(&mut *lock_conn(&self.conn), &self.session, participant_id, event_id)
+    }
+
+    fn participant_withdraw(&mut self, participant_id: i32, event_id: i32) {
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"DELETE FROM participant_event WHERE participant_id=? AND event_id=?",
+                (participant_id, event_id),
+            )
             .unwrap();
-        College {
-            id: res.last_insert_id() as i32,
+    }
+
+    fn team_create(
+        &mut self,
+        name: &str,
+        event_id: i32,
+        leader: i32,
+        members: &[i32],
+    ) -> Result<Team, TeamError> {
+        let mut all = vec![leader];
+        for &member in members {
+            if !all.contains(&member) {
+                all.push(member);
+            }
+        }
+
+        let team_id = {
+            let mut lock = lock_conn(&self.conn);
+            // Returning early drops the transaction, which rolls it back.
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            // Locking the event makes desks filling it take turns, so the
+            // checks below still hold when the members are enrolled.
+            let locked: Option<i32> = tx
+                .first_exec(r"SELECT id FROM `event` WHERE id=? FOR UPDATE", (event_id,))
+                .unwrap();
+            locked.ok_or(TeamError::UnknownEvent)?;
+            let event = events_where(&mut tx, Some(("?", event_id)))
+                .pop()
+                .ok_or(TeamError::UnknownEvent)?;
+            check_team_size(&event, all.len())?;
+
+            let college_id: Option<i32> = tx
+                .first_exec(r"SELECT college_id FROM participant WHERE id=?", (leader,))
+                .unwrap();
+            let college_id = college_id.ok_or(TeamError::UnknownParticipant(leader))?;
+            for &member in all.iter() {
+                let exists: Option<i32> = tx
+                    .first_exec(r"SELECT id FROM participant WHERE id=?", (member,))
+                    .unwrap();
+                if exists.is_none() {
+                    return Err(TeamError::UnknownParticipant(member));
+                }
+                if team_of(&mut tx, member, event_id).is_some() {
+                    return Err(TeamError::AlreadyInTeam(member));
+                }
+            }
+            if let Some(max) = event.max_participants {
+                let enrolled = enrolled_among(&mut tx, &all, event_id);
+                if event.enrolled + (all.len() - enrolled) as i64 > i64::from(max) {
+                    return Err(TeamError::EventFull);
+                }
+            }
+
+            let team_id = tx
+                .prep_exec(
+                    r"INSERT INTO team(name, event_id, college_id, leader_id, admin_id, desk)
+                      VALUES(?,?,?,?,?,?)",
+                    (
+                        name,
+                        event_id,
+                        college_id,
+                        leader,
+                        self.session.admin.id,
+                        &self.session.desk,
+                    ),
+                )
+                .unwrap()
+                .last_insert_id() as i32;
+            for &member in all.iter() {
+                enrol(&mut tx, &self.session, member, event_id)
+                    .map_err(|err| team_error(err, member))?;
+                tx.prep_exec(
+                    r"INSERT INTO team_member(team_id, participant_id) VALUES(?,?)",
+                    (team_id, member),
+                )
+                .unwrap();
+            }
+            tx.commit().unwrap();
+            team_id
+        };
+        Ok(self.team_get(team_id).unwrap())
+    }
+
+    fn team_get(&self, id: i32) -> Option<Team> {
+        let row: Option<(String, i32, i32, i32)> = lock_conn(&self.conn)
+            .first_exec(
+                r"SELECT name, event_id, college_id, leader_id FROM team WHERE id=?",
+                (id,),
+            )
+            .unwrap();
+        let (name, event_id, college_id, leader) = row?;
+        let college = college_get(&mut lock_conn(&self.conn), college_id)?;
+        let members = lock_conn(&self.conn)
+            .prep_exec(
+                r"SELECT participant_id FROM team_member WHERE team_id=? ORDER BY participant_id",
+                (id,),
+            )
+            .map(|result| {
+                result
+                    .map(|x| x.unwrap())
+                    .map(mysql::from_row)
+                    .collect()
+            })
+            .unwrap();
+        Some(Team {
+            id,
             name,
+            event: self.event_get(event_id)?,
+            college,
+            leader,
+            members,
+        })
+    }
+
+    fn team_add_member(&mut self, team_id: i32, participant_id: i32) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        if team.members.contains(&participant_id) {
+            return Ok(team);
         }
+        check_team_size(&team.event, team.members.len() + 1)?;
+        self.team_enrol(participant_id, team.event.id)?;
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"INSERT INTO team_member(team_id, participant_id) VALUES(?,?)",
+                (team_id, participant_id),
+            )
+            .unwrap();
+        Ok(self.team_get(team_id).unwrap())
+    }
+
+    fn team_remove_member(
+        &mut self,
+        team_id: i32,
+        participant_id: i32,
+    ) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        if team.leader == participant_id {
+            return Err(TeamError::RemoveLeader);
+        }
+        if !team.members.contains(&participant_id) {
+            return Ok(team);
+        }
+        check_team_size(&team.event, team.members.len() - 1)?;
+        lock_conn(&self.conn)
+            .prep_exec(
+                r"DELETE FROM team_member WHERE team_id=? AND participant_id=?",
+                (team_id, participant_id),
+            )
+            .unwrap();
+        self.participant_withdraw(participant_id, team.event.id);
+        Ok(self.team_get(team_id).unwrap())
+    }
+
+    fn team_verify(&mut self, team_id: i32) -> Result<Team, TeamError> {
+        let team = self.team_get(team_id).ok_or(TeamError::UnknownTeam)?;
+        {
+            let mut lock = lock_conn(&self.conn);
+            let mut tx = lock.start_transaction(false, None, None).unwrap();
+            // Locked in id order, so that desks verifying teams that share
+            // members cannot deadlock.
+            let mut members = team.members.clone();
+            members.sort();
+            for &member in members.iter() {
+                lock_participant(&mut tx, member);
+            }
+            let mut not_verified = Vec::new();
+            for &member in members.iter() {
+                if !reg_verified(&mut tx, member) {
+                    if amount_due(&mut tx, member) > 0 {
+                        return Err(TeamError::AmountDue(member));
+                    }
+                    not_verified.push(member);
+                }
+            }
+            for member in not_verified {
+                insert_offline_reg(&mut tx, &self.session, member);
+            }
+            tx.commit().unwrap();
+        }
+        self.last_action = None;
+        Ok(team)
     }
 }
 
+impl RegDesk {
+    fn college_exists(&self, college_id: i32) -> bool {
+        let count: Option<i64> = lock_conn(&self.conn)
+            .first_exec(r"SELECT COUNT(*) FROM `college` WHERE id=?", (college_id,))
+            .unwrap();
+        count.unwrap_or(0) > 0
+    }
+
+    fn category_active(&self, category_id: i32) -> bool {
+        let active: Option<bool> = lock_conn(&self.conn)
+            .first_exec(r"SELECT active FROM `category` WHERE id=?", (category_id,))
+            .unwrap();
+        active.unwrap_or(false)
+    }
+
+    fn stay(&self, participant_id: i32) -> Result<HospitalityVerified, HospitalityError> {
+        hospitality_stay(&mut *lock_conn(&self.conn), participant_id)
+            .ok_or(HospitalityError::NotAllocated)
+    }
+
+    fn payments_where(&self, condition: &str, params: mysql::Params) -> Vec<Payment> {
+        let sql = format!(
+            r"SELECT payment.id, participant_id, purpose, amount, mode, reference, receipt_no,
+                     admin.id, admin.name, desk
+              FROM `payment`
+              JOIN admin ON admin.id=payment.admin_id
+              WHERE {}
+              ORDER BY payment.id",
+            condition
+        );
+        lock_conn(&self.conn)
+            .prep_exec(sql, params)
+            .map(|result| {
+                result
+                    .map(|x| x.unwrap())
+                    .map(|row| {
+                        let (
+                            id,
+                            participant_id,
+                            purpose,
+                            amount,
+                            mode,
+                            reference,
+                            receipt_no,
+                            admin_id,
+                            admin_name,
+                            desk,
+                        ) = mysql::from_row(row);
+                        Payment {
+                            id,
+                            participant_id,
+                            purpose: payment_purpose_from_i32(purpose),
+                            info: PaymentInfo {
+                                amount,
+                                mode: payment_mode_from_i32(mode),
+                                reference,
+                            },
+                            receipt_no,
+                            admin: Admin {
+                                id: admin_id,
+                                name: admin_name,
+                            },
+                            desk,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap()
+    }
+    fn events_where(&self, filter: Option<(&str, i32)>) -> Vec<Event> {
+        events_where(&mut *lock_conn(&self.conn), filter)
+    }
+
+    fn event_get(&self, id: i32) -> Option<Event> {
+        self.events_where(Some(("?", id))).pop()
+    }
+
+    /// Checks that the participant exists, is free to join a team for the
+    /// event and then enrols them in it.
+    fn team_enrol(&mut self, participant_id: i32, event_id: i32) -> Result<(), TeamError> {
+        if self.participant_get(participant_id).is_none() {
+            return Err(TeamError::UnknownParticipant(participant_id));
+        }
+        if team_of(&mut *lock_conn(&self.conn), participant_id, event_id).is_some() {
+            return Err(TeamError::AlreadyInTeam(participant_id));
+        }
+        self.participant_enrol(participant_id, event_id)
+            .map_err(|err| team_error(err, participant_id))
+    }
+
+    /// Runs a `SELECT key, COUNT(*) ... GROUP BY key` query.
+    fn count_by<T: FromValue>(&self, sql: &str) -> Vec<(T, i64)> {
+        lock_conn(&self.conn)
+            .prep_exec(sql, ())
+            .map(|result| {
+                result
+                    .map(|x| x.unwrap())
+                    .map(mysql::from_row)
+                    .collect()
+            })
+            .unwrap()
+    }
+}
+
+fn save_kalotsavam_details<C: GenericConnection>(
+    conn: &mut C,
+    participant_id: i32,
+    details: Option<&KalotsavamDetails>,
+) {
+    match details {
+        Some(details) => conn.prep_exec(
+            r"INSERT INTO kalotsavam_details(participant_id, institution_type, class, district, date_of_birth)
+              VALUES(?,?,?,?,?)
+              ON DUPLICATE KEY UPDATE institution_type=VALUES(institution_type), class=VALUES(class),
+                district=VALUES(district), date_of_birth=VALUES(date_of_birth)",
+            (
+                participant_id,
+                institution_type_to_i32(details.institution_type),
+                details.class.trim(),
+                details.district.trim(),
+                details.date_of_birth,
+            ),
+        ),
+        None => conn.prep_exec(
+            r"DELETE FROM kalotsavam_details WHERE participant_id=?",
+            (participant_id,),
+        ),
+    }
+    .unwrap();
+}
+
+/// Locks the participant's row until the transaction ends. Returns whether
+/// the participant exists.
+fn lock_participant<C: GenericConnection>(conn: &mut C, participant_id: i32) -> bool {
+    let locked: Option<i32> = conn
+        .first_exec(
+            r"SELECT id FROM participant WHERE id=? FOR UPDATE",
+            (participant_id,),
+        )
+        .unwrap();
+    locked.is_some()
+}
+
+/// The room allocated to the participant, `None` if there is none.
+fn hospitality_stay<C: GenericConnection>(
+    conn: &mut C,
+    participant_id: i32,
+) -> Option<HospitalityVerified> {
+    let row: Option<mysql::Row> = conn
+        .first_exec(
+            r"SELECT admin.id, admin.name, hostel, room,
+                COALESCE(arrival, DATE(hospitality_reg.created_at)),
+                COALESCE(departure, DATE(hospitality_reg.created_at) + INTERVAL 1 DAY),
+                checked_in, checked_out, tariff,
+                (SELECT CAST(COALESCE(SUM(amount), 0) AS SIGNED) FROM payment
+                 WHERE payment.participant_id=hospitality_reg.participant_id AND purpose=1)
+              FROM hospitality_reg
+              JOIN admin ON admin.id=hospitality_reg.admin_id
+              WHERE participant_id=?",
+            (participant_id,),
+        )
+        .unwrap();
+    let row = row?;
+    Some(HospitalityVerified {
+        admin: Admin {
+            id: row.get_opt(0).unwrap().expect("0"),
+            name: row.get_opt(1).unwrap().expect("1"),
+        },
+        hostel: row.get_opt(2).unwrap().expect("2"),
+        room: row.get_opt(3).unwrap().expect("3"),
+        arrival: row.get_opt(4).unwrap().expect("4"),
+        departure: row.get_opt(5).unwrap().expect("5"),
+        checked_in: row.get(6).unwrap(),
+        checked_out: row.get(7).unwrap(),
+        tariff: row.get_opt(8).unwrap().expect("8"),
+        paid: row.get_opt(9).unwrap().expect("9"),
+    })
+}
+
+fn reg_verified<C: GenericConnection>(conn: &mut C, participant_id: i32) -> bool {
+    let found: Option<i32> = conn
+        .first_exec(
+            r"SELECT participant_id FROM offline_reg WHERE participant_id=?",
+            (participant_id,),
+        )
+        .unwrap();
+    found.is_some()
+}
+
+fn insert_offline_reg<C: GenericConnection>(conn: &mut C, session: &Session, participant_id: i32) {
+    conn.prep_exec(
+        r"INSERT INTO offline_reg(participant_id, admin_id, desk, session_id) VALUES(?,?,?,?)",
+        (participant_id, session.admin.id, &session.desk, session.id),
+    )
+    .unwrap();
+}
+
+/// The category fee less the registration payments so far.
+fn amount_due<C: GenericConnection>(conn: &mut C, participant_id: i32) -> i64 {
+    let due: Option<i64> = conn
+        .first_exec(
+            r"SELECT CAST(category.fee - COALESCE(
+                  (SELECT SUM(amount) FROM payment
+                   WHERE participant_id=participant.id AND purpose=0), 0
+                ) AS SIGNED)
+              FROM `participant`
+              JOIN category ON category.id=participant.category
+              WHERE participant.id=?",
+            (participant_id,),
+        )
+        .unwrap();
+    due.unwrap_or(0)
+}
+
+fn add_payment<C: GenericConnection>(
+    conn: &mut C,
+    session: &Session,
+    participant_id: i32,
+    purpose: PaymentPurpose,
+    info: PaymentInfo,
+) -> Payment {
+    let id = conn
+        .prep_exec(
+            r"INSERT INTO payment(participant_id, purpose, amount, mode, reference, admin_id, desk, session_id)
+              VALUES(?,?,?,?,?,?,?,?)",
+            (
+                participant_id,
+                payment_purpose_to_i32(purpose),
+                info.amount,
+                payment_mode_to_i32(info.mode),
+                info.reference.trim(),
+                session.admin.id,
+                &session.desk,
+                session.id,
+            ),
+        )
+        .unwrap()
+        .last_insert_id() as i32;
+    let receipt_no = receipt_no(&session.desk, id);
+    conn.prep_exec(
+        r"UPDATE payment SET receipt_no=? WHERE id=?",
+        (&receipt_no, id),
+    )
+    .unwrap();
+    Payment {
+        id,
+        participant_id,
+        purpose,
+        info: PaymentInfo {
+            reference: info.reference.trim().to_owned(),
+            ..info
+        },
+        receipt_no,
+        admin: session.admin.clone(),
+        desk: session.desk.clone(),
+    }
+}
+
+/// Events with their enrolment counts, restricted by an `event.id IN (...)`
+/// subquery or returning all events when `filter` is `None`.
+fn events_where<C: GenericConnection>(conn: &mut C, filter: Option<(&str, i32)>) -> Vec<Event> {
+    let (condition, param) = filter.unwrap_or(("SELECT id FROM event", 0));
+    let sql = format!(
+        r"SELECT event.id, event.name, event.max_participants,
+                 event.min_team_size, event.max_team_size,
+                 COUNT(participant_event.participant_id)
+          FROM `event`
+          LEFT JOIN participant_event ON participant_event.event_id=event.id
+          WHERE event.id IN ({})
+          GROUP BY event.id, event.name, event.max_participants,
+                   event.min_team_size, event.max_team_size
+          ORDER BY event.name",
+        condition
+    );
+    let params = if filter.is_some() {
+        mysql::Params::from((param,))
+    } else {
+        mysql::Params::Empty
+    };
+    conn.prep_exec(sql, params)
+        .map(|result| {
+            result
+                .map(|x| x.unwrap())
+                .map(|row| {
+                    let (id, name, max_participants, min_team, max_team, enrolled) =
+                        mysql::from_row(row);
+                    Event {
+                        id,
+                        name,
+                        max_participants,
+                        team_size: match (min_team, max_team) {
+                            (Some(min), Some(max)) => Some((min, max)),
+                            _ => None,
+                        },
+                        enrolled,
+                    }
+                })
+                .collect()
+        })
+        .unwrap()
+}
+
+/// The team the participant is in for the event, if any.
+fn team_of<C: GenericConnection>(
+    conn: &mut C,
+    participant_id: i32,
+    event_id: i32,
+) -> Option<i32> {
+    conn.first_exec(
+        r"SELECT team.id FROM team_member
+          JOIN team ON team.id=team_member.team_id
+          WHERE team_member.participant_id=? AND team.event_id=?",
+        (participant_id, event_id),
+    )
+    .unwrap()
+}
+
+/// How many of the participants are already enrolled in the event.
+fn enrolled_among<C: GenericConnection>(
+    conn: &mut C,
+    participants: &[i32],
+    event_id: i32,
+) -> usize {
+    participants
+        .iter()
+        .filter(|&&p| {
+            let enrolled: Option<i32> = conn
+                .first_exec(
+                    r"SELECT event_id FROM participant_event WHERE participant_id=? AND event_id=?",
+                    (p, event_id),
+                )
+                .unwrap();
+            enrolled.is_some()
+        })
+        .count()
+}
+
+/// Enrols the participant by the session's admin. Enrolling twice is not an
+/// error.
+fn enrol<C: GenericConnection>(
+    conn: &mut C,
+    session: &Session,
+    participant_id: i32,
+    event_id: i32,
+) -> Result<(), EnrolError> {
+    let enrolled: Option<i32> = conn
+        .first_exec(
+            r"SELECT event_id FROM participant_event WHERE participant_id=? AND event_id=?",
+            (participant_id, event_id),
+        )
+        .unwrap();
+    if enrolled.is_some() {
+        return Ok(());
+    }
+    let events: Option<bool> = conn
+        .first_exec(
+            r"SELECT category.events FROM participant
+              JOIN category ON category.id=participant.category
+              WHERE participant.id=?",
+            (participant_id,),
+        )
+        .unwrap();
+    if events != Some(true) {
+        return Err(EnrolError::NoEvents);
+    }
+    // Checking the limit in the insert itself keeps two desks from
+    // taking the last place at the same time.
+    let inserted = conn
+        .prep_exec(
+            r"INSERT INTO participant_event(participant_id, event_id, admin_id, desk)
+              SELECT ?, id, ?, ? FROM `event`
+              WHERE id=? AND (max_participants IS NULL OR max_participants >
+                  (SELECT COUNT(*) FROM participant_event WHERE event_id=?))",
+            (
+                participant_id,
+                session.admin.id,
+                &session.desk,
+                event_id,
+                event_id,
+            ),
+        )
+        .unwrap()
+        .affected_rows();
+    if inserted > 0 {
+        return Ok(());
+    }
+    let exists: Option<i32> = conn
+        .first_exec(r"SELECT id FROM `event` WHERE id=?", (event_id,))
+        .unwrap();
+    match exists {
+        Some(_) => Err(EnrolError::EventFull),
+        None => Err(EnrolError::UnknownEvent),
+    }
+}
+
+fn team_error(err: EnrolError, participant_id: i32) -> TeamError {
+    match err {
+        EnrolError::EventFull => TeamError::EventFull,
+        EnrolError::UnknownEvent => TeamError::UnknownEvent,
+        EnrolError::NoEvents => TeamError::NoEvents(participant_id),
+    }
+}
+
+/// The connection of a login and its sessions, with how the last call on it
+/// went.
+struct SharedConn {
+    conn: Mutex<mysql::Conn>,
+    last_call: Mutex<Option<LastCall>>,
+}
+
+/// The locked connection. Times the call made with it, and records it as
+/// failed when the call panics.
+struct ConnGuard<'a> {
+    lock: MutexGuard<'a, mysql::Conn>,
+    last_call: &'a Mutex<Option<LastCall>>,
+    started: Instant,
+    failed: bool,
+}
+
+impl Deref for ConnGuard<'_> {
+    type Target = mysql::Conn;
+
+    fn deref(&self) -> &mysql::Conn {
+        &self.lock
+    }
+}
+
+impl DerefMut for ConnGuard<'_> {
+    fn deref_mut(&mut self) -> &mut mysql::Conn {
+        &mut self.lock
+    }
+}
+
+impl Drop for ConnGuard<'_> {
+    fn drop(&mut self) {
+        let call = if self.failed || std::thread::panicking() {
+            LastCall::Failed
+        } else {
+            LastCall::Done(self.started.elapsed())
+        };
+        *self.last_call.lock().unwrap_or_else(|e| e.into_inner()) = Some(call);
+    }
+}
+
+/// Checks the password of admin `id`, refusing disabled and locked accounts.
+/// A wrong password counts towards the lock, and a right one clears the count.
+fn check_password(conn: &mut mysql::Conn, id: i32, password: &str) -> Result<(), LoginError> {
+    let unreachable = |_| LoginError::Unreachable;
+    // Password, disabled and seconds left of a lock.
+    let row: Option<(String, bool, Option<i64>)> = conn
+        .first_exec(
+            r"SELECT password, disabled,
+                TIMESTAMPDIFF(SECOND, CURRENT_TIMESTAMP, locked_until)
+              FROM `admin` WHERE id=?",
+            (id,),
+        )
+        .map_err(unreachable)?;
+    let (expected, disabled, locked_for) = row.ok_or(LoginError::UnknownUser)?;
+    if disabled {
+        return Err(LoginError::Disabled);
+    }
+    if let Some(secs) = locked_for.filter(|secs| *secs > 0) {
+        return Err(LoginError::Locked(Duration::from_secs(secs as u64)));
+    }
+    if expected != password {
+        return Err(count_failed_attempt(conn, id).map_err(unreachable)?);
+    }
+    conn.prep_exec(
+        r"UPDATE `admin` SET failed_attempts=0, locked_until=NULL WHERE id=?",
+        (id,),
+    )
+    .map_err(unreachable)?;
+    Ok(())
+}
+
+/// Counts a wrong password for admin `id` and locks the account on the last
+/// allowed attempt. Incremented in place, so that attempts made at the same
+/// time from other desks all count.
+fn count_failed_attempt(conn: &mut mysql::Conn, id: i32) -> Result<LoginError, mysql::Error> {
+    let mut tx = conn.start_transaction(false, None, None)?;
+    // `locked_until` is set first, as MySQL assigns left to right and the
+    // second assignment sees the first.
+    tx.prep_exec(
+        r"UPDATE `admin` SET
+            locked_until=IF(failed_attempts+1>=?,
+                CURRENT_TIMESTAMP + INTERVAL ? SECOND, locked_until),
+            failed_attempts=IF(failed_attempts+1>=?, 0, failed_attempts+1)
+          WHERE id=?",
+        (
+            LOGIN_MAX_FAILED_ATTEMPTS,
+            LOGIN_LOCKOUT.as_secs(),
+            LOGIN_MAX_FAILED_ATTEMPTS,
+            id,
+        ),
+    )?;
+    let row: Option<(u32, Option<i64>)> = tx.first_exec(
+        r"SELECT failed_attempts, TIMESTAMPDIFF(SECOND, CURRENT_TIMESTAMP, locked_until)
+          FROM `admin` WHERE id=?",
+        (id,),
+    )?;
+    tx.commit()?;
+    let (failed_attempts, locked_for) = row.unwrap_or((0, None));
+    Ok(match locked_for.filter(|secs| *secs > 0) {
+        Some(secs) => LoginError::Locked(Duration::from_secs(secs as u64)),
+        None => LoginError::WrongPassword {
+            attempts_left: LOGIN_MAX_FAILED_ATTEMPTS.saturating_sub(failed_attempts),
+        },
+    })
+}
+
+/// A call that fails mid-query panics with the lock held. The connection is
+/// still usable afterwards, or will be once `reconnect` has run, so the
+/// poisoned lock is taken over instead of failing every later call.
+fn lock_conn(conn: &SharedConn) -> ConnGuard<'_> {
+    ConnGuard {
+        lock: conn.conn.lock().unwrap_or_else(|e| e.into_inner()),
+        last_call: &conn.last_call,
+        started: Instant::now(),
+        failed: false,
+    }
+}
+
+/// `CREATE TABLE IF NOT EXISTS` leaves tables from older releases untouched,
+/// so columns added later are created here.
+fn add_column_if_missing(
+    conn: &mut mysql::Conn,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), mysql::Error> {
+    let count: Option<i64> = conn.first_exec(
+        r"SELECT COUNT(*) FROM information_schema.COLUMNS
+          WHERE TABLE_SCHEMA=DATABASE() AND TABLE_NAME=? AND COLUMN_NAME=?",
+        (table, column),
+    )?;
+    if count.unwrap_or(0) == 0 {
+        conn.query(format!(
+            "ALTER TABLE `{}` ADD COLUMN `{}` {}",
+            table, column, definition
+        ))?;
+    }
+    Ok(())
+}
+
+/// Adds a unique key on `column` to tables from releases that had none. Fails
+/// while the table holds duplicates, which must then be resolved by hand.
+fn add_unique_key_if_missing(
+    conn: &mut mysql::Conn,
+    table: &str,
+    column: &str,
+) -> Result<(), mysql::Error> {
+    let count: Option<i64> = conn.first_exec(
+        r"SELECT COUNT(*) FROM information_schema.STATISTICS
+          WHERE TABLE_SCHEMA=DATABASE() AND TABLE_NAME=? AND COLUMN_NAME=? AND NON_UNIQUE=0",
+        (table, column),
+    )?;
+    if count.unwrap_or(0) == 0 {
+        conn.query(format!(
+            "ALTER TABLE `{}` ADD UNIQUE KEY `{}` (`{}`)",
+            table, column, column
+        ))?;
+    }
+    Ok(())
+}
+
+/// Moves fees from the `category_fee` table of older releases onto the
+/// categories.
+fn fold_category_fees(conn: &mut mysql::Conn) -> Result<(), mysql::Error> {
+    let count: Option<i64> = conn.first_exec(
+        r"SELECT COUNT(*) FROM information_schema.TABLES
+          WHERE TABLE_SCHEMA=DATABASE() AND TABLE_NAME='category_fee'",
+        (),
+    )?;
+    if count.unwrap_or(0) > 0 {
+        conn.query(
+            r"UPDATE `category` JOIN `category_fee` ON category_fee.category=category.id
+              SET category.fee=category_fee.fee",
+        )?;
+        conn.query(r"DROP TABLE `category_fee`")?;
+    }
+    Ok(())
+}
+
+/// Rewrites phone numbers saved before they were normalised on write. Numbers
+/// that are not Indian mobiles are left as they were.
+fn normalise_phones(conn: &mut mysql::Conn) -> Result<(), mysql::Error> {
+    let rows: Vec<(i32, String)> = conn
+        .prep_exec(r"SELECT id, phone FROM participant WHERE phone NOT LIKE '+%'", ())?
+        .map(|x| x.map(mysql::from_row))
+        .collect::<Result<_, _>>()?;
+    for (id, phone) in rows {
+        if let Some(phone) = normalise_phone(&phone) {
+            conn.prep_exec(r"UPDATE participant SET phone=? WHERE id=?", (phone, id))?;
+        }
+    }
+    Ok(())
+}
+
+/// Every college with its aliases, ordered by id.
+fn college_list(conn: &mut mysql::Conn) -> Vec<College> {
+    let mut aliases = college_aliases(conn, None);
+    conn.prep_exec(
+        r"SELECT id, name, city, district, short_code FROM `college` ORDER BY id",
+        (),
+    )
+    .map(|result| {
+        result
+            .map(|x| x.unwrap())
+            .map(|row| {
+                let (id, name, city, district, short_code) = mysql::from_row(row);
+                College {
+                    id,
+                    name,
+                    city,
+                    district,
+                    short_code,
+                    aliases: aliases.remove(&id).unwrap_or_default(),
+                }
+            })
+            .collect()
+    })
+    .unwrap()
+}
+
+fn college_get(conn: &mut mysql::Conn, id: i32) -> Option<College> {
+    let row: Option<(String, String, String, String)> = conn
+        .first_exec(
+            r"SELECT name, city, district, short_code FROM `college` WHERE id=?",
+            (id,),
+        )
+        .unwrap();
+    let (name, city, district, short_code) = row?;
+    Some(College {
+        id,
+        name,
+        city,
+        district,
+        short_code,
+        aliases: college_aliases(conn, Some(id)).remove(&id).unwrap_or_default(),
+    })
+}
+
+/// Aliases by college id, of one college or of all.
+fn college_aliases(conn: &mut mysql::Conn, college_id: Option<i32>) -> HashMap<i32, Vec<String>> {
+    let mut aliases: HashMap<i32, Vec<String>> = HashMap::new();
+    conn.prep_exec(
+        r"SELECT college_id, alias FROM college_alias
+          WHERE ? IS NULL OR college_id=? ORDER BY college_id, alias",
+        (college_id, college_id),
+    )
+    .unwrap()
+    .map(|x| mysql::from_row::<(i32, String)>(x.unwrap()))
+    .for_each(|(id, alias)| aliases.entry(id).or_default().push(alias));
+    aliases
+}
+
+fn category_list(conn: &mut mysql::Conn) -> Vec<Category> {
+    conn.prep_exec(
+        r"SELECT id, name, prefix, fee, hospitality, events, active
+          FROM `category` ORDER BY id",
+        (),
+    )
+    .map(|result| {
+        result
+            .map(|x| x.unwrap())
+            .map(|row| {
+                let (id, name, prefix, fee, hospitality, events, active) = mysql::from_row(row);
+                Category {
+                    id,
+                    name,
+                    prefix,
+                    fee,
+                    hospitality,
+                    events,
+                    active,
+                }
+            })
+            .collect()
+    })
+    .unwrap()
+}
+
 fn gender_to_i32(gender: Gender) -> i32 {
     match gender {
         Gender::Male => 0,
@@ -280,16 +2312,48 @@ fn gender_from_i32(gender: i32) -> Gender {
     }
 }
 
-fn category_to_i32(cat: ParticipantCategory) -> i32 {
-    match cat {
-        ParticipantCategory::Ragam => 0,
-        ParticipantCategory::Kalotsavam => 1,
+fn institution_type_to_i32(institution_type: InstitutionType) -> i32 {
+    match institution_type {
+        InstitutionType::School => 0,
+        InstitutionType::HigherSecondary => 1,
+        InstitutionType::College => 2,
+    }
+}
+
+fn institution_type_from_i32(institution_type: i32) -> InstitutionType {
+    match institution_type {
+        0 => InstitutionType::School,
+        1 => InstitutionType::HigherSecondary,
+        _ => InstitutionType::College,
+    }
+}
+
+fn payment_mode_to_i32(mode: PaymentMode) -> i32 {
+    match mode {
+        PaymentMode::Cash => 0,
+        PaymentMode::Upi => 1,
+        PaymentMode::Card => 2,
+    }
+}
+
+fn payment_mode_from_i32(mode: i32) -> PaymentMode {
+    match mode {
+        1 => PaymentMode::Upi,
+        2 => PaymentMode::Card,
+        _ => PaymentMode::Cash,
+    }
+}
+
+fn payment_purpose_to_i32(purpose: PaymentPurpose) -> i32 {
+    match purpose {
+        PaymentPurpose::Registration => 0,
+        PaymentPurpose::Hospitality => 1,
     }
 }
 
-fn category_from_i32(cat: i32) -> ParticipantCategory {
-    match cat {
-        0 => ParticipantCategory::Ragam,
-        _ => ParticipantCategory::Kalotsavam,
+fn payment_purpose_from_i32(purpose: i32) -> PaymentPurpose {
+    match purpose {
+        1 => PaymentPurpose::Hospitality,
+        _ => PaymentPurpose::Registration,
     }
 }
@@ -1,6 +1,17 @@
 #![allow(dead_code)]
 
+pub mod duplicates;
+pub mod impl_in_mem;
+// Setup and migrations hand back the driver's own error, which is large but
+// only ever returned once, at startup.
+#[allow(clippy::result_large_err)]
 pub mod impl_mysql;
+pub mod undo;
+pub mod validation;
+
+use chrono::NaiveDate;
+use validation::InvalidField;
+use std::time::Duration;
 
 #[derive(Clone)]
 pub struct Admin {
@@ -8,18 +19,49 @@ pub struct Admin {
     pub name: String,
 }
 
+/// A login at a desk, recorded by the backend from login until logout.
+#[derive(Clone)]
+pub struct Session {
+    pub id: i32,
+    pub admin: Admin,
+    /// The desk the admin logged in at, as configured by `desk_id`.
+    pub desk: String,
+    /// Whether the admin was a supervisor at login. Only supervisors may
+    /// merge colleges.
+    pub supervisor: bool,
+}
+
 #[derive(Clone)]
 pub struct Participant {
     id: i32,
+    version: i32,
     pub info: ParticipantInfo,
     pub college: College,
     pub reg_status: Result<ParticipantRegVerified, ParticipantRegNotVerified>,
     pub hospitality: Option<HospitalityVerified>,
 }
 
-#[derive(Copy, Clone)]
-pub enum ParticipantCategory {
-    Ragam, Kalotsavam
+/// A kind of attendee, such as Ragam or Kalotsavam participants. Kept in the
+/// backend so that a new kind needs no code change.
+#[derive(Clone)]
+pub struct Category {
+    pub id: i32,
+    pub name: String,
+    /// Starts the participant code, e.g. `R` for Ragam.
+    pub prefix: String,
+    /// Registration fee in rupees.
+    pub fee: i64,
+    /// Whether participants may be allocated a room.
+    pub hospitality: bool,
+    /// Whether participants enrol in events and give the Kalotsavam details.
+    pub events: bool,
+    /// Inactive categories keep their participants but take no new ones.
+    pub active: bool,
+}
+
+pub enum CategoryError {
+    PrefixTaken,
+    UnknownCategory,
 }
 
 #[derive(Clone)]
@@ -37,6 +79,67 @@ pub struct HospitalityVerified {
     pub admin: Admin,
     pub hostel: String,
     pub room: String,
+    /// Planned stay; the night before `departure` is the last one.
+    pub arrival: NaiveDate,
+    pub departure: NaiveDate,
+    pub checked_in: Option<NaiveDate>,
+    pub checked_out: Option<NaiveDate>,
+    /// Charge per night, from the hostel's tariff when the room was allocated.
+    pub tariff: i64,
+    /// Hospitality payments so far.
+    pub paid: i64,
+}
+
+impl HospitalityVerified {
+    pub fn planned_nights(&self) -> i64 {
+        (self.departure - self.arrival).num_days().max(1)
+    }
+
+    /// `None` until checked out.
+    pub fn actual_nights(&self) -> Option<i64> {
+        match (self.checked_in, self.checked_out) {
+            (Some(checked_in), Some(checked_out)) => {
+                Some((checked_out - checked_in).num_days().max(1))
+            }
+            _ => None,
+        }
+    }
+
+    /// For the actual nights once checked out, the planned ones before.
+    pub fn charge(&self) -> i64 {
+        self.tariff * self.actual_nights().unwrap_or_else(|| self.planned_nights())
+    }
+
+    /// Negative when the guest left early and is owed a refund.
+    pub fn amount_due(&self) -> i64 {
+        self.charge() - self.paid
+    }
+}
+
+/// A participant's photo, for checking the person at the desk against the
+/// registration. Both are encoded images as read from or written to a file.
+#[derive(Clone)]
+pub struct Photo {
+    pub image: Vec<u8>,
+    /// Small copy shown on the desk screens.
+    pub thumbnail: Vec<u8>,
+}
+
+/// Largest photo the backend accepts, in bytes.
+pub const PHOTO_MAX_SIZE: usize = 2 * 1024 * 1024;
+
+pub enum PhotoError {
+    TooLarge,
+    UnknownParticipant,
+}
+
+pub enum HospitalityError {
+    NotAllocated,
+    AlreadyCheckedIn,
+    NotCheckedIn,
+    AlreadyCheckedOut,
+    /// The participant's category offers no rooms.
+    NoHospitality,
 }
 
 impl Participant {
@@ -45,16 +148,50 @@ impl Participant {
     }
 }
 
+/// Returned by `participant_update` when another desk has saved the participant
+/// since it was loaded. `current` holds the participant as it is now stored.
+pub struct UpdateConflict {
+    pub current: Participant,
+}
+
+pub enum UpdateError {
+    /// Boxed, as a whole participant dwarfs the other variant.
+    Conflict(Box<UpdateConflict>),
+    /// The edited details break the rules in `validation`.
+    Invalid(Vec<InvalidField>),
+    UnknownParticipant,
+}
+
 #[derive(Clone)]
 pub struct ParticipantInfo {
     pub name: String,
     pub gender: Gender,
     pub email: String,
+    /// Stored in E.164 form, see `validation::normalise_phone`.
     pub phone: String,
-    pub category: ParticipantCategory
+    pub category: Category,
+    /// Required when the category has events, `None` for everyone else.
+    pub kalotsavam: Option<KalotsavamDetails>,
 }
 
-#[derive(Copy, Clone)]
+/// What Kalotsavam asks of its participants on top of the common fields.
+#[derive(Clone)]
+pub struct KalotsavamDetails {
+    pub institution_type: InstitutionType,
+    /// Class for schools, year of study for colleges.
+    pub class: String,
+    pub district: String,
+    pub date_of_birth: NaiveDate,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum InstitutionType {
+    School,
+    HigherSecondary,
+    College,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Gender {
     Male,
     Female,
@@ -65,25 +202,527 @@ pub enum Gender {
 pub struct College {
     id: i32,
     pub name: String,
+    pub city: String,
+    pub district: String,
+    /// Initials operators search by, such as `GECT`. May be empty.
+    pub short_code: String,
+    /// Other names the college goes by, including those of colleges merged
+    /// into it.
+    pub aliases: Vec<String>,
 }
 
 impl College {
     pub fn id(&self) -> i32 {
         self.id
     }
+
+    /// The name followed by the city, as the desk shows a college so that
+    /// colleges of the same name in different cities can be told apart.
+    pub fn label(&self) -> String {
+        if self.city.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{}, {}", self.name, self.city)
+        }
+    }
+
+    /// Whether `key` fuzzily matches the name, short code, city, district or
+    /// an alias, ignoring case. An empty key matches every college.
+    pub fn matches(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        key.is_empty()
+            || [&self.name, &self.short_code, &self.city, &self.district]
+                .iter()
+                .cloned()
+                .chain(self.aliases.iter())
+                .any(|term| fuzzy_filter::matches(&key, &term.to_lowercase()))
+    }
+}
+
+/// A college as entered at the desk, before the backend gives it an id.
+pub struct NewCollege {
+    pub name: String,
+    pub city: String,
+    pub district: String,
+    pub short_code: String,
+}
+
+pub enum CollegeError {
+    UnknownCollege,
+}
+
+/// A college and how many participants name it, as listed for merging.
+#[derive(Clone)]
+pub struct CollegeUsage {
+    pub college: College,
+    pub participants: i64,
+}
+
+/// A college folded into another, as kept in the merge history.
+#[derive(Clone)]
+pub struct CollegeMerge {
+    /// Name of the college that no longer exists.
+    pub merged: String,
+    pub into: College,
+    /// Participants moved to `into`.
+    pub participants: i64,
+    pub admin: Admin,
+    pub desk: String,
+    /// Local time of the merge, as `YYYY-MM-DD HH:MM`.
+    pub merged_at: String,
+}
+
+pub enum MergeError {
+    UnknownCollege,
+    /// The college to keep is also among those to merge.
+    MergeIntoSelf,
+    /// The admin is not a supervisor.
+    NotSupervisor,
+}
+
+/// Fees are in whole rupees.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PaymentMode {
+    Cash,
+    Upi,
+    Card,
+}
+
+/// A payment as entered at the desk, before the backend issues a receipt.
+#[derive(Clone)]
+pub struct PaymentInfo {
+    pub amount: i64,
+    pub mode: PaymentMode,
+    /// UPI transaction id or card slip number. Empty for cash.
+    pub reference: String,
+}
+
+/// What a payment is for. Both count towards the cash handed over at the
+/// end of a shift.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PaymentPurpose {
+    Registration,
+    Hospitality,
+}
+
+#[derive(Clone)]
+pub struct Payment {
+    pub id: i32,
+    pub participant_id: i32,
+    pub purpose: PaymentPurpose,
+    pub info: PaymentInfo,
+    pub receipt_no: String,
+    pub admin: Admin,
+    pub desk: String,
+}
+
+pub enum PaymentError {
+    /// The amount is not positive.
+    InvalidAmount,
+    /// UPI and card payments need a reference number.
+    ReferenceRequired,
+    /// More than the amount due. Holds the amount due.
+    Overpaid(i64),
+    UnknownParticipant,
+}
+
+impl PaymentInfo {
+    pub fn check(&self, due: i64) -> Result<(), PaymentError> {
+        if self.amount <= 0 {
+            Err(PaymentError::InvalidAmount)
+        } else if self.mode != PaymentMode::Cash && self.reference.trim().is_empty() {
+            Err(PaymentError::ReferenceRequired)
+        } else if self.amount > due {
+            Err(PaymentError::Overpaid(due))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Receipt numbers are unique across desks: the desk id followed by the
+/// payment id.
+pub fn receipt_no(desk: &str, payment_id: i32) -> String {
+    format!("{}/{:06}", desk, payment_id)
+}
+
+/// What one admin did at one desk during a session, for handing over the
+/// cash at the end of a shift.
+#[derive(Clone)]
+pub struct ShiftSummary {
+    pub session: Session,
+    /// Local time the session started, as `YYYY-MM-DD HH:MM`.
+    pub started_at: String,
+    pub payments: Vec<Payment>,
+    pub verifications: i64,
+    pub hospitality_allocations: i64,
+    /// Cash counted at the desk, once recorded.
+    pub counted_cash: Option<i64>,
+}
+
+impl ShiftSummary {
+    pub fn expected(&self, mode: PaymentMode) -> i64 {
+        self.payments
+            .iter()
+            .filter(|p| p.info.mode == mode)
+            .map(|p| p.info.amount)
+            .sum()
+    }
+
+    /// Counted less expected cash; negative when cash is short.
+    pub fn discrepancy(&self) -> Option<i64> {
+        self.counted_cash
+            .map(|counted| counted - self.expected(PaymentMode::Cash))
+    }
+}
+
+/// Something a desk did to a participant.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum DeskAction {
+    Created,
+    Verified,
+    /// Allocated a room.
+    Allocated,
+}
+
+/// A participant recently handled at a desk, listed on the home screens so
+/// that they can be reopened without retyping the ID.
+#[derive(Clone)]
+pub struct DeskActivity {
+    pub participant: Participant,
+    pub action: DeskAction,
+    /// Local time, as `YYYY-MM-DD HH:MM`.
+    pub at: String,
+}
+
+/// How long after a verification, update or room allocation it can be undone.
+pub const UNDO_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum UndoableAction {
+    Verify,
+    /// An update of the participant's details.
+    Update,
+    Allocate,
+}
+
+/// The last action of a session, while `undo_last` can still reverse it.
+#[derive(Clone)]
+pub struct LastAction {
+    pub action: UndoableAction,
+    /// The participant as the action left them.
+    pub participant: Participant,
+    /// Time left to undo.
+    pub remaining: Duration,
+}
+
+pub enum UndoError {
+    /// Nothing was done in this session, or it is too late to undo.
+    NothingToUndo,
+    /// The participant has changed since, here or at another desk. Holds the
+    /// participant as they are now.
+    Changed(Box<Participant>),
+}
+
+pub enum VerifyError {
+    /// The registration fee is not fully paid. Holds the amount due.
+    AmountDue(i64),
+}
+
+/// A Kalotsavam competition participants enrol in.
+#[derive(Clone)]
+pub struct Event {
+    pub id: i32,
+    pub name: String,
+    /// `None` if any number may take part.
+    pub max_participants: Option<i32>,
+    /// Smallest and largest team, for group events entered as a team.
+    pub team_size: Option<(i32, i32)>,
+    pub enrolled: i64,
+}
+
+impl Event {
+    pub fn is_full(&self) -> bool {
+        match self.max_participants {
+            Some(max) => self.enrolled >= i64::from(max),
+            None => false,
+        }
+    }
+}
+
+pub enum EnrolError {
+    EventFull,
+    UnknownEvent,
+    /// The participant's category does not enter events, or there is no
+    /// such participant.
+    NoEvents,
+}
+
+/// A group of participants entering a group event together. The leader is
+/// one of the members.
+#[derive(Clone)]
+pub struct Team {
+    pub id: i32,
+    pub name: String,
+    pub event: Event,
+    pub college: College,
+    pub leader: i32,
+    pub members: Vec<i32>,
+}
+
+pub enum TeamError {
+    NotAGroupEvent,
+    /// The team would end up with fewer than `min` or more than `max` members.
+    TeamSize { min: i32, max: i32 },
+    EventFull,
+    UnknownEvent,
+    UnknownTeam,
+    UnknownParticipant(i32),
+    /// The participant is already in another team for the same event.
+    AlreadyInTeam(i32),
+    /// The leader can only leave by disbanding the team.
+    RemoveLeader,
+    /// The participant still owes part of the registration fee.
+    AmountDue(i32),
+    /// The participant's category does not enter events.
+    NoEvents(i32),
+}
+
+/// Checks a team of `members` against the event's team size limits.
+pub fn check_team_size(event: &Event, members: usize) -> Result<(), TeamError> {
+    let (min, max) = event.team_size.ok_or(TeamError::NotAGroupEvent)?;
+    if (members as i32) < min || (members as i32) > max {
+        Err(TeamError::TeamSize { min, max })
+    } else {
+        Ok(())
+    }
+}
+
+/// Aggregate counts for the dashboard. All counts except hospitality are of
+/// verified registrations.
+#[derive(Clone)]
+pub struct Statistics {
+    pub total: i64,
+    /// Category names.
+    pub by_category: Vec<(String, i64)>,
+    pub by_gender: Vec<(Gender, i64)>,
+    pub by_college: Vec<(String, i64)>,
+    pub by_admin: Vec<(String, i64)>,
+    pub by_desk: Vec<(String, i64)>,
+    pub by_hour: Vec<(u32, i64)>,
+    pub hospitality: Vec<HostelOccupancy>,
+}
+
+#[derive(Clone)]
+pub struct HostelOccupancy {
+    pub hostel: String,
+    pub participants: i64,
+    pub rooms: i64,
+}
+
+/// An admin as seen by the account management commands.
+#[derive(Clone)]
+pub struct AdminAccount {
+    pub admin: Admin,
+    pub username: String,
+    pub disabled: bool,
+    pub supervisor: bool,
+}
+
+pub enum AdminError {
+    UsernameTaken,
+    UnknownUser,
+}
+
+/// Failed logins in a row after which an account is locked.
+pub const LOGIN_MAX_FAILED_ATTEMPTS: u32 = 5;
+/// How long an account stays locked after too many failed logins.
+pub const LOGIN_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+pub enum LoginError {
+    UnknownUser,
+    WrongPassword { attempts_left: u32 },
+    Disabled,
+    /// Too many failed attempts. Holds the time until the lock expires.
+    Locked(Duration),
+    Unreachable,
+}
+
+/// How the last call to the backend went.
+#[derive(Clone, Copy)]
+pub enum LastCall {
+    /// Answered in the time held.
+    Done(Duration),
+    /// Failed, as on a lost connection.
+    Failed,
 }
 
 pub trait ILogin: Send + Sync {
-    fn login_reg_desk(&self, username: &str, password: &str) -> Result<Box<dyn IRegDesk>, ()>;
+    fn login_reg_desk(
+        &self,
+        desk: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<Box<dyn IRegDesk>, LoginError>;
+    /// The last call made by this or any logged in session. `None` before
+    /// the first.
+    fn last_call(&self) -> Option<LastCall>;
+    /// Checks that the backend still answers. Timed like any other call.
+    fn ping(&self) -> bool;
+    /// Re-establishes the backend connection. Logged in sessions share it and
+    /// stay valid.
+    fn reconnect(&self) -> bool;
+    /// Checks the password of the admin who owns an open session, to unlock
+    /// a desk that locked itself while idle. Wrong passwords count towards
+    /// the same lock as failed logins. `UnknownUser` once the session has
+    /// ended.
+    fn session_unlock(&self, session_id: i32, password: &str) -> Result<(), LoginError>;
+}
+
+pub trait IAdmins {
+    fn admin_add(&self, name: &str, username: &str, password: &str) -> Result<Admin, AdminError>;
+    fn admin_set_password(&self, username: &str, password: &str) -> Result<(), AdminError>;
+    /// Enabling an account also clears a lock from failed logins.
+    fn admin_set_disabled(&self, username: &str, disabled: bool) -> Result<(), AdminError>;
+    /// Supervisors may merge colleges. Takes effect at the admin's next login.
+    fn admin_set_supervisor(&self, username: &str, supervisor: bool) -> Result<(), AdminError>;
+    fn admin_list(&self) -> Vec<AdminAccount>;
+    fn event_add(
+        &self,
+        name: &str,
+        max_participants: Option<i32>,
+        team_size: Option<(i32, i32)>,
+    ) -> Event;
+    fn category_list(&self) -> Vec<Category>;
+    fn category_add(
+        &self,
+        name: &str,
+        prefix: &str,
+        fee: i64,
+        hospitality: bool,
+        events: bool,
+    ) -> Result<Category, CategoryError>;
+    /// Fee due from every participant of the category with this prefix.
+    fn category_set_fee(&self, prefix: &str, fee: i64) -> Result<(), CategoryError>;
+    fn category_set_active(&self, prefix: &str, active: bool) -> Result<(), CategoryError>;
+    /// Charge per night for rooms allocated from now on. Hostels without a
+    /// tariff are free.
+    fn hostel_set_tariff(&self, hostel: &str, tariff: i64);
+    fn college_list(&self) -> Vec<College>;
+    fn college_set_details(
+        &self,
+        college_id: i32,
+        short_code: &str,
+        district: &str,
+        city: &str,
+    ) -> Result<(), CollegeError>;
+    /// Adding an alias the college already has is not an error.
+    fn college_add_alias(&self, college_id: i32, alias: &str) -> Result<(), CollegeError>;
 }
 
 pub trait IRegDesk: Send + Sync {
-    fn participant_new_verified(&mut self, info: ParticipantInfo, college: College) -> Participant;
+    fn session(&self) -> &Session;
+    fn logout(&mut self);
+    /// Active and inactive categories, in the order they were added.
+    fn category_list(&self) -> Vec<Category>;
+    /// The participant is created unverified; the fee is collected and the
+    /// registration verified on the verification screen. Refused when the
+    /// details break the rules in `validation` or the category is inactive.
+    fn participant_new(&mut self, info: ParticipantInfo, college: College)
+        -> Result<Participant, Vec<InvalidField>>;
     fn participant_get(&self, id: i32) -> Option<Participant>;
-    fn participant_update(&mut self, participant: &Participant);
-    fn participant_verify_reg(&mut self, p: ParticipantRegNotVerified) -> Participant;
-    fn participant_update_hospi(&mut self, p: Participant, hostel: &str, room: &str)
-        -> Participant;
-    fn college_get_filtered(&self, name: &str) -> Vec<College>;
-    fn college_add(&mut self, name: String) -> College;
+    /// Matches however the number is written. Oldest registration first.
+    fn participant_find_by_phone(&self, phone: &str) -> Vec<Participant>;
+    fn participant_update(&mut self, participant: &Participant)
+        -> Result<Participant, UpdateError>;
+    /// Refused while part of the registration fee is due. When another desk
+    /// verified the participant first, returns that verification.
+    fn participant_verify_reg(&mut self, p: ParticipantRegNotVerified)
+        -> Result<Participant, VerifyError>;
+    /// The category fee less what has been paid towards it.
+    fn participant_amount_due(&self, participant_id: i32) -> i64;
+    fn participant_payments(&self, participant_id: i32) -> Vec<Payment>;
+    /// Replaces any earlier photo.
+    fn participant_set_photo(&mut self, participant_id: i32, photo: Photo)
+        -> Result<(), PhotoError>;
+    /// `None` if no photo was attached.
+    fn participant_thumbnail(&self, participant_id: i32) -> Option<Vec<u8>>;
+    /// Records a payment by the logged in admin and issues its receipt number.
+    fn payment_add(&mut self, participant_id: i32, info: PaymentInfo)
+        -> Result<Payment, PaymentError>;
+    /// The last verification, update or room allocation made in this session,
+    /// while it can be undone. Creating a participant or verifying a team
+    /// leaves nothing to undo.
+    fn last_action(&self) -> Option<LastAction>;
+    /// Reverses the last action and returns the participant as they were
+    /// before it. An undone update restores the details; the photo and events
+    /// are left as they are.
+    fn undo_last(&mut self) -> Result<Participant, UndoError>;
+    /// The last `limit` participants created, verified or allocated a room at
+    /// this desk, by any admin. Newest first, each listed once for the latest
+    /// action.
+    fn desk_recent_activity(&self, limit: usize) -> Vec<DeskActivity>;
+    /// Summary of the current session.
+    fn shift_summary(&self) -> ShiftSummary;
+    /// Records the cash counted at the end of the current session. Recording
+    /// again replaces the earlier count.
+    fn shift_record_cash(&mut self, counted_cash: i64) -> ShiftSummary;
+    /// Allocates a room, or changes the allocation and planned stay. The
+    /// hostel's current tariff applies. Refused when the participant's
+    /// category has no hospitality.
+    fn participant_update_hospi(
+        &mut self,
+        p: Participant,
+        hostel: &str,
+        room: &str,
+        arrival: NaiveDate,
+        departure: NaiveDate,
+    ) -> Result<Participant, HospitalityError>;
+    /// Checks in today.
+    fn hospitality_check_in(&mut self, participant_id: i32)
+        -> Result<Participant, HospitalityError>;
+    /// Checks out today. The charge is then for the actual nights; any
+    /// balance is settled with `hospitality_collect`.
+    fn hospitality_check_out(&mut self, participant_id: i32)
+        -> Result<Participant, HospitalityError>;
+    /// Records a payment towards the stay, at check-in or checkout.
+    fn hospitality_collect(&mut self, participant_id: i32, info: PaymentInfo)
+        -> Result<Payment, PaymentError>;
+    /// Colleges that `College::matches` the key, ordered by id.
+    fn college_get_filtered(&self, key: &str) -> Vec<College>;
+    fn college_add(&mut self, college: NewCollege) -> College;
+    /// Groups of colleges that are probably the same college entered
+    /// differently, see `duplicates::group_duplicates`.
+    fn college_duplicates(&self) -> Vec<Vec<CollegeUsage>>;
+    /// Moves the participants and teams of each `merged` college to `into`,
+    /// then deletes the merged colleges and records them in the history. The
+    /// merged names and aliases become aliases of `into`. Only supervisors
+    /// may merge.
+    fn college_merge(&mut self, into: i32, merged: &[i32])
+        -> Result<Vec<CollegeMerge>, MergeError>;
+    /// Most recent first.
+    fn college_merge_history(&self) -> Vec<CollegeMerge>;
+    fn statistics(&self) -> Statistics;
+    fn event_list(&self) -> Vec<Event>;
+    /// Events the participant is enrolled in.
+    fn participant_events(&self, participant_id: i32) -> Vec<Event>;
+    /// Enrolling twice in the same event is not an error.
+    fn participant_enrol(&mut self, participant_id: i32, event_id: i32) -> Result<(), EnrolError>;
+    fn participant_withdraw(&mut self, participant_id: i32, event_id: i32);
+    /// Creates the team and enrols every member, leader included, in its event.
+    /// The team belongs to the leader's college.
+    fn team_create(
+        &mut self,
+        name: &str,
+        event_id: i32,
+        leader: i32,
+        members: &[i32],
+    ) -> Result<Team, TeamError>;
+    fn team_get(&self, id: i32) -> Option<Team>;
+    fn team_add_member(&mut self, team_id: i32, participant_id: i32) -> Result<Team, TeamError>;
+    fn team_remove_member(&mut self, team_id: i32, participant_id: i32)
+        -> Result<Team, TeamError>;
+    /// Verifies the registration of every member not yet verified. Nobody is
+    /// verified if any member still owes part of the fee.
+    fn team_verify(&mut self, team_id: i32) -> Result<Team, TeamError>;
 }
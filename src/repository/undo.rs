@@ -0,0 +1,57 @@
+use super::{LastAction, Participant, UndoableAction, UNDO_WINDOW};
+use std::time::Instant;
+
+/// What a backend remembers of the last action of a session to reverse it.
+pub struct UndoRecord {
+    pub action: UndoableAction,
+    pub before: Participant,
+    pub after: Participant,
+    /// Desk and session of the allocation that an `Allocate` changed, which
+    /// the participant does not hold. `None` for a first allocation.
+    pub allocated_by: Option<(String, Option<i32>)>,
+    done_at: Instant,
+}
+
+impl UndoRecord {
+    pub fn new(action: UndoableAction, before: Participant, after: Participant) -> Self {
+        UndoRecord {
+            action,
+            before,
+            after,
+            allocated_by: None,
+            done_at: Instant::now(),
+        }
+    }
+
+    /// `None` once `UNDO_WINDOW` has passed.
+    pub fn last_action(&self) -> Option<LastAction> {
+        let remaining = UNDO_WINDOW.checked_sub(self.done_at.elapsed())?;
+        Some(LastAction {
+            action: self.action,
+            participant: self.after.clone(),
+            remaining,
+        })
+    }
+
+    /// Whether `current` is still as the action left it. Undoing on top of a
+    /// later change, here or at another desk, would lose that change.
+    pub fn unchanged(&self, current: &Participant) -> bool {
+        let verified_by = |p: &Participant| p.reg_status.as_ref().ok().map(|v| v.admin.id);
+        let stay = |p: &Participant| {
+            p.hospitality.as_ref().map(|h| {
+                (
+                    h.hostel.clone(),
+                    h.room.clone(),
+                    h.arrival,
+                    h.departure,
+                    h.checked_in,
+                    h.checked_out,
+                    h.paid,
+                )
+            })
+        };
+        current.version == self.after.version
+            && verified_by(current) == verified_by(&self.after)
+            && stay(current) == stay(&self.after)
+    }
+}
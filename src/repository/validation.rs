@@ -0,0 +1,291 @@
+use super::{KalotsavamDetails, ParticipantInfo};
+use chrono::{Local, NaiveDate};
+
+/// Longest name the backend stores, in characters.
+pub const NAME_MAX_LEN: usize = 100;
+
+/// A participant field that breaks one of the rules below. The form shows it
+/// next to the entry, and the backend refuses to save the participant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InvalidField {
+    NameEmpty,
+    NameTooLong,
+    Email,
+    Phone,
+    /// No college was picked, or the backend does not know it.
+    UnknownCollege,
+    /// The category has events, which need the Kalotsavam details.
+    KalotsavamDetailsMissing,
+    ClassEmpty,
+    DistrictEmpty,
+    /// The date of birth is not in the past.
+    DateOfBirth,
+    /// The category takes no new participants. Only the backend checks this.
+    InactiveCategory,
+}
+
+/// Checks the details entered for a participant. `college_exists` tells
+/// whether the chosen college is one the backend knows.
+pub fn check_participant(info: &ParticipantInfo, college_exists: bool) -> Result<(), Vec<InvalidField>> {
+    let mut errors = Vec::new();
+    if let Err(err) = check_name(&info.name) {
+        errors.push(err);
+    }
+    if !is_valid_email(&info.email) {
+        errors.push(InvalidField::Email);
+    }
+    if normalise_phone(&info.phone).is_none() {
+        errors.push(InvalidField::Phone);
+    }
+    if !college_exists {
+        errors.push(InvalidField::UnknownCollege);
+    }
+    match info.kalotsavam {
+        Some(ref details) => {
+            errors.extend(check_kalotsavam_details(details, Local::today().naive_local()))
+        }
+        None if info.category.events => errors.push(InvalidField::KalotsavamDetailsMissing),
+        None => {}
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the Kalotsavam details as of `today`.
+pub fn check_kalotsavam_details(
+    details: &KalotsavamDetails,
+    today: NaiveDate,
+) -> Vec<InvalidField> {
+    let mut errors = Vec::new();
+    if details.class.trim().is_empty() {
+        errors.push(InvalidField::ClassEmpty);
+    }
+    if details.district.trim().is_empty() {
+        errors.push(InvalidField::DistrictEmpty);
+    }
+    if details.date_of_birth >= today {
+        errors.push(InvalidField::DateOfBirth);
+    }
+    errors
+}
+
+pub fn check_name(name: &str) -> Result<(), InvalidField> {
+    let name = name.trim();
+    if name.is_empty() {
+        Err(InvalidField::NameEmpty)
+    } else if name.chars().count() > NAME_MAX_LEN {
+        Err(InvalidField::NameTooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// Email is optional; when given it must look like `local@domain.tld`.
+pub fn is_valid_email(email: &str) -> bool {
+    let email = email.trim();
+    if email.is_empty() {
+        return true;
+    }
+    let at = match email.find('@') {
+        Some(at) => at,
+        None => return false,
+    };
+    let (local, domain) = (&email[..at], &email[at + 1..]);
+    let labels: Vec<&str> = domain.split('.').collect();
+    !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Rewrites an Indian mobile number in E.164 form, e.g. `+919876543210`.
+/// Accepts ten digits starting with 6 to 9, optionally after `+91`, `91` or
+/// `0`, and ignores spaces and hyphens between digits. `None` for anything
+/// else.
+pub fn normalise_phone(phone: &str) -> Option<String> {
+    let phone = phone.trim();
+    let (plus, rest) = match phone.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, phone),
+    };
+    if !rest.chars().all(|c| c.is_ascii_digit() || c == ' ' || c == '-') {
+        return None;
+    }
+    let digits: String = rest.chars().filter(char::is_ascii_digit).collect();
+    let number = match (plus, digits.len()) {
+        (_, 12) if digits.starts_with("91") => &digits[2..],
+        (false, 11) if digits.starts_with('0') => &digits[1..],
+        (false, 10) => &digits[..],
+        _ => return None,
+    };
+    if number.starts_with(|c| ('6'..='9').contains(&c)) {
+        Some(format!("+91{}", number))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::{Category, Gender, InstitutionType};
+
+    fn category(events: bool) -> Category {
+        Category {
+            id: 1,
+            name: String::from("Kalotsavam"),
+            prefix: String::from("K"),
+            fee: 100,
+            hospitality: false,
+            events,
+            active: true,
+        }
+    }
+
+    fn details(date_of_birth: NaiveDate) -> KalotsavamDetails {
+        KalotsavamDetails {
+            institution_type: InstitutionType::School,
+            class: String::from("10"),
+            district: String::from("Thrissur"),
+            date_of_birth,
+        }
+    }
+
+    fn info() -> ParticipantInfo {
+        ParticipantInfo {
+            name: String::from("Anjali Menon"),
+            gender: Gender::Female,
+            email: String::from("anjali@example.com"),
+            phone: String::from("9876543210"),
+            category: category(false),
+            kalotsavam: None,
+        }
+    }
+
+    #[test]
+    fn email() {
+        assert!(is_valid_email("anjali@example.com"));
+        assert!(is_valid_email(" anjali.menon@mail.example.co.in "));
+        // Email is optional.
+        assert!(is_valid_email(""));
+        assert!(is_valid_email("   "));
+
+        assert!(!is_valid_email("anjali"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("anjali@example"));
+        assert!(!is_valid_email("anjali@example..com"));
+        assert!(!is_valid_email("anjali@-example.com"));
+        assert!(!is_valid_email("anjali menon@example.com"));
+        assert!(!is_valid_email("anjali@exa_mple.com"));
+    }
+
+    #[test]
+    fn phone_forms() {
+        let expected = Some(String::from("+919876543210"));
+        assert_eq!(normalise_phone("+91 98765 43210"), expected);
+        assert_eq!(normalise_phone("09876543210"), expected);
+        assert_eq!(normalise_phone("9876543210"), expected);
+        assert_eq!(normalise_phone("919876543210"), expected);
+        assert_eq!(normalise_phone("+919876543210"), expected);
+        assert_eq!(normalise_phone(" 98765-43210 "), expected);
+    }
+
+    #[test]
+    fn phone_not_normalised() {
+        assert_eq!(normalise_phone(""), None);
+        // Too short, too long.
+        assert_eq!(normalise_phone("987654321"), None);
+        assert_eq!(normalise_phone("98765432101"), None);
+        // Not a mobile number.
+        assert_eq!(normalise_phone("5876543210"), None);
+        assert_eq!(normalise_phone("04872345678"), None);
+        // Other country codes, and a trunk prefix after `+`.
+        assert_eq!(normalise_phone("+19876543210"), None);
+        assert_eq!(normalise_phone("+09876543210"), None);
+        assert_eq!(normalise_phone("98765 4321O"), None);
+        assert_eq!(normalise_phone("(987) 654-3210"), None);
+    }
+
+    #[test]
+    fn participant_valid() {
+        assert_eq!(check_participant(&info(), true), Ok(()));
+    }
+
+    #[test]
+    fn participant_errors_in_field_order() {
+        let info = ParticipantInfo {
+            name: String::from("  "),
+            email: String::from("anjali"),
+            phone: String::from("12345"),
+            ..info()
+        };
+        assert_eq!(
+            check_participant(&info, false),
+            Err(vec![
+                InvalidField::NameEmpty,
+                InvalidField::Email,
+                InvalidField::Phone,
+                InvalidField::UnknownCollege,
+            ])
+        );
+    }
+
+    #[test]
+    fn participant_name_too_long() {
+        let info = ParticipantInfo {
+            name: "a".repeat(NAME_MAX_LEN + 1),
+            ..info()
+        };
+        assert_eq!(
+            check_participant(&info, true),
+            Err(vec![InvalidField::NameTooLong])
+        );
+    }
+
+    #[test]
+    fn participant_kalotsavam_details() {
+        let missing = ParticipantInfo {
+            category: category(true),
+            ..info()
+        };
+        assert_eq!(
+            check_participant(&missing, true),
+            Err(vec![InvalidField::KalotsavamDetailsMissing])
+        );
+
+        let given = ParticipantInfo {
+            kalotsavam: Some(details(NaiveDate::from_ymd(2005, 6, 1))),
+            ..missing
+        };
+        assert_eq!(check_participant(&given, true), Ok(()));
+    }
+
+    #[test]
+    fn kalotsavam_details() {
+        let today = NaiveDate::from_ymd(2019, 1, 10);
+        let valid = details(NaiveDate::from_ymd(2005, 6, 1));
+        assert!(check_kalotsavam_details(&valid, today).is_empty());
+
+        let blank = KalotsavamDetails {
+            class: String::from(" "),
+            district: String::new(),
+            ..details(today)
+        };
+        assert_eq!(
+            check_kalotsavam_details(&blank, today),
+            vec![
+                InvalidField::ClassEmpty,
+                InvalidField::DistrictEmpty,
+                InvalidField::DateOfBirth,
+            ]
+        );
+    }
+}
@@ -1,6 +1,15 @@
+use super::event_picker::{self, EventPicker};
+use super::fuzzy;
 use super::main_view::View;
+use super::participant_code;
+use super::photo::{self, show_thumbnail};
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::verify_reg::gender_to_str;
+use super::{run_with_desk, CONNECTION_LOST};
+use crate::repository::validation::{check_participant, InvalidField};
 use crate::repository::*;
-use fuzzy_filter;
+use chrono::NaiveDate;
 use gdk;
 use gtk::prelude::*;
 use std::cell::{Cell, RefCell};
@@ -16,11 +25,16 @@ pub struct CreateUpdateParticipant {
     reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
 
     participant: Cell<Option<Participant>>,
-    mode: Cell<Option<Mode>>,
-}
-
-pub enum Message {
-    Back(Option<Participant>, Box<dyn IRegDesk>),
+    /// The college last picked, while the college entry still shows it. It
+    /// need not be in the loaded list.
+    college: RefCell<Option<College>>,
+    /// Picked on the form and not yet saved.
+    photo: RefCell<Option<Photo>>,
+    /// All events and the current enrolments, from the last conflict.
+    conflict_events: RefCell<Option<(Vec<Event>, Vec<Event>)>>,
+    mode: RefCell<Option<Mode>>,
+    event_picker: EventPicker,
+    edition: u16,
 }
 
 struct CollegeList {
@@ -28,6 +42,21 @@ struct CollegeList {
     colleges: Vec<College>,
 }
 
+/// A college as listed in the popover, with the best match of the search key
+/// against its name, city or one of its other names.
+struct RankedCollege<'a> {
+    college: &'a College,
+    matched: MatchedField<'a>,
+    m: fuzzy::Match,
+}
+
+enum MatchedField<'a> {
+    Name,
+    City,
+    /// The short code, district or an alias.
+    Other(&'a str),
+}
+
 ui_struct! {
     struct CreateUpdateParticipantUI {
         root: gtk::Widget,
@@ -46,40 +75,89 @@ ui_struct! {
         college_search: gtk::SearchEntry,
         college_list: gtk::ListBox,
         new_college_entry: gtk::Entry,
+        new_college_city: gtk::Entry,
+        new_college_district: gtk::Entry,
+        new_college_code: gtk::Entry,
         new_college: gtk::Button,
 
         email: gtk::Entry,
         phone: gtk::Entry,
+        name_error: gtk::Label,
+        email_error: gtk::Label,
+        college_error: gtk::Label,
+        phone_error: gtk::Label,
+
+        photo: gtk::Image,
+        choose_photo: gtk::Button,
+        photo_error: gtk::Label,
+
+        kalotsavam_box: gtk::Box,
+        institution_type: gtk::ComboBoxText,
+        class: gtk::Entry,
+        district: gtk::Entry,
+        date_of_birth: gtk::Entry,
+        details_error: gtk::Label,
+
+        events_box: gtk::Box,
+        events: gtk::ListBox,
+        events_error: gtk::Label,
+
+        conflict: gtk::Box,
+        conflict_name: gtk::Label,
+        conflict_gender: gtk::Label,
+        conflict_email: gtk::Label,
+        conflict_college: gtk::Label,
+        conflict_phone: gtk::Label,
+        conflict_details_title: gtk::Label,
+        conflict_details: gtk::Label,
+        conflict_events_title: gtk::Label,
+        conflict_events: gtk::Label,
+        conflict_use_name: gtk::Button,
+        conflict_use_gender: gtk::Button,
+        conflict_use_email: gtk::Button,
+        conflict_use_college: gtk::Button,
+        conflict_use_phone: gtk::Button,
+        conflict_use_details: gtk::Button,
+        conflict_use_events: gtk::Button,
 
         back: gtk::Button,
         save: gtk::Button,
+        verify: gtk::Button,
 
-        saved_successfully: gtk::Label
+        saved_successfully: gtk::Label,
+        save_error: gtk::Label
     }
 }
 
-#[derive(Copy, Clone)]
+/// Both hold the category of the participant on the form.
+#[derive(Clone)]
 enum Mode {
-    CreateRagam,
-    CreateKalotsavam,
-    Update,
+    Create(Category),
+    Update(Category),
 }
 
 impl CreateUpdateParticipant {
-    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
         let glade_src = include_str!(concat!(
             env!("CARGO_MANIFEST_DIR"),
             "/src/ui/create_update_participant.glade"
         ));
         let builder = gtk::Builder::new_from_string(glade_src);
 
+        let ui = CreateUpdateParticipantUI::build(builder);
+        let event_picker = EventPicker::new(ui.events.clone());
         let ret = Rc::from(Self {
-            ui: CreateUpdateParticipantUI::build(builder),
+            ui,
             college_list: RefCell::from(None),
             callback,
             reg_desk: Cell::from(None),
             participant: Cell::from(None),
-            mode: Cell::from(None),
+            college: RefCell::from(None),
+            photo: RefCell::from(None),
+            conflict_events: RefCell::from(None),
+            mode: RefCell::from(None),
+            event_picker,
+            edition,
         });
 
         ret.college_list
@@ -90,36 +168,28 @@ impl CreateUpdateParticipant {
         ret
     }
 
-    pub fn set_mode_create_ragam(&self, reg_desk: Box<dyn IRegDesk>) {
+    pub fn set_mode_create(&self, category: Category, reg_desk: Box<dyn IRegDesk>) {
         self.reg_desk.set(Some(reg_desk));
-        self.mode.set(Some(Mode::CreateRagam));
+        self.ui.events_box.set_visible(category.events);
+        self.ui.kalotsavam_box.set_visible(category.events);
+        self.mode.replace(Some(Mode::Create(category)));
 
         self.state_default_create();
-        self.load_colleges();
-
-        self.ui.name.set_text("");
-        self.ui.email.set_text("");
-        self.ui.college.set_text("");
-        self.ui.phone.set_text("");
-        self.ui.new_college_entry.set_text("");
-        self.ui.male.set_active(true);
-
-        self.participant.set(None);
-    }
-
-    pub fn set_mode_create_kalotsavam(&self, reg_desk: Box<dyn IRegDesk>) {
-        self.reg_desk.set(Some(reg_desk));
-        self.mode.set(Some(Mode::CreateKalotsavam));
-
-        self.state_default_create();
-        self.load_colleges();
+        self.load_colleges(None);
 
         self.ui.name.set_text("");
         self.ui.email.set_text("");
         self.ui.college.set_text("");
+        self.college.replace(None);
         self.ui.phone.set_text("");
         self.ui.new_college_entry.set_text("");
+        self.ui.new_college_city.set_text("");
+        self.ui.new_college_district.set_text("");
+        self.ui.new_college_code.set_text("");
         self.ui.male.set_active(true);
+        self.load_kalotsavam_details(None);
+        self.show_invalid_fields(&[]);
+        self.reset_photo();
 
         self.participant.set(None);
     }
@@ -128,15 +198,25 @@ impl CreateUpdateParticipant {
         self.state_default_update();
 
         self.reg_desk.set(Some(reg_desk));
-        self.mode.set(Some(Mode::Update));
-        self.load_colleges();
+        self.mode
+            .replace(Some(Mode::Update(participant.info.category.clone())));
+        self.ui.events_box.set_visible(participant.info.category.events);
+        self.ui
+            .kalotsavam_box
+            .set_visible(participant.info.category.events);
+        self.show_invalid_fields(&[]);
+        self.reset_photo();
+        self.load_colleges(Some(participant.id()));
 
         self.load_participant(&participant);
         self.participant.set(Some(participant));
     }
 
-    fn load_colleges(&self) {
+    /// Also loads the events, with the enrolments of `participant_id` ticked.
+    fn load_colleges(&self, participant_id: Option<i32>) {
         self.state_action_pending();
+        self.event_picker.clear();
+        self.ui.events_error.set_opacity(0.0);
 
         let reg_desk = self.reg_desk.take().unwrap();
         let this_weak = self
@@ -147,29 +227,40 @@ impl CreateUpdateParticipant {
             .parent_weak
             .clone();
 
-        let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-        std::thread::spawn(move || tx.send((reg_desk.college_get_filtered(""), reg_desk)));
-
-        rx.attach(None, move |(colleges, reg_desk)| {
+        let work = move |reg_desk: &mut dyn IRegDesk| {
+            let colleges = reg_desk.college_get_filtered("");
+            let events = reg_desk.event_list();
+            let enrolled = participant_id
+                .map(|id| reg_desk.participant_events(id))
+                .unwrap_or_default();
+            let thumbnail = participant_id.and_then(|id| reg_desk.participant_thumbnail(id));
+            (colleges, events, enrolled, thumbnail)
+        };
+        run_with_desk(reg_desk, work, move |loaded, reg_desk| {
             let this = this_weak.upgrade().unwrap();
             this.reg_desk.set(Some(reg_desk));
-            this.college_list
-                .borrow_mut()
-                .as_mut()
-                .unwrap()
-                .reload(colleges);
-
-            match this.mode.get().unwrap() {
-                Mode::CreateRagam | Mode::CreateKalotsavam => this.state_default_create(),
-                Mode::Update => {
+            match loaded {
+                Some((colleges, events, enrolled, thumbnail)) => {
+                    show_thumbnail(&this.ui.photo, thumbnail.as_deref());
+                    this.event_picker.load(events, &enrolled);
+                    this.college_list
+                        .borrow_mut()
+                        .as_mut()
+                        .unwrap()
+                        .reload(colleges);
+                }
+                None => this.show_connection_lost(),
+            }
+
+            match this.mode.borrow().as_ref().unwrap() {
+                Mode::Create(_) => this.state_default_create(),
+                Mode::Update(_) => {
                     let p = this.participant.take().unwrap();
                     this.load_participant(&p);
                     this.participant.set(Some(p));
                     this.state_default_update();
                 }
-            }
-
-            glib::source::Continue(false)
+            };
         });
     }
 
@@ -224,14 +315,23 @@ impl CreateUpdateParticipant {
                         .college_list
                         .borrow();
                 let college_list = college_list_borrow.as_ref().unwrap();
-                let colleges = college_list
-                        .fuzzy_filter(college_str)
-                        .take(100);
+                let colleges = college_list.ranked(college_str);
 
                 this.ui.college_list.foreach(|child| this.ui.college_list.remove(child));
-                for college in colleges
+                for ranked in colleges.iter().take(100)
                 {
-                    let row = gtk::Label::new(Some(college.name.as_str()));
+                    let college = ranked.college;
+                    let name = gtk::Label::new(None);
+                    name.set_markup(&ranked.name_markup());
+                    name.set_halign(gtk::Align::Start);
+                    let city = gtk::Label::new(None);
+                    city.set_markup(&ranked.detail_markup());
+                    city.get_style_context().add_class("dim-label");
+                    let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+                    row.pack_start(&name, true, true, 0);
+                    row.pack_end(&city, false, false, 0);
+                    // The row is looked up by id when picked.
+                    WidgetExt::set_name(&row, &college.id().to_string());
                     row.show_all();
                     this.ui.college_list.add(&row);
                 }
@@ -268,7 +368,12 @@ impl CreateUpdateParticipant {
             let this = this_weak.upgrade().unwrap();
 
             if let Some(row) = row {
-                this.ui.college.set_text(row.get_child().unwrap().downcast::<gtk::Label>().unwrap().get_text().unwrap().as_str());
+                let id = WidgetExt::get_name(&row.get_child().unwrap()).and_then(|id| id.parse().ok());
+                let college_list = this.college_list.borrow();
+                if let Some(college) = id.and_then(|id| college_list.as_ref().unwrap().get(id)) {
+                    this.ui.college.set_text(&college.label());
+                    this.college.replace(Some(college.clone()));
+                }
             }
         }});
 
@@ -277,91 +382,312 @@ impl CreateUpdateParticipant {
             .connect_clicked(clone! {this_weak => move |_| {
                 let this = this_weak.upgrade().unwrap();
 
-                let mut reg_desk = this.reg_desk.take().unwrap();
+                let reg_desk = this.reg_desk.take().unwrap();
 
-                this.ui.new_college.set_sensitive(false);
-                this.ui.new_college_entry.set_sensitive(false);
+                set_sensitive!(false, this.ui{
+                    new_college,
+                    new_college_entry,
+                    new_college_city,
+                    new_college_district,
+                    new_college_code
+                });
 
-                let college_g_str = this.ui.new_college_entry.get_text().unwrap();
-                let college = String::from(college_g_str.as_str());
+                let college = NewCollege {
+                    name: this.ui.new_college_entry.get_text().unwrap().to_string(),
+                    city: this.ui.new_college_city.get_text().unwrap().to_string(),
+                    district: this.ui.new_college_district.get_text().unwrap().to_string(),
+                    short_code: this.ui.new_college_code.get_text().unwrap().to_string(),
+                };
 
-                let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                std::thread::spawn(move || {
+                let work = move |reg_desk: &mut dyn IRegDesk| {
                     let college = reg_desk.college_add(college);
                     let colleges = reg_desk.college_get_filtered("");
-                    tx.send((college, colleges, reg_desk))
-                });
-
+                    (college, colleges)
+                };
                 let this_weak = this_weak.clone();
-                rx.attach(None, move |(college, colleges, reg_desk)| {
+                run_with_desk(reg_desk, work, move |added, reg_desk| {
                     let this = this_weak.upgrade().unwrap();
-                    this.college_list.borrow_mut().as_mut().unwrap().reload(colleges);
-                    this.ui.college.set_text(&college.name);
-                    this.ui.college_search.set_text(&college.name);
+                    match added {
+                        Some((college, colleges)) => {
+                            this.college_list.borrow_mut().as_mut().unwrap().reload(colleges);
+                            this.ui.college.set_text(&college.label());
+                            this.ui.college_search.set_text(&college.name);
+                            this.college.replace(Some(college));
+                        }
+                        None => {
+                            this.ui.college_popup.hide();
+                            this.show_connection_lost();
+                        }
+                    }
                     this.reg_desk.set(Some(reg_desk));
-                    this.ui.new_college.set_sensitive(true);
-                    this.ui.new_college_entry.set_sensitive(true);
-                    glib::source::Continue(false)
+                    set_sensitive!(true, this.ui{
+                        new_college,
+                        new_college_entry,
+                        new_college_city,
+                        new_college_district,
+                        new_college_code
+                    });
                 });
             }});
 
-        this.ui.save.connect_clicked(clone! {this_weak => move |_|{
+        this.ui.choose_photo.connect_clicked(clone! {this_weak => move |_| {
             let this = this_weak.upgrade().unwrap();
-
-            let (participant_info, college) = this.new_participant_from_fields();
-            if participant_info.name.is_empty() || participant_info.phone.is_empty() || college.is_none(){
-                return;
+            let path = match photo::choose_photo_file(&this.ui.root) {
+                Some(path) => path,
+                None => return,
+            };
+            match photo::read_photo(&path) {
+                Ok(photo) => {
+                    show_thumbnail(&this.ui.photo, Some(&photo.thumbnail));
+                    this.ui.photo_error.set_opacity(0.0);
+                    this.photo.replace(Some(photo));
+                }
+                Err(message) => this.show_photo_error(message),
             }
-            let college = college.unwrap();
-
-            this.state_action_pending();
+        }});
 
-            match this.mode.get().unwrap() {
-                Mode::CreateRagam|Mode::CreateKalotsavam => {
-                    let mut reg_desk = this.reg_desk.take().unwrap();
+        this.ui.save.connect_clicked(clone! {this_weak => move |_|{
+            let this = this_weak.upgrade().unwrap();
 
-                    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                    std::thread::spawn(move || {
-                        tx.send((reg_desk.participant_new_verified(participant_info, college), reg_desk))
-                    });
+            let (mut participant_info, college) = this.new_participant_from_fields();
+            participant_info.kalotsavam = match this.kalotsavam_details_from_fields() {
+                Ok(details) => details,
+                Err(message) => {
+                    this.ui.details_error.set_text(message);
+                    this.ui.details_error.set_opacity(1.0);
+                    return;
+                }
+            };
+            let invalid = check_participant(&participant_info, college.is_some())
+                .err()
+                .unwrap_or_default();
+            this.show_invalid_fields(&invalid);
+            let college = match college {
+                Some(college) if invalid.is_empty() => college,
+                _ => return,
+            };
 
+            this.state_action_pending();
+            let photo = this.photo.replace(None);
+            let selected_events = if this.ui.events_box.get_visible() {
+                Some(this.event_picker.selected())
+            } else {
+                None
+            };
+
+            let mode = this.mode.borrow().clone().unwrap();
+            match mode {
+                Mode::Create(_) => {
+                    let reg_desk = this.reg_desk.take().unwrap();
+
+                    // Kept on the form if the participant is not saved.
+                    let kept_photo = photo.clone();
+                    let work = move |reg_desk: &mut dyn IRegDesk| {
+                        let result = reg_desk.participant_new(participant_info, college);
+                        let (enrolment, photo_error, photo) = match result {
+                            Ok(ref participant) => (
+                                selected_events.map(|selected| {
+                                    save_enrolment(reg_desk, participant.id(), &selected)
+                                }),
+                                save_photo(reg_desk, participant.id(), photo),
+                                None,
+                            ),
+                            Err(_) => (None, None, photo),
+                        };
+                        (result, enrolment, photo_error, photo)
+                    };
                     let this_weak = this_weak.clone();
-                    rx.attach(None, move |(participant, reg_desk)| {
+                    run_with_desk(reg_desk, work, move |saved, reg_desk| {
                         let this = this_weak.upgrade().unwrap();
-                        this.load_participant(&participant);
-                        this.state_create_complete();
+                        let (result, enrolment, photo_error, photo) = match saved {
+                            Some(saved) => saved,
+                            None => {
+                                this.photo.replace(kept_photo);
+                                this.reg_desk.set(Some(reg_desk));
+                                this.state_default_create();
+                                this.show_connection_lost();
+                                return;
+                            }
+                        };
+                        this.photo.replace(photo);
+                        if let Some(message) = photo_error {
+                            this.show_photo_error(message);
+                        }
+                        match result {
+                            Ok(participant) => {
+                                this.load_participant(&participant);
+                                this.load_enrolment(enrolment);
+                                this.state_create_complete();
+                                this.participant.set(Some(participant));
+                            }
+                            Err(invalid) => {
+                                this.show_invalid_fields(&invalid);
+                                this.state_default_create();
+                            }
+                        }
                         this.reg_desk.set(Some(reg_desk));
-                        this.participant.set(Some(participant));
-                        glib::source::Continue(false)
                     });
                 },
-                Mode::Update => {
-                    let mut reg_desk = this.reg_desk.take().unwrap();
-                    let mut participant = this.participant.take().unwrap();
-                    participant.college = college;
-                    participant.info = participant_info;
-
-                    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                    std::thread::spawn(move || {
-                        reg_desk.participant_update(&participant);
-                        tx.send((participant, reg_desk))
-                    });
-
+                Mode::Update(_) => {
+                    let reg_desk = this.reg_desk.take().unwrap();
+                    let participant = this.participant.take().unwrap();
+                    let mut updated = participant.clone();
+                    updated.college = college;
+                    updated.info = participant_info;
+
+                    // Kept on the form if the update is not saved.
+                    let kept_photo = photo.clone();
+                    let work = move |reg_desk: &mut dyn IRegDesk| {
+                        let result = reg_desk.participant_update(&updated);
+                        let enrolment = match (&result, selected_events) {
+                            (Ok(participant), Some(selected)) => {
+                                Some(save_enrolment(reg_desk, participant.id(), &selected))
+                            }
+                            // For the conflict panel; the picked events are
+                            // saved with the next try.
+                            (Err(UpdateError::Conflict(conflict)), Some(_)) => Some((
+                                Vec::new(),
+                                reg_desk.event_list(),
+                                reg_desk.participant_events(conflict.current.id()),
+                            )),
+                            _ => None,
+                        };
+                        let (photo_error, photo) = match result {
+                            Ok(ref participant) => {
+                                (save_photo(reg_desk, participant.id(), photo), None)
+                            }
+                            Err(_) => (None, photo),
+                        };
+                        (result, enrolment, photo_error, photo)
+                    };
                     let this_weak = this_weak.clone();
-                    rx.attach(None, move |(participant, reg_desk)| {
+                    run_with_desk(reg_desk, work, move |saved, reg_desk| {
                         let this = this_weak.upgrade().unwrap();
-                        this.load_participant(&participant);
-                        this.state_update_complete();
+                        let (result, enrolment, photo_error, photo) = match saved {
+                            Some(saved) => saved,
+                            None => {
+                                this.photo.replace(kept_photo);
+                                this.participant.set(Some(participant));
+                                this.reg_desk.set(Some(reg_desk));
+                                this.state_default_update();
+                                this.show_connection_lost();
+                                return;
+                            }
+                        };
+                        this.photo.replace(photo);
+                        if let Some(message) = photo_error {
+                            this.show_photo_error(message);
+                        }
+                        match result {
+                            Ok(participant) => {
+                                this.load_participant(&participant);
+                                this.load_enrolment(enrolment);
+                                this.state_update_complete();
+                                this.participant.set(Some(participant));
+                                if let Some(last_action) = reg_desk.last_action() {
+                                    (this.callback)(Message::Undoable(last_action));
+                                }
+                            }
+                            Err(UpdateError::Invalid(invalid)) => {
+                                this.show_invalid_fields(&invalid);
+                                this.state_default_update();
+                                this.participant.set(Some(participant));
+                            }
+                            Err(UpdateError::Conflict(conflict)) => {
+                                let UpdateConflict { current } = *conflict;
+                                // The form keeps the operator's edits; saving again
+                                // is checked against the version shown in the panel.
+                                this.load_conflict(&current, enrolment);
+                                this.state_update_conflict();
+                                this.participant.set(Some(current));
+                            }
+                            Err(UpdateError::UnknownParticipant) => {
+                                this.state_default_update();
+                                this.participant.set(Some(participant));
+                                this.show_save_error("This participant no longer exists");
+                            }
+                        }
                         this.reg_desk.set(Some(reg_desk));
-                        this.participant.set(Some(participant));
-                        glib::source::Continue(false)
                     });
                 }
             }
         }});
+
+        this.ui
+            .conflict_use_name
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.ui.name.set_text(&current.info.name);
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_gender
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.gender_button(current.info.gender).set_active(true);
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_email
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.ui.email.set_text(&current.info.email);
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_college
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.ui.college.set_text(&current.college.label());
+                this.college.replace(Some(current.college.clone()));
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_phone
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.ui.phone.set_text(&current.info.phone);
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_details
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let current = this.participant.take().unwrap();
+                this.load_kalotsavam_details(current.info.kalotsavam.as_ref());
+                this.participant.set(Some(current));
+            }});
+
+        this.ui
+            .conflict_use_events
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let conflict_events = this.conflict_events.borrow().clone();
+                if let Some((events, enrolled)) = conflict_events {
+                    this.event_picker.load(events, &enrolled);
+                }
+            }});
+
         this.ui.back.connect_clicked(clone! {this_weak => move |_| {
             let this = this_weak.upgrade().unwrap();
-            (this.callback)(Message::Back(this.participant.take(), this.reg_desk.take().unwrap()));
+            (this.callback)(Message::Back(this.reg_desk.take().unwrap()));
+        }});
+
+        this.ui.verify.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            let route = Route::VerifyReg(this.participant.take().unwrap());
+            // The form is done with; Back from verification goes home.
+            (this.callback)(Message::Replace(route, this.reg_desk.take().unwrap()));
         }});
     }
 
@@ -374,13 +700,15 @@ impl CreateUpdateParticipant {
 
     // TODO: Merge state_default_* methods
     fn state_default_create(&self) {
-        match self.mode.get().unwrap() {
-            Mode::CreateRagam => self.ui.title.set_text("Ragam Registration"),
-            Mode::CreateKalotsavam => self.ui.title.set_text("Kalotsavam Registration"),
-            _ => {}
+        if let Some(Mode::Create(ref category)) = *self.mode.borrow() {
+            self.ui
+                .title
+                .set_text(&format!("{} Registration", category.name));
         }
         self.ui.ragam_id.set_opacity(0.0);
         self.ui.saved_successfully.set_opacity(0.0);
+        self.ui.conflict.hide();
+        self.ui.verify.hide();
         set_sensitive!(true, self.ui{
             name,
             male,
@@ -389,6 +717,12 @@ impl CreateUpdateParticipant {
             college,
             phone,
             email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
             back,
             save
         });
@@ -398,6 +732,8 @@ impl CreateUpdateParticipant {
         self.ui.title.set_text("Update Details");
         self.ui.saved_successfully.set_opacity(0.0);
         self.ui.ragam_id.set_opacity(1.0);
+        self.ui.conflict.hide();
+        self.ui.verify.hide();
         set_sensitive!(true, self.ui{
             name,
             male,
@@ -406,12 +742,19 @@ impl CreateUpdateParticipant {
             college,
             phone,
             email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
             back,
             save
         });
     }
 
     fn state_action_pending(&self) {
+        self.ui.save_error.set_opacity(0.0);
         set_sensitive!(false, self.ui{
             name,
             male,
@@ -420,12 +763,19 @@ impl CreateUpdateParticipant {
             college,
             phone,
             email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
             back,
             save
         });
     }
 
     fn state_update_complete(&self) {
+        self.ui.conflict.hide();
         self.ui.saved_successfully.set_opacity(1.0);
         let saved_successfully = self.ui.saved_successfully.clone();
         glib::timeout_add_local(5000, move || {
@@ -440,6 +790,34 @@ impl CreateUpdateParticipant {
             college,
             phone,
             email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
+            back,
+            save
+        });
+    }
+
+    fn state_update_conflict(&self) {
+        self.ui.saved_successfully.set_opacity(0.0);
+        self.ui.conflict.show();
+        set_sensitive!(true, self.ui{
+            name,
+            male,
+            female,
+            other,
+            college,
+            phone,
+            email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
             back,
             save
         });
@@ -456,27 +834,186 @@ impl CreateUpdateParticipant {
             college,
             phone,
             email,
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+            choose_photo,
+            events,
             save
         });
         set_sensitive!(true, self.ui.back);
+        self.ui.verify.show();
+    }
+
+    fn show_connection_lost(&self) {
+        self.show_save_error(CONNECTION_LOST);
+    }
+
+    fn show_save_error(&self, message: &str) {
+        self.ui.save_error.set_text(message);
+        self.ui.save_error.set_opacity(1.0);
     }
 
     fn load_participant(&self, participant: &Participant) {
-        let id = match participant.info.category {
-            ParticipantCategory::Kalotsavam => format!("K19{:06}", participant.id()),
-            ParticipantCategory::Ragam => format!("R19{:06}", participant.id()),
-        };
-        self.ui.ragam_id.set_text(&id);
+        self.ui
+            .ragam_id
+            .set_text(&participant_code(participant, self.edition));
         self.ui.name.set_text(&participant.info.name);
-        match participant.info.gender {
+        self.gender_button(participant.info.gender).set_active(true);
+        self.ui.college.set_text(&participant.college.label());
+        self.college.replace(Some(participant.college.clone()));
+        self.ui.email.set_text(&participant.info.email);
+        self.ui.phone.set_text(&participant.info.phone);
+        self.load_kalotsavam_details(participant.info.kalotsavam.as_ref());
+    }
+
+    fn load_kalotsavam_details(&self, details: Option<&KalotsavamDetails>) {
+        self.ui.details_error.set_opacity(0.0);
+        match details {
+            Some(details) => {
+                self.ui
+                    .institution_type
+                    .set_active_id(Some(institution_type_to_id(details.institution_type)));
+                self.ui.class.set_text(&details.class);
+                self.ui.district.set_text(&details.district);
+                self.ui
+                    .date_of_birth
+                    .set_text(&details.date_of_birth.to_string());
+            }
+            None => {
+                self.ui.institution_type.set_active(None);
+                self.ui.class.set_text("");
+                self.ui.district.set_text("");
+                self.ui.date_of_birth.set_text("");
+            }
+        }
+    }
+
+    /// `None` when the form has no Kalotsavam fields for this participant.
+    /// The details are checked with the other fields by `check_participant`.
+    fn kalotsavam_details_from_fields(&self) -> Result<Option<KalotsavamDetails>, &'static str> {
+        if !self.ui.kalotsavam_box.get_visible() {
+            return Ok(None);
+        }
+        let institution_type = match self.ui.institution_type.get_active_id().as_ref().map(|id| id.as_str()) {
+            Some("school") => InstitutionType::School,
+            Some("higher_secondary") => InstitutionType::HigherSecondary,
+            Some("college") => InstitutionType::College,
+            _ => return Err("Choose the type of institution"),
+        };
+        let class = self.ui.class.get_text().unwrap().trim().to_owned();
+        let district = self.ui.district.get_text().unwrap().trim().to_owned();
+        let date_of_birth = NaiveDate::parse_from_str(
+            self.ui.date_of_birth.get_text().unwrap().trim(),
+            "%Y-%m-%d",
+        )
+        .map_err(|_| "Enter the date of birth as YYYY-MM-DD")?;
+        Ok(Some(KalotsavamDetails {
+            institution_type,
+            class,
+            district,
+            date_of_birth,
+        }))
+    }
+
+    /// Shows each error next to its entry and clears the rest.
+    fn show_invalid_fields(&self, invalid: &[InvalidField]) {
+        for label in &[
+            &self.ui.name_error,
+            &self.ui.email_error,
+            &self.ui.college_error,
+            &self.ui.phone_error,
+            &self.ui.details_error,
+        ] {
+            label.set_opacity(0.0);
+        }
+        // Backwards, so that the first error of the Kalotsavam details is the
+        // one left on their label.
+        for field in invalid.iter().rev() {
+            let label = match field {
+                InvalidField::NameEmpty | InvalidField::NameTooLong => &self.ui.name_error,
+                InvalidField::Email => &self.ui.email_error,
+                InvalidField::UnknownCollege => &self.ui.college_error,
+                InvalidField::Phone => &self.ui.phone_error,
+                InvalidField::KalotsavamDetailsMissing
+                | InvalidField::ClassEmpty
+                | InvalidField::DistrictEmpty
+                | InvalidField::DateOfBirth => &self.ui.details_error,
+                InvalidField::InactiveCategory => &self.ui.save_error,
+            };
+            label.set_text(invalid_field_to_str(*field));
+            label.set_opacity(1.0);
+        }
+    }
+
+    fn reset_photo(&self) {
+        self.photo.replace(None);
+        show_thumbnail(&self.ui.photo, None);
+        self.ui.photo_error.set_opacity(0.0);
+    }
+
+    fn show_photo_error(&self, message: &str) {
+        self.ui.photo_error.set_text(message);
+        self.ui.photo_error.set_opacity(1.0);
+    }
+
+    fn load_enrolment(&self, enrolment: Option<Enrolment>) {
+        if let Some((errors, events, enrolled)) = enrolment {
+            self.event_picker.load(events, &enrolled);
+            self.ui.events_error.set_text(&errors.join(" "));
+            self.ui
+                .events_error
+                .set_opacity(if errors.is_empty() { 0.0 } else { 1.0 });
+        }
+    }
+
+    fn load_conflict(&self, current: &Participant, enrolment: Option<Enrolment>) {
+        self.ui.conflict_name.set_text(&current.info.name);
+        self.ui
+            .conflict_gender
+            .set_text(gender_to_str(&current.info.gender));
+        self.ui.conflict_email.set_text(&current.info.email);
+        self.ui.conflict_college.set_text(&current.college.label());
+        self.ui.conflict_phone.set_text(&current.info.phone);
+
+        let events = current.info.category.events;
+        self.ui.conflict_details_title.set_visible(events);
+        self.ui.conflict_details.set_visible(events);
+        self.ui.conflict_use_details.set_visible(events);
+        self.ui.conflict_events_title.set_visible(events);
+        self.ui.conflict_events.set_visible(events);
+        self.ui.conflict_use_events.set_visible(events);
+        let details = match current.info.kalotsavam {
+            Some(ref details) => format!(
+                "{}, class {}, {} district, born {}",
+                institution_type_to_str(details.institution_type),
+                details.class,
+                details.district,
+                details.date_of_birth
+            ),
+            None => String::from("Not entered"),
+        };
+        self.ui.conflict_details.set_text(&details);
+        let enrolled = enrolment.map(|(_, events, enrolled)| (events, enrolled));
+        let names = match enrolled {
+            Some((_, ref enrolled)) if !enrolled.is_empty() => enrolled
+                .iter()
+                .map(|event| event.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => String::from("None"),
+        };
+        self.ui.conflict_events.set_text(&names);
+        self.conflict_events.replace(enrolled);
+    }
+
+    fn gender_button(&self, gender: Gender) -> &gtk::RadioButton {
+        match gender {
             Gender::Male => &self.ui.male,
             Gender::Female => &self.ui.female,
             Gender::Other => &self.ui.other,
         }
-        .set_active(true);
-        self.ui.college.set_text(&participant.college.name);
-        self.ui.email.set_text(&participant.info.email);
-        self.ui.phone.set_text(&participant.info.phone);
     }
 
     fn new_participant_from_fields(&self) -> (ParticipantInfo, Option<College>) {
@@ -485,13 +1022,12 @@ impl CreateUpdateParticipant {
         let phone = self.ui.phone.get_text().unwrap().to_string();
         let college_list_borrow = self.college_list.borrow();
         let college_list = college_list_borrow.as_ref().unwrap();
-        let college = self
-            .ui
-            .college
-            .get_text()
-            .as_ref()
-            .map(glib::GString::as_str)
-            .and_then(|s| college_list.find(s));
+        let text = self.ui.college.get_text();
+        let text = text.as_ref().map(glib::GString::as_str).unwrap_or("");
+        let college = match *self.college.borrow() {
+            Some(ref college) if college.label() == text => Some(college.clone()),
+            _ => college_list.find(text),
+        };
         (
             ParticipantInfo {
                 name,
@@ -506,16 +1042,43 @@ impl CreateUpdateParticipant {
                         Gender::Other
                     }
                 },
-                category: match self.mode.get().unwrap() {
-                    Mode::CreateRagam => ParticipantCategory::Ragam,
-                    _ => ParticipantCategory::Kalotsavam,
+                // The form has no category field; it comes with the mode.
+                category: match self.mode.borrow().as_ref().unwrap() {
+                    Mode::Create(category) | Mode::Update(category) => category.clone(),
                 },
+                kalotsavam: None,
             },
             college,
         )
     }
 }
 
+/// Errors from saving the picked events, then all events and the ones the
+/// participant ended up in.
+type Enrolment = (Vec<String>, Vec<Event>, Vec<Event>);
+
+fn save_enrolment(reg_desk: &mut dyn IRegDesk, participant_id: i32, selected: &[i32]) -> Enrolment {
+    let errors = event_picker::save_enrolment(reg_desk, participant_id, selected);
+    (
+        errors,
+        reg_desk.event_list(),
+        reg_desk.participant_events(participant_id),
+    )
+}
+
+/// Returns why the photo was not saved, if it was not.
+fn save_photo(
+    reg_desk: &mut dyn IRegDesk,
+    participant_id: i32,
+    photo: Option<Photo>,
+) -> Option<&'static str> {
+    match reg_desk.participant_set_photo(participant_id, photo?) {
+        Ok(()) => None,
+        Err(PhotoError::TooLarge) => Some("The photo must be under 2 MB"),
+        Err(PhotoError::UnknownParticipant) => Some("Participant not found"),
+    }
+}
+
 impl CollegeList {
     pub fn new(parent_weak: Weak<CreateUpdateParticipant>) -> Self {
         CollegeList {
@@ -528,16 +1091,108 @@ impl CollegeList {
         self.colleges = colleges;
     }
 
-    pub fn fuzzy_filter<'a>(&'a self, key: &'a str) -> Box<dyn Iterator<Item = &'a College> + 'a> {
-        Box::from(
-            self.colleges.iter().filter(move |c| {
-                fuzzy_filter::matches(&key.to_lowercase(), &c.name.to_lowercase())
-            }),
-        )
+    /// Colleges matching `key`, best match first.
+    pub fn ranked<'a>(&'a self, key: &str) -> Vec<RankedCollege<'a>> {
+        let mut ranked: Vec<RankedCollege> = self
+            .colleges
+            .iter()
+            .filter_map(|college| {
+                let others = vec![&college.short_code, &college.district]
+                    .into_iter()
+                    .chain(college.aliases.iter())
+                    .map(|other| (MatchedField::Other(other.as_str()), other.as_str()));
+                let fields = vec![
+                    (MatchedField::Name, college.name.as_str()),
+                    (MatchedField::City, college.city.as_str()),
+                ];
+                let mut best: Option<(MatchedField, fuzzy::Match)> = None;
+                for (matched, text) in fields.into_iter().chain(others) {
+                    let m = match fuzzy::fuzzy_match(key, text) {
+                        Some(m) => m,
+                        None => continue,
+                    };
+                    // The name wins ties, so it is what gets highlighted.
+                    let better = match &best {
+                        Some((_, best)) => m > *best,
+                        None => true,
+                    };
+                    if better {
+                        best = Some((matched, m));
+                    }
+                }
+                best.map(|(matched, m)| RankedCollege { college, matched, m })
+            })
+            .collect();
+        // Stable, so equally good matches stay in id order.
+        ranked.sort_by(|a, b| b.m.cmp(&a.m));
+        ranked
+    }
+
+    pub fn get(&self, id: i32) -> Option<&College> {
+        self.colleges.iter().find(|c| c.id() == id)
     }
 
-    pub fn find(&self, name: &str) -> Option<College> {
-        self.colleges.iter().find(|c| c.name == name).cloned()
+    /// The college whose label, as shown in the college entry, is `label`.
+    pub fn find(&self, label: &str) -> Option<College> {
+        self.colleges.iter().find(|c| c.label() == label).cloned()
+    }
+}
+
+impl<'a> RankedCollege<'a> {
+    fn name_markup(&self) -> String {
+        match self.matched {
+            MatchedField::Name => fuzzy::highlight(&self.college.name, &self.m.positions),
+            _ => glib::markup_escape_text(&self.college.name).to_string(),
+        }
+    }
+
+    /// The city, after the short code, district or alias that matched if
+    /// neither the name nor the city did.
+    fn detail_markup(&self) -> String {
+        let city = &self.college.city;
+        match self.matched {
+            MatchedField::City => fuzzy::highlight(city, &self.m.positions),
+            MatchedField::Other(other) if city.is_empty() => {
+                fuzzy::highlight(other, &self.m.positions)
+            }
+            MatchedField::Other(other) => format!(
+                "{} · {}",
+                fuzzy::highlight(other, &self.m.positions),
+                glib::markup_escape_text(city)
+            ),
+            MatchedField::Name => glib::markup_escape_text(city).to_string(),
+        }
+    }
+}
+
+fn invalid_field_to_str(field: InvalidField) -> &'static str {
+    match field {
+        InvalidField::NameEmpty => "Enter the name",
+        InvalidField::NameTooLong => "Name is too long",
+        InvalidField::Email => "Not a valid email",
+        InvalidField::UnknownCollege => "Choose a college from the list",
+        InvalidField::Phone => "Not a valid mobile number",
+        InvalidField::KalotsavamDetailsMissing => "Enter the Kalotsavam details",
+        InvalidField::ClassEmpty => "Enter the class or year of study",
+        InvalidField::DistrictEmpty => "Enter the district",
+        InvalidField::DateOfBirth => "The date of birth must be in the past",
+        InvalidField::InactiveCategory => "This category takes no new participants",
+    }
+}
+
+fn institution_type_to_str(institution_type: InstitutionType) -> &'static str {
+    match institution_type {
+        InstitutionType::School => "School",
+        InstitutionType::HigherSecondary => "Higher Secondary",
+        InstitutionType::College => "College",
+    }
+}
+
+fn institution_type_to_id(institution_type: InstitutionType) -> &'static str {
+    match institution_type {
+        InstitutionType::School => "school",
+        InstitutionType::HigherSecondary => "higher_secondary",
+        InstitutionType::College => "college",
     }
 }
 
@@ -545,4 +1200,31 @@ impl View for CreateUpdateParticipant {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        match (route, reg_desk) {
+            (Route::NewReg(category), Some(reg_desk)) => self.set_mode_create(category, reg_desk),
+            (Route::UpdateParticipant(participant), Some(reg_desk)) => {
+                self.set_mode_update(participant, reg_desk)
+            }
+            _ => {}
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Save => shortcuts::click(&self.ui.save),
+            Shortcut::Verify => shortcuts::click(&self.ui.verify),
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,231 @@
+use super::main_view::View;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::verify_reg::gender_to_str;
+use super::{run_with_desk, CONNECTION_LOST};
+use crate::repository::*;
+use chrono;
+use glib;
+use gtk;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::{Rc, Weak};
+
+const REFRESH_INTERVAL_MS: u32 = 30_000;
+
+pub struct Dashboard {
+    ui: DashboardUI,
+    reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
+    callback: Box<dyn Fn(Message)>,
+    // Bumped whenever the view is unmapped, so the refresh timer started
+    // by an earlier visit stops on its next tick.
+    generation: Cell<u32>,
+    // Back was pressed while a refresh held the reg_desk.
+    back_pending: Cell<bool>,
+}
+
+ui_struct! {
+    struct DashboardUI {
+        root: gtk::Widget,
+        total: gtk::Label,
+        by_category: gtk::ListBox,
+        by_gender: gtk::ListBox,
+        hospitality: gtk::ListBox,
+        by_college: gtk::ListBox,
+        by_admin: gtk::ListBox,
+        by_desk: gtk::ListBox,
+        by_hour: gtk::ListBox,
+        last_updated: gtk::Label,
+        back: gtk::Button
+    }
+}
+
+impl Dashboard {
+    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+        let glade_src = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/ui/dashboard.glade"
+        ));
+        let builder = gtk::Builder::new_from_string(glade_src);
+
+        let ret = Rc::from(Dashboard {
+            ui: DashboardUI::build(builder),
+            reg_desk: Cell::from(None),
+            callback,
+            generation: Cell::from(0),
+            back_pending: Cell::from(false),
+        });
+
+        Self::initialize_callbacks(ret.clone());
+
+        ret
+    }
+
+    pub fn set_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn initialize_callbacks(this: Rc<Self>) {
+        let this_weak = Rc::downgrade(&this);
+
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.back_pending.set(false);
+            this.ui.back.set_sensitive(true);
+            this.refresh(this_weak.clone());
+
+            let generation = this.generation.get();
+            glib::timeout_add_local(REFRESH_INTERVAL_MS, clone! {this_weak => move || {
+                match this_weak.upgrade() {
+                    Some(ref this) if this.generation.get() == generation => {
+                        this.refresh(this_weak.clone());
+                        glib::source::Continue(true)
+                    }
+                    _ => glib::source::Continue(false),
+                }
+            }});
+        }});
+
+        this.ui.root.connect_unmap(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.generation.set(this.generation.get().wrapping_add(1));
+        }});
+
+        this.ui.back.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            match this.reg_desk.take() {
+                Some(reg_desk) => (this.callback)(Message::Back(reg_desk)),
+                None => {
+                    this.back_pending.set(true);
+                    this.ui.back.set_sensitive(false);
+                }
+            }
+        }});
+    }
+
+    fn refresh(&self, this_weak: Weak<Self>) {
+        // A refresh is already in flight.
+        let reg_desk = match self.reg_desk.take() {
+            Some(reg_desk) => reg_desk,
+            None => return,
+        };
+
+        run_with_desk(
+            reg_desk,
+            |reg_desk| reg_desk.statistics(),
+            move |statistics, reg_desk| {
+                let this = this_weak.upgrade().unwrap();
+                if this.back_pending.replace(false) {
+                    (this.callback)(Message::Back(reg_desk));
+                    return;
+                }
+                this.reg_desk.set(Some(reg_desk));
+                match statistics {
+                    Some(statistics) => this.load_statistics(&statistics),
+                    None => this.ui.last_updated.set_text(CONNECTION_LOST),
+                }
+            },
+        );
+    }
+
+    fn load_statistics(&self, statistics: &Statistics) {
+        self.ui
+            .total
+            .set_text(&format!("{} registrations verified", statistics.total));
+        fill_list(
+            &self.ui.by_category,
+            statistics
+                .by_category
+                .iter()
+                .map(|(name, count)| (name.clone(), count.to_string())),
+        );
+        fill_list(
+            &self.ui.by_gender,
+            statistics
+                .by_gender
+                .iter()
+                .map(|(gender, count)| (gender_to_str(gender).to_owned(), count.to_string())),
+        );
+        fill_list(
+            &self.ui.hospitality,
+            statistics.hospitality.iter().map(|h| {
+                (
+                    h.hostel.clone(),
+                    format!("{} in {} rooms", h.participants, h.rooms),
+                )
+            }),
+        );
+        fill_list(
+            &self.ui.by_college,
+            statistics
+                .by_college
+                .iter()
+                .map(|(college, count)| (college.clone(), count.to_string())),
+        );
+        fill_list(
+            &self.ui.by_admin,
+            statistics
+                .by_admin
+                .iter()
+                .map(|(admin, count)| (admin.clone(), count.to_string())),
+        );
+        fill_list(
+            &self.ui.by_desk,
+            statistics
+                .by_desk
+                .iter()
+                .map(|(desk, count)| (desk.clone(), count.to_string())),
+        );
+        fill_list(
+            &self.ui.by_hour,
+            statistics
+                .by_hour
+                .iter()
+                .map(|(hour, count)| (format!("{:02}:00", hour), count.to_string())),
+        );
+        self.ui.last_updated.set_text(&format!(
+            "Updated at {}",
+            chrono::Local::now().format("%H:%M:%S")
+        ));
+    }
+}
+
+fn fill_list<I: Iterator<Item = (String, String)>>(list: &gtk::ListBox, rows: I) {
+    list.foreach(|child| list.remove(child));
+    for (key, value) in rows {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let key = gtk::Label::new(Some(key.as_str()));
+        key.set_halign(gtk::Align::Start);
+        row.pack_start(&key, true, true, 0);
+        row.pack_end(&gtk::Label::new(Some(value.as_str())), false, false, 0);
+        row.show_all();
+        list.add(&row);
+    }
+}
+
+impl View for Dashboard {
+    fn get_root_widget(&self) -> &gtk::Widget {
+        &self.ui.root
+    }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::repository::*;
+use gtk;
+use gtk::prelude::*;
+use std::cell::RefCell;
+
+/// A check button per Kalotsavam event, used by the screens that enrol
+/// participants.
+pub struct EventPicker {
+    list: gtk::ListBox,
+    rows: RefCell<Vec<(Event, gtk::CheckButton)>>,
+}
+
+impl EventPicker {
+    pub fn new(list: gtk::ListBox) -> Self {
+        EventPicker {
+            list,
+            rows: RefCell::from(Vec::new()),
+        }
+    }
+
+    /// Full events can only be unticked. Group events are left out; teams
+    /// are entered on the team screen.
+    pub fn load(&self, events: Vec<Event>, enrolled: &[Event]) {
+        self.list.foreach(|child| self.list.remove(child));
+        let mut rows = self.rows.borrow_mut();
+        rows.clear();
+        for event in events.into_iter().filter(|e| e.team_size.is_none()) {
+            let is_enrolled = enrolled.iter().any(|e| e.id == event.id);
+            let label = match event.max_participants {
+                Some(max) => format!("{}  ({}/{})", event.name, event.enrolled, max),
+                None => format!("{}  ({})", event.name, event.enrolled),
+            };
+            let check = gtk::CheckButton::new_with_label(&label);
+            check.set_active(is_enrolled);
+            check.set_sensitive(is_enrolled || !event.is_full());
+            check.show_all();
+            self.list.add(&check);
+            rows.push((event, check));
+        }
+    }
+
+    pub fn clear(&self) {
+        self.load(Vec::new(), &[]);
+    }
+
+    pub fn selected(&self) -> Vec<i32> {
+        self.rows
+            .borrow()
+            .iter()
+            .filter(|(_, check)| check.get_active())
+            .map(|(event, _)| event.id)
+            .collect()
+    }
+}
+
+/// Enrols in and withdraws from events until the participant is in exactly
+/// `selected`. Returns a message for every event that could not be joined.
+pub fn save_enrolment(
+    reg_desk: &mut dyn IRegDesk,
+    participant_id: i32,
+    selected: &[i32],
+) -> Vec<String> {
+    for event in reg_desk.participant_events(participant_id) {
+        if event.team_size.is_none() && !selected.contains(&event.id) {
+            reg_desk.participant_withdraw(participant_id, event.id);
+        }
+    }
+    let mut errors = Vec::new();
+    for &event_id in selected {
+        if let Err(err) = reg_desk.participant_enrol(participant_id, event_id) {
+            let name = reg_desk
+                .event_list()
+                .into_iter()
+                .find(|e| e.id == event_id)
+                .map(|e| e.name)
+                .unwrap_or_else(|| format!("Event {}", event_id));
+            errors.push(match err {
+                EnrolError::EventFull => format!("{} is full.", name),
+                EnrolError::UnknownEvent => format!("{} no longer exists.", name),
+                EnrolError::NoEvents => format!("Not registered for events; {} skipped.", name),
+            });
+        }
+    }
+    errors
+}
@@ -0,0 +1,263 @@
+//! Ranks how well a search key matches a piece of text, for pickers where the
+//! best match should come first.
+//!
+//! Only letters and digits take part, ignoring case, so "nitc" matches
+//! "N.I.T Calicut" and "gec thrissur" matches "GEC, Thrissur".
+
+use glib;
+use std::cmp::Ordering;
+
+/// How a key matched, from weakest to strongest.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    /// The key's characters appear in order, anywhere.
+    Subsequence,
+    /// Every run of the key starts a word, as "calicut" does in "NIT
+    /// Calicut" and "gect" does in "Government Engineering College Thrissur".
+    WordBoundary,
+    /// The text starts with the key.
+    Prefix,
+    /// The text is the key.
+    Exact,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    pub tier: Tier,
+    /// Char indices of the matched characters in the text, ascending.
+    pub positions: Vec<usize>,
+}
+
+impl Match {
+    /// Characters skipped between the first and last matched ones. Fewer is
+    /// a tighter match.
+    fn gaps(&self) -> usize {
+        match (self.positions.first(), self.positions.last()) {
+            (Some(first), Some(last)) => last - first + 1 - self.positions.len(),
+            _ => 0,
+        }
+    }
+}
+
+impl Ord for Match {
+    /// Better matches are greater: a stronger tier, then an earlier start,
+    /// then fewer gaps.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.tier
+            .cmp(&other.tier)
+            .then(other.positions.first().cmp(&self.positions.first()))
+            .then(other.gaps().cmp(&self.gaps()))
+    }
+}
+
+impl PartialOrd for Match {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Matches `key` against `text`, or `None` if its characters do not all
+/// appear in order. An empty key is a prefix of every text.
+pub fn fuzzy_match(key: &str, text: &str) -> Option<Match> {
+    let key: Vec<char> = key
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .map(lowercase)
+        .collect();
+    // Letters and digits of the text, lowercased, with their char index and
+    // whether they start a word.
+    let mut chars = Vec::new();
+    let mut in_word = false;
+    for (i, c) in text.chars().enumerate() {
+        if c.is_alphanumeric() {
+            // Dots join initials into a word, as in "N.I.T", but each initial
+            // still starts a word of its own.
+            chars.push((i, lowercase(c), !in_word));
+            in_word = true;
+        } else {
+            in_word = false;
+        }
+    }
+
+    if key.len() <= chars.len() && key.iter().zip(&chars).all(|(k, (_, c, _))| k == c) {
+        let tier = if key.len() == chars.len() {
+            Tier::Exact
+        } else {
+            Tier::Prefix
+        };
+        let positions = chars[..key.len()].iter().map(|(i, _, _)| *i).collect();
+        return Some(Match { tier, positions });
+    }
+    if let Some(positions) = word_boundary(&key, &chars) {
+        return Some(Match {
+            tier: Tier::WordBoundary,
+            positions,
+        });
+    }
+    subsequence(&key, &chars).map(|positions| Match {
+        tier: Tier::Subsequence,
+        positions,
+    })
+}
+
+/// Wraps the matched characters of `text` in bold, escaping the rest for use
+/// as Pango markup.
+pub fn highlight(text: &str, positions: &[usize]) -> String {
+    let mut markup = String::new();
+    let mut bold = false;
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        if matched != bold {
+            markup.push_str(if matched { "<b>" } else { "</b>" });
+            bold = matched;
+        }
+        markup.push_str(&glib::markup_escape_text(c.encode_utf8(&mut [0; 4])));
+    }
+    if bold {
+        markup.push_str("</b>");
+    }
+    markup
+}
+
+/// Places each key character either right after the previous one or at the
+/// start of a later word. `fits[k][j]` says whether `key[k..]` can be placed
+/// with `key[k]` at `chars[j]`.
+fn word_boundary(key: &[char], chars: &[(usize, char, bool)]) -> Option<Vec<usize>> {
+    if key.is_empty() {
+        return None;
+    }
+    let n = chars.len();
+    let mut fits = vec![vec![false; n + 1]; key.len()];
+    for k in (0..key.len()).rev() {
+        for j in (0..n).rev() {
+            fits[k][j] = chars[j].1 == key[k]
+                && (k + 1 == key.len()
+                    || fits[k + 1][j + 1]
+                    || (j + 1..n).any(|next| chars[next].2 && fits[k + 1][next]));
+        }
+    }
+
+    let mut j = (0..n).find(|&j| chars[j].2 && fits[0][j])?;
+    let mut positions = vec![chars[j].0];
+    for fits in &fits[1..] {
+        // Staying in the same word keeps the match tight.
+        j = if fits[j + 1] {
+            j + 1
+        } else {
+            (j + 1..n).find(|&next| chars[next].2 && fits[next])?
+        };
+        positions.push(chars[j].0);
+    }
+    Some(positions)
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn subsequence(key: &[char], chars: &[(usize, char, bool)]) -> Option<Vec<usize>> {
+    let mut rest = chars.iter();
+    key.iter()
+        .map(|k| rest.find(|(_, c, _)| c == k).map(|(i, _, _)| *i))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(key: &str, text: &str) -> Option<Tier> {
+        fuzzy_match(key, text).map(|m| m.tier)
+    }
+
+    #[test]
+    fn exact_ignores_case_and_punctuation() {
+        assert_eq!(tier("nit calicut", "NIT Calicut"), Some(Tier::Exact));
+        assert_eq!(tier("nitcalicut", "N.I.T Calicut"), Some(Tier::Exact));
+    }
+
+    #[test]
+    fn prefix() {
+        assert_eq!(tier("nit", "NIT Calicut"), Some(Tier::Prefix));
+        assert_eq!(tier("", "NIT Calicut"), Some(Tier::Prefix));
+    }
+
+    #[test]
+    fn word_boundary_and_acronym() {
+        assert_eq!(tier("calicut", "NIT Calicut"), Some(Tier::WordBoundary));
+        assert_eq!(
+            tier("gect", "Government Engineering College Thrissur"),
+            Some(Tier::WordBoundary)
+        );
+        assert_eq!(
+            tier("gec thrissur", "Government Engineering College Thrissur"),
+            Some(Tier::WordBoundary)
+        );
+        // Words such as "of" may be skipped.
+        assert_eq!(
+            tier("nitc", "National Institute of Technology Calicut"),
+            Some(Tier::WordBoundary)
+        );
+    }
+
+    #[test]
+    fn subsequence_and_no_match() {
+        assert_eq!(tier("alct", "NIT Calicut"), Some(Tier::Subsequence));
+        assert_eq!(tier("calicutx", "NIT Calicut"), None);
+        assert_eq!(tier("tin", "NIT Calicut"), None);
+    }
+
+    #[test]
+    fn positions_are_char_indices_of_the_text() {
+        let m = fuzzy_match("nitc", "N.I.T Calicut").unwrap();
+        assert_eq!(m.positions, vec![0, 2, 4, 6]);
+        let m = fuzzy_match("calicut", "NIT Calicut").unwrap();
+        assert_eq!(m.positions, (4..11).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn word_boundary_backtracks() {
+        // Taking "coc" from "Cochin" would leave no word for "ol".
+        let m = fuzzy_match("cocol", "Cochin College").unwrap();
+        assert_eq!(m.tier, Tier::WordBoundary);
+        assert_eq!(m.positions, vec![0, 1, 7, 8, 9]);
+    }
+
+    #[test]
+    fn ranking() {
+        let key = "gec";
+        let mut texts = vec![
+            "Sree Gokulam Engineering College",
+            "Government Engineering College Thrissur",
+            "GEC Thrissur",
+            "Govt Model Engineering College, Cochin",
+        ];
+        texts.sort_by_key(|text| std::cmp::Reverse(fuzzy_match(key, text)));
+        assert_eq!(
+            texts,
+            vec![
+                "GEC Thrissur",
+                "Government Engineering College Thrissur",
+                "Govt Model Engineering College, Cochin",
+                "Sree Gokulam Engineering College",
+            ]
+        );
+    }
+
+    #[test]
+    fn tighter_match_ranks_higher() {
+        let loose = fuzzy_match("nc", "NIT Calicut").unwrap();
+        let tight = fuzzy_match("nc", "NT Calicut").unwrap();
+        assert_eq!(loose.tier, tight.tier);
+        assert!(tight > loose);
+    }
+
+    #[test]
+    fn highlight_escapes_and_bolds() {
+        assert_eq!(
+            highlight("A&B College", &[0, 4, 5]),
+            "<b>A</b>&amp;B <b>Co</b>llege"
+        );
+        assert_eq!(highlight("NIT", &[1, 2]), "N<b>IT</b>");
+    }
+}
@@ -1,22 +1,20 @@
 use super::main_view::View;
+use super::recent_activity::{RecentActivity, RECENT_ACTIVITY_LIMIT};
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::{find_participant, parse_participant_query, run_with_desk, CONNECTION_LOST};
 use crate::repository::*;
-use glib;
 use gtk;
 use gtk::prelude::*;
 use std::cell::Cell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct Home {
     ui: HomeUI,
+    recent_activity: RecentActivity,
     reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
     callback: Box<dyn Fn(Message)>,
-}
-
-pub enum Message {
-    VerifyReg(Participant, Box<dyn IRegDesk>),
-    RagamReg(Box<dyn IRegDesk>),
-    KaloReg(Box<dyn IRegDesk>),
-    Logout(Box<dyn IRegDesk>)
+    edition: u16,
 }
 
 ui_struct! {
@@ -25,21 +23,32 @@ ui_struct! {
         ragam_id: gtk::Entry,
         ragam_id_not_found: gtk::Label,
         search: gtk::Button,
-        ragam_reg: gtk::Button,
-        kalo_reg: gtk::Button,
+        recent_activity: gtk::ListBox,
+        recent_activity_empty: gtk::Label,
+        category_buttons: gtk::Box,
+        team_reg: gtk::Button,
+        dashboard: gtk::Button,
+        merge_colleges: gtk::Button,
         logout: gtk::Button
     }
 }
 
 impl Home {
-    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
         let glade_src = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ui/home.glade"));
         let builder = gtk::Builder::new_from_string(glade_src);
 
+        let ui = HomeUI::build(builder);
         let home = Home {
-            ui: HomeUI::build(builder),
+            recent_activity: RecentActivity::new(
+                ui.recent_activity.clone(),
+                ui.recent_activity_empty.clone(),
+                edition,
+            ),
+            ui,
             reg_desk: Cell::from(None),
             callback,
+            edition,
         };
 
         let ret = Rc::from(home);
@@ -55,6 +64,20 @@ impl Home {
     fn initialize_callbacks(this: Rc<Self>) {
         let this_weak = Rc::downgrade(&this);
 
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().expect("Home.ui.root: Reference to Home dropped unexpectedly.");
+            this.load_categories(this_weak.clone());
+        }});
+
+        this.recent_activity.connect_activated(clone! {this_weak => move |participant| {
+            let this = this_weak.upgrade().expect("Home.ui.recent_activity: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::VerifyReg(participant), reg_desk));
+        }});
+
         this.ui
             .ragam_id
             .connect_activate(clone! {this_weak => move |_| {
@@ -65,61 +88,135 @@ impl Home {
         this.ui.search.connect_clicked(clone! {this_weak => move |_| {
             let this = this_weak.upgrade().expect("Home.ui.search: Reference to Home dropped unexpectedly.");
             let ragam_id_text = this.ui.ragam_id.get_text().unwrap();
-            let mut ragam_id = ragam_id_text.as_str();
-
-            if ragam_id.starts_with("R19") || ragam_id.starts_with("K19") {
-                ragam_id = &ragam_id[3..];
-            }
-
-            let ragam_id = ragam_id.parse::<i32>();
-            if ragam_id.is_err() {
-                this.state_ragam_id_invalid();
-                return;
-            }
+            let query = match parse_participant_query(ragam_id_text.as_str(), this.edition) {
+                Some(query) => query,
+                None => {
+                    this.state_ragam_id_invalid();
+                    return;
+                }
+            };
 
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
             this.state_searching_participant();
 
-            let ragam_id = ragam_id.unwrap();
-
-            let reg_desk = this.reg_desk.take().expect(concat!(line!(), "Home: reg_desk is None"));
-            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-            std::thread::spawn(move || {
-                let participant = reg_desk.participant_get(ragam_id);
-                tx.send((participant, reg_desk))
-            });
-
             let this = this.clone();
-            rx.attach(None, move |(participant, reg_desk)| {
-                if let Some(participant) = participant {
-                    this.state_default();
-                    (this.callback)(Message::VerifyReg(participant, reg_desk));
-                } else {
-                    this.state_ragam_id_not_found();
-                    this.reg_desk.set(Some(reg_desk))
+            run_with_desk(reg_desk, |reg_desk| find_participant(reg_desk, query), move |participant, reg_desk| {
+                match participant {
+                    Some(Ok(participant)) => {
+                        this.state_default();
+                        (this.callback)(Message::Open(Route::VerifyReg(participant), reg_desk));
+                    }
+                    Some(Err(message)) => {
+                        this.state_ragam_id_not_found(message);
+                        this.reg_desk.set(Some(reg_desk))
+                    }
+                    None => {
+                        this.state_ragam_id_not_found(CONNECTION_LOST);
+                        this.reg_desk.set(Some(reg_desk))
+                    }
                 }
-                glib::source::Continue(false)
             });
         }});
 
-        this.ui.ragam_reg.connect_clicked(clone! (this_weak => move|_| {
-            let this = this_weak.upgrade().expect("Home.ui.ragam_reg: Reference to Home dropped unexpectedly.");
-            let reg_desk = this.reg_desk.take().expect("Home: reg_desk is None");
-            (this.callback)(Message::RagamReg(reg_desk));
+        this.ui.team_reg.connect_clicked(clone! (this_weak => move|_| {
+            let this = this_weak.upgrade().expect("Home.ui.team_reg: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::TeamReg, reg_desk));
+        }));
+
+        this.ui.dashboard.connect_clicked(clone! (this_weak => move|_| {
+            let this = this_weak.upgrade().expect("Home.ui.dashboard: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::Dashboard, reg_desk));
         }));
 
-        this.ui.kalo_reg.connect_clicked(clone! (this_weak => move|_| {
-            let this = this_weak.upgrade().expect("Home.ui.kalo_reg: Reference to Home dropped unexpectedly.");
-            let reg_desk = this.reg_desk.take().expect("Home: reg_desk is None");
-            (this.callback)(Message::KaloReg(reg_desk));
+        this.ui.merge_colleges.connect_clicked(clone! (this_weak => move|_| {
+            let this = this_weak.upgrade().expect("Home.ui.merge_colleges: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::MergeColleges, reg_desk));
         }));
 
         this.ui.logout.connect_clicked(clone! (this_weak => move|_| {
             let this = this_weak.upgrade().expect("Home.ui.logout: Reference to Home dropped unexpectedly.");
-            let reg_desk = this.reg_desk.take().expect("Home: reg_desk is None");
-            (this.callback)(Message::Logout(reg_desk));
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::ShiftReport, reg_desk));
         }));
     }
 
+    /// Adds a registration button for each active category, and lists the
+    /// participants this desk handled last.
+    fn load_categories(&self, this_weak: Weak<Self>) {
+        let reg_desk = match self.reg_desk.take() {
+            Some(reg_desk) => reg_desk,
+            // A search or load is under way.
+            None => return,
+        };
+        self.state_loading();
+
+        let work = |reg_desk: &mut dyn IRegDesk| {
+            let categories = reg_desk.category_list();
+            let activity = reg_desk.desk_recent_activity(RECENT_ACTIVITY_LIMIT);
+            (categories, activity)
+        };
+        run_with_desk(reg_desk, work, move |loaded, reg_desk| {
+            let this = this_weak.upgrade().expect("Home: Reference to Home dropped unexpectedly.");
+            this.reg_desk.set(Some(reg_desk));
+            let (categories, activity) = match loaded {
+                Some(loaded) => loaded,
+                None => {
+                    this.state_ragam_id_not_found(CONNECTION_LOST);
+                    return;
+                }
+            };
+            this.recent_activity.load(activity);
+            let buttons = &this.ui.category_buttons;
+            buttons.foreach(|child| buttons.remove(child));
+            for category in categories.into_iter().filter(|c| c.active) {
+                let button = gtk::Button::new_with_label(&format!("{} Registration", category.name));
+                // Found by the new registration shortcuts.
+                WidgetExt::set_name(&button, &category.prefix);
+                button.connect_clicked(clone! {this_weak => move |_| {
+                    let this = this_weak.upgrade().expect("Home.ui.category_buttons: Reference to Home dropped unexpectedly.");
+                    let reg_desk = match this.reg_desk.take() {
+                        Some(reg_desk) => reg_desk,
+                        None => return,
+                    };
+                    (this.callback)(Message::Open(Route::NewReg(category.clone()), reg_desk));
+                }});
+                button.show();
+                buttons.add(&button);
+            }
+            this.state_default();
+        });
+    }
+
+    fn state_loading(&self) {
+        self.ui.ragam_id_not_found.set_opacity(0.0);
+        self.ui.ragam_id.set_sensitive(false);
+        self.ui.search.set_sensitive(false);
+        self.ui.category_buttons.set_sensitive(false);
+        self.recent_activity.set_sensitive(false);
+        self.ui.team_reg.set_sensitive(false);
+        self.ui.dashboard.set_sensitive(false);
+        self.ui.merge_colleges.set_sensitive(false);
+        self.ui.logout.set_sensitive(false);
+    }
+
     fn state_searching_participant(&self) {
         self.ui
             .ragam_id_not_found
@@ -127,34 +224,50 @@ impl Home {
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(false);
         self.ui.search.set_sensitive(false);
-        self.ui.ragam_reg.set_sensitive(false);
-        self.ui.kalo_reg.set_sensitive(false);
+        self.ui.category_buttons.set_sensitive(false);
+        self.recent_activity.set_sensitive(false);
+        self.ui.team_reg.set_sensitive(false);
+        self.ui.dashboard.set_sensitive(false);
+        self.ui.merge_colleges.set_sensitive(false);
+        self.ui.logout.set_sensitive(false);
     }
 
     fn state_default(&self) {
         self.ui.ragam_id_not_found.set_opacity(0.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
-        self.ui.ragam_reg.set_sensitive(true);
-        self.ui.kalo_reg.set_sensitive(true);
+        self.ui.category_buttons.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.team_reg.set_sensitive(true);
+        self.ui.dashboard.set_sensitive(true);
+        self.ui.merge_colleges.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 
-    fn state_ragam_id_not_found(&self) {
-        self.ui.ragam_id_not_found.set_text("Ragam ID not found");
+    fn state_ragam_id_not_found(&self, message: &str) {
+        self.ui.ragam_id_not_found.set_text(message);
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
-        self.ui.ragam_reg.set_sensitive(true);
-        self.ui.kalo_reg.set_sensitive(true);
+        self.ui.category_buttons.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.team_reg.set_sensitive(true);
+        self.ui.dashboard.set_sensitive(true);
+        self.ui.merge_colleges.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 
     fn state_ragam_id_invalid(&self) {
-        self.ui.ragam_id_not_found.set_text("Ragam ID invalid");
+        self.ui.ragam_id_not_found.set_text("Enter a Ragam ID or phone number");
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
-        self.ui.ragam_reg.set_sensitive(true);
-        self.ui.kalo_reg.set_sensitive(true);
+        self.ui.category_buttons.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.team_reg.set_sensitive(true);
+        self.ui.dashboard.set_sensitive(true);
+        self.ui.merge_colleges.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 }
 
@@ -162,4 +275,43 @@ impl View for Home {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            // The backend checks again when merging.
+            self.ui
+                .merge_colleges
+                .set_visible(reg_desk.session().supervisor);
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Search => shortcuts::focus(&self.ui.ragam_id),
+            Shortcut::NewRegistration(prefix) => {
+                let button = self
+                    .ui
+                    .category_buttons
+                    .get_children()
+                    .into_iter()
+                    .filter_map(|child| child.downcast::<gtk::Button>().ok())
+                    .find(|button| WidgetExt::get_name(button).as_deref() == Some(prefix));
+                match button {
+                    Some(button) => shortcuts::click(&button),
+                    None => false,
+                }
+            }
+            Shortcut::Logout => shortcuts::click(&self.ui.logout),
+            _ => false,
+        }
+    }
 }
@@ -1,19 +1,20 @@
 use super::main_view::View;
+use super::recent_activity::{RecentActivity, RECENT_ACTIVITY_LIMIT};
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::{find_participant, parse_participant_query, run_with_desk, CONNECTION_LOST};
 use crate::repository::*;
-use glib;
 use gtk;
 use gtk::prelude::*;
 use std::cell::Cell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct HomeHospi {
     ui: HomeHospiUI,
+    recent_activity: RecentActivity,
     reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
     callback: Box<dyn Fn(Message)>,
-}
-
-pub enum Message {
-    RegHospi(Participant, Box<dyn IRegDesk>),
+    edition: u16,
 }
 
 ui_struct! {
@@ -21,19 +22,29 @@ ui_struct! {
         root: gtk::Widget,
         ragam_id: gtk::Entry,
         ragam_id_not_found: gtk::Label,
-        search: gtk::Button
+        search: gtk::Button,
+        recent_activity: gtk::ListBox,
+        recent_activity_empty: gtk::Label,
+        logout: gtk::Button
     }
 }
 
 impl HomeHospi {
-    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
         let glade_src = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ui/home_hospi.glade"));
         let builder = gtk::Builder::new_from_string(glade_src);
 
+        let ui = HomeHospiUI::build(builder);
         let home = Self {
-            ui: HomeHospiUI::build(builder),
+            recent_activity: RecentActivity::new(
+                ui.recent_activity.clone(),
+                ui.recent_activity_empty.clone(),
+                edition,
+            ),
+            ui,
             reg_desk: Cell::from(None),
             callback,
+            edition,
         };
 
         let ret = Rc::from(home);
@@ -49,6 +60,20 @@ impl HomeHospi {
     fn initialize_callbacks(this: Rc<Self>) {
         let this_weak = Rc::downgrade(&this);
 
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().expect("HomeHospi.ui.root: Reference to Home dropped unexpectedly.");
+            this.load_recent_activity(this_weak.clone());
+        }});
+
+        this.recent_activity.connect_activated(clone! {this_weak => move |participant| {
+            let this = this_weak.upgrade().expect("HomeHospi.ui.recent_activity: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::HospiReg(participant), reg_desk));
+        }});
+
         this.ui
             .ragam_id
             .connect_activate(clone! {this_weak => move |_| {
@@ -59,42 +84,77 @@ impl HomeHospi {
         this.ui.search.connect_clicked(clone! {this_weak => move |_| {
             let this = this_weak.upgrade().expect("HomeHospi.ui.search: Reference to Home dropped unexpectedly.");
             let ragam_id_text = this.ui.ragam_id.get_text().unwrap();
-            let mut ragam_id = ragam_id_text.as_str();
-
-            if ragam_id.starts_with("R19") {
-                ragam_id = &ragam_id[3..];
-            }
-
-            let ragam_id = ragam_id.parse::<i32>();
-            if ragam_id.is_err() {
-                this.state_ragam_id_invalid();
-                return;
-            }
+            let query = match parse_participant_query(ragam_id_text.as_str(), this.edition) {
+                Some(query) => query,
+                None => {
+                    this.state_ragam_id_invalid();
+                    return;
+                }
+            };
 
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
             this.state_searching_participant();
 
-            let ragam_id = ragam_id.unwrap();
-
-            let reg_desk = this.reg_desk.take().expect(concat!(line!(), "HomeHospi: reg_desk is None"));
-            let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-            std::thread::spawn(move || {
-                let participant = reg_desk.participant_get(ragam_id);
-                tx.send((participant, reg_desk))
-            });
-
             let this = this.clone();
-            rx.attach(None, move |(participant, reg_desk)| {
-                if let Some(participant) = participant {
-                    this.state_default();
-                    (this.callback)(Message::RegHospi(participant, reg_desk));
-                } else {
-                    this.state_ragam_id_not_found();
-                    this.reg_desk.set(Some(reg_desk))
+            run_with_desk(reg_desk, |reg_desk| find_participant(reg_desk, query), move |participant, reg_desk| {
+                match participant {
+                    Some(Ok(participant)) => {
+                        this.state_default();
+                        (this.callback)(Message::Open(Route::HospiReg(participant), reg_desk));
+                    }
+                    Some(Err(message)) => {
+                        this.state_ragam_id_not_found(message);
+                        this.reg_desk.set(Some(reg_desk))
+                    }
+                    None => {
+                        this.state_ragam_id_not_found(CONNECTION_LOST);
+                        this.reg_desk.set(Some(reg_desk))
+                    }
                 }
-                glib::source::Continue(false)
             });
         }});
 
+        this.ui.logout.connect_clicked(clone! (this_weak => move|_| {
+            let this = this_weak.upgrade().expect("HomeHospi.ui.logout: Reference to Home dropped unexpectedly.");
+            let reg_desk = match this.reg_desk.take() {
+                Some(reg_desk) => reg_desk,
+                None => return,
+            };
+            (this.callback)(Message::Open(Route::ShiftReport, reg_desk));
+        }));
+    }
+
+    fn load_recent_activity(&self, this_weak: Weak<Self>) {
+        let reg_desk = match self.reg_desk.take() {
+            Some(reg_desk) => reg_desk,
+            // A search or load is under way.
+            None => return,
+        };
+        self.state_loading();
+
+        let work = |reg_desk: &mut dyn IRegDesk| reg_desk.desk_recent_activity(RECENT_ACTIVITY_LIMIT);
+        run_with_desk(reg_desk, work, move |activity, reg_desk| {
+            let this = this_weak.upgrade().expect("HomeHospi: Reference to Home dropped unexpectedly.");
+            this.reg_desk.set(Some(reg_desk));
+            match activity {
+                Some(activity) => {
+                    this.recent_activity.load(activity);
+                    this.state_default();
+                }
+                None => this.state_ragam_id_not_found(CONNECTION_LOST),
+            }
+        });
+    }
+
+    fn state_loading(&self) {
+        self.ui.ragam_id_not_found.set_opacity(0.0);
+        self.ui.ragam_id.set_sensitive(false);
+        self.ui.search.set_sensitive(false);
+        self.recent_activity.set_sensitive(false);
+        self.ui.logout.set_sensitive(false);
     }
 
     fn state_searching_participant(&self) {
@@ -104,26 +164,34 @@ impl HomeHospi {
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(false);
         self.ui.search.set_sensitive(false);
+        self.recent_activity.set_sensitive(false);
+        self.ui.logout.set_sensitive(false);
     }
 
     fn state_default(&self) {
         self.ui.ragam_id_not_found.set_opacity(0.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 
-    fn state_ragam_id_not_found(&self) {
-        self.ui.ragam_id_not_found.set_text("Ragam ID not found");
+    fn state_ragam_id_not_found(&self, message: &str) {
+        self.ui.ragam_id_not_found.set_text(message);
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 
     fn state_ragam_id_invalid(&self) {
-        self.ui.ragam_id_not_found.set_text("Ragam ID invalid");
+        self.ui.ragam_id_not_found.set_text("Enter a Ragam ID or phone number");
         self.ui.ragam_id_not_found.set_opacity(1.0);
         self.ui.ragam_id.set_sensitive(true);
         self.ui.search.set_sensitive(true);
+        self.recent_activity.set_sensitive(true);
+        self.ui.logout.set_sensitive(true);
     }
 }
 
@@ -131,4 +199,26 @@ impl View for HomeHospi {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Search => shortcuts::focus(&self.ui.ragam_id),
+            Shortcut::Logout => shortcuts::click(&self.ui.logout),
+            _ => false,
+        }
+    }
 }
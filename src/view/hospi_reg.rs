@@ -1,31 +1,51 @@
 use super::main_view::View;
+use super::participant_code;
+use super::photo::show_thumbnail;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::verify_reg::{payment_error_to_str, payment_mode_to_str};
+use super::{run_with_desk, CONNECTION_LOST};
 use crate::repository::*;
-use glib;
+use chrono::{Duration, Local, NaiveDate};
 use gtk;
 use gtk::prelude::*;
 use std::cell::Cell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct HospiReg {
     ui: HospiRegUI,
     participant: Cell<Option<Participant>>,
     reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
     callback: Box<dyn Fn(Message)>,
-}
-
-pub enum Message {
-    Back(Participant, Box<dyn IRegDesk>),
+    edition: u16,
 }
 
 ui_struct! {
     struct HospiRegUI {
         root: gtk::Widget,
         ragam_id: gtk::Label,
+        photo: gtk::Image,
         name: gtk::Label,
         college: gtk::Label,
         reg_status: gtk::Label,
         hostel: gtk::Entry,
         room: gtk::Entry,
+        arrival: gtk::Entry,
+        departure: gtk::Entry,
+        stay_box: gtk::Box,
+        tariff: gtk::Label,
+        nights: gtk::Label,
+        stay_status: gtk::Label,
+        charge: gtk::Label,
+        paid: gtk::Label,
+        due: gtk::Label,
+        check_in: gtk::Button,
+        check_out: gtk::Button,
+        amount: gtk::Entry,
+        payment_mode: gtk::ComboBoxText,
+        reference: gtk::Entry,
+        collect: gtk::Button,
+        stay_error: gtk::Label,
         saved_successfully: gtk::Label,
         back: gtk::Button,
         save: gtk::Button
@@ -33,7 +53,7 @@ ui_struct! {
 }
 
 impl HospiReg {
-    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
         let glade_src = include_str!(concat!(
             env!("CARGO_MANIFEST_DIR"),
             "/src/ui/hospi_reg.glade"
@@ -45,6 +65,7 @@ impl HospiReg {
             callback,
             participant: Cell::from(None),
             reg_desk: Cell::from(None),
+            edition,
         });
 
         Self::initialize_callbacks(ret.clone());
@@ -59,6 +80,8 @@ impl HospiReg {
     ) {
         self.state_default();
         self.load_participant(&participant);
+        // The photo is loaded once the screen is shown; see connect_map.
+        show_thumbnail(&self.ui.photo, None);
         self.participant.replace(Some(participant));
         self.reg_desk.replace(Some(reg_desk));
     }
@@ -66,46 +89,257 @@ impl HospiReg {
     fn initialize_callbacks(this: Rc<Self>) {
         let this_weak = Rc::downgrade(&this);
 
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.load_photo(this_weak.clone());
+        }});
+
         this.ui
             .save
             .connect_clicked(clone! {this_weak => move |_|{
                 let this = this_weak.upgrade().unwrap();
                 this.state_saving();
 
-                let mut reg_desk = this.reg_desk.take().unwrap();
+                let reg_desk = this.reg_desk.take().unwrap();
                 let participant = this.participant.take().unwrap();
 
                 let hostel = this.ui.hostel.get_text().unwrap().as_str().to_owned();
                 let room = this.ui.room.get_text().unwrap().as_str().to_owned();
+                let (arrival, departure) = match this.dates_from_fields() {
+                    Ok(dates) => dates,
+                    Err(message) => {
+                        this.participant.set(Some(participant));
+                        this.reg_desk.set(Some(reg_desk));
+                        this.state_default();
+                        this.show_stay_error(message);
+                        return;
+                    }
+                };
 
-
-                let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                std::thread::spawn(move || {
-                    tx.send((reg_desk.participant_update_hospi(participant, &hostel, &room), reg_desk))
-                });
-
+                let allocated = participant.clone();
+                let work = move |reg_desk: &mut dyn IRegDesk| {
+                    reg_desk.participant_update_hospi(allocated, &hostel, &room, arrival, departure)
+                };
                 let this_weak = this_weak.clone();
-                rx.attach(None, move |(participant, reg_desk)| {
+                run_with_desk(reg_desk, work, move |allocated, reg_desk| {
                     let this = this_weak.upgrade().unwrap();
-                    this.load_participant(&participant);
-                    this.participant.set(Some(participant));
+                    let allocated = match allocated {
+                        Some(Ok(allocated)) => allocated,
+                        failed => {
+                            this.participant.set(Some(participant));
+                            this.reg_desk.set(Some(reg_desk));
+                            this.state_default();
+                            this.show_stay_error(match failed {
+                                Some(Err(ref err)) => hospitality_error_to_str(err),
+                                _ => CONNECTION_LOST,
+                            });
+                            return;
+                        }
+                    };
+                    let last_action = reg_desk.last_action();
+                    this.load_participant(&allocated);
+                    this.participant.set(Some(allocated));
                     this.reg_desk.set(Some(reg_desk));
                     this.state_saved();
-                    glib::source::Continue(false)
+                    if let Some(last_action) = last_action {
+                        (this.callback)(Message::Undoable(last_action));
+                    }
                 });
             }});
 
+        this.ui.check_in.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.change_stay(this_weak.clone(), |reg_desk, id| reg_desk.hospitality_check_in(id));
+        }});
+
+        this.ui.check_out.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.change_stay(this_weak.clone(), |reg_desk, id| reg_desk.hospitality_check_out(id));
+        }});
+
+        this.ui.collect.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.collect(this_weak.clone());
+        }});
+
         this.ui.back.connect_clicked(clone!{this_weak => move |_| {
             let this = this_weak.upgrade().unwrap();
-            (this.callback)(Message::Back(this.participant.take().unwrap(), this.reg_desk.take().unwrap()));
+            (this.callback)(Message::Back(this.reg_desk.take().unwrap()));
         }});
     }
 
+    fn load_photo(&self, this_weak: Weak<Self>) {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+        let participant_id = {
+            let participant = self.participant.take().unwrap();
+            let id = participant.id();
+            self.participant.set(Some(participant));
+            id
+        };
+
+        let work = move |reg_desk: &mut dyn IRegDesk| reg_desk.participant_thumbnail(participant_id);
+        run_with_desk(reg_desk, work, move |thumbnail, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            show_thumbnail(&this.ui.photo, thumbnail.flatten().as_deref());
+            this.reg_desk.set(Some(reg_desk));
+            this.state_default();
+            let participant = this.participant.take().unwrap();
+            this.load_participant(&participant);
+            this.participant.set(Some(participant));
+        });
+    }
+
+    /// Runs a check in or check out and reloads the stay with its result.
+    fn change_stay<F>(&self, this_weak: Weak<Self>, change: F)
+    where
+        F: FnOnce(&mut dyn IRegDesk, i32) -> Result<Participant, HospitalityError>
+            + Send
+            + 'static,
+    {
+        self.state_saving();
+        let reg_desk = self.reg_desk.take().unwrap();
+        let participant = self.participant.take().unwrap();
+
+        let participant_id = participant.id();
+        let work = move |reg_desk: &mut dyn IRegDesk| change(reg_desk, participant_id);
+        run_with_desk(reg_desk, work, move |result, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            this.state_default();
+            match result {
+                Some(Ok(participant)) => {
+                    this.load_participant(&participant);
+                    this.participant.set(Some(participant));
+                }
+                Some(Err(err)) => {
+                    this.participant.set(Some(participant));
+                    this.show_stay_error(hospitality_error_to_str(&err));
+                }
+                None => {
+                    this.participant.set(Some(participant));
+                    this.show_stay_error(CONNECTION_LOST);
+                }
+            }
+        });
+    }
+
+    fn collect(&self, this_weak: Weak<Self>) {
+        let info = match self.payment_from_fields() {
+            Ok(info) => info,
+            Err(message) => return self.show_stay_error(message),
+        };
+        self.state_saving();
+        let reg_desk = self.reg_desk.take().unwrap();
+        let participant = self.participant.take().unwrap();
+
+        let participant_id = participant.id();
+        let work = move |reg_desk: &mut dyn IRegDesk| {
+            let result = reg_desk.hospitality_collect(participant_id, info);
+            let participant = reg_desk.participant_get(participant_id).unwrap();
+            (result, participant)
+        };
+        run_with_desk(reg_desk, work, move |collected, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            this.state_default();
+            let (result, participant) = match collected {
+                Some(collected) => collected,
+                None => {
+                    this.participant.set(Some(participant));
+                    this.show_stay_error(CONNECTION_LOST);
+                    return;
+                }
+            };
+            this.load_participant(&participant);
+            this.participant.set(Some(participant));
+            match result {
+                Ok(payment) => {
+                    this.ui.amount.set_text("");
+                    this.ui.reference.set_text("");
+                    this.ui.stay_error.set_text(&format!(
+                        "Receipt {}: \u{20b9}{} by {}",
+                        payment.receipt_no,
+                        payment.info.amount,
+                        payment_mode_to_str(payment.info.mode)
+                    ));
+                    this.ui.stay_error.set_opacity(1.0);
+                }
+                Err(err) => this.show_stay_error(&payment_error_to_str(&err)),
+            }
+        });
+    }
+
+    fn dates_from_fields(&self) -> Result<(NaiveDate, NaiveDate), &'static str> {
+        let parse = |entry: &gtk::Entry| {
+            NaiveDate::parse_from_str(entry.get_text().unwrap().trim(), "%Y-%m-%d")
+        };
+        let arrival = parse(&self.ui.arrival).map_err(|_| "Enter the arrival as YYYY-MM-DD")?;
+        let departure =
+            parse(&self.ui.departure).map_err(|_| "Enter the departure as YYYY-MM-DD")?;
+        if departure <= arrival {
+            return Err("Departure must be after arrival");
+        }
+        Ok((arrival, departure))
+    }
+
+    fn payment_from_fields(&self) -> Result<PaymentInfo, &'static str> {
+        let amount = self
+            .ui
+            .amount
+            .get_text()
+            .unwrap()
+            .trim()
+            .parse()
+            .map_err(|_| "Enter the amount in rupees")?;
+        let mode = match self.ui.payment_mode.get_active_id().as_ref().map(|id| id.as_str()) {
+            Some("upi") => PaymentMode::Upi,
+            Some("card") => PaymentMode::Card,
+            _ => PaymentMode::Cash,
+        };
+        Ok(PaymentInfo {
+            amount,
+            mode,
+            reference: self.ui.reference.get_text().unwrap().to_string(),
+        })
+    }
+
+    fn show_stay_error(&self, message: &str) {
+        self.ui.stay_error.set_text(message);
+        self.ui.stay_error.set_opacity(1.0);
+    }
+
+    fn load_stay(&self, stay: &HospitalityVerified) {
+        self.ui.tariff.set_text(&format!("\u{20b9}{} per night", stay.tariff));
+        self.ui.nights.set_text(&match stay.actual_nights() {
+            Some(nights) => format!("{} (planned {})", nights, stay.planned_nights()),
+            None => stay.planned_nights().to_string(),
+        });
+        self.ui.stay_status.set_text(&match (stay.checked_in, stay.checked_out) {
+            (_, Some(out)) => format!("Checked out on {}", out),
+            (Some(checked_in), None) => format!("Checked in on {}", checked_in),
+            (None, None) => String::from("Not checked in"),
+        });
+        self.ui.charge.set_text(&format!("\u{20b9}{}", stay.charge()));
+        self.ui.paid.set_text(&format!("\u{20b9}{}", stay.paid));
+        self.ui.due.set_text(&match stay.amount_due() {
+            due if due < 0 => format!("\u{20b9}{} to refund", -due),
+            due => format!("\u{20b9}{}", due),
+        });
+        self.ui.check_in.set_visible(stay.checked_in.is_none());
+        self.ui
+            .check_out
+            .set_visible(stay.checked_in.is_some() && stay.checked_out.is_none());
+        self.ui.stay_box.show();
+    }
+
     fn load_participant(&self, participant: &Participant) {
-        let id = format!("R19{:06}", participant.id());
-        self.ui.ragam_id.set_text(&id);
+        self.ui.stay_error.set_opacity(0.0);
+        self.ui
+            .ragam_id
+            .set_text(&participant_code(participant, self.edition));
         self.ui.name.set_text(&participant.info.name);
-        self.ui.college.set_text(&participant.college.name);
+        self.ui.college.set_text(&participant.college.label());
         match participant.hospitality {
             Some(ref hospi_regd) => {
                 self.ui
@@ -117,6 +351,9 @@ impl HospiReg {
                 self.ui
                     .room
                     .set_text(&hospi_regd.room);
+                self.ui.arrival.set_text(&hospi_regd.arrival.to_string());
+                self.ui.departure.set_text(&hospi_regd.departure.to_string());
+                self.load_stay(hospi_regd);
             },
             None => {
                 self.ui
@@ -128,8 +365,21 @@ impl HospiReg {
                 self.ui
                     .room
                     .set_text("");
+                let today = Local::today().naive_local();
+                self.ui.arrival.set_text(&today.to_string());
+                self.ui
+                    .departure
+                    .set_text(&(today + Duration::days(1)).to_string());
+                self.ui.stay_box.hide();
             }
         }
+        if !participant.info.category.hospitality {
+            self.ui.reg_status.set_text(&format!(
+                "{} participants do not get hospitality",
+                participant.info.category.name
+            ));
+            self.ui.save.set_sensitive(false);
+        }
     }
 
     fn state_default(&self) {
@@ -139,7 +389,32 @@ impl HospiReg {
             back,
             save,
             hostel,
-            room
+            room,
+            arrival,
+            departure,
+            check_in,
+            check_out,
+            amount,
+            payment_mode,
+            reference,
+            collect
+        });
+    }
+
+    fn state_busy(&self) {
+        set_sensitive!(false, self.ui{
+            back,
+            save,
+            hostel,
+            room,
+            arrival,
+            departure,
+            check_in,
+            check_out,
+            amount,
+            payment_mode,
+            reference,
+            collect
         });
     }
 
@@ -149,7 +424,15 @@ impl HospiReg {
             back,
             save,
             hostel,
-            room
+            room,
+            arrival,
+            departure,
+            check_in,
+            check_out,
+            amount,
+            payment_mode,
+            reference,
+            collect
         });
     }
 
@@ -159,7 +442,15 @@ impl HospiReg {
             back,
             save,
             hostel,
-            room
+            room,
+            arrival,
+            departure,
+            check_in,
+            check_out,
+            amount,
+            payment_mode,
+            reference,
+            collect
         });
         self.ui.saved_successfully.set_opacity(1.0);
         let saved_successfully = self.ui.saved_successfully.clone();
@@ -174,4 +465,36 @@ impl View for HospiReg {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let (Route::HospiReg(participant), Some(reg_desk)) = (route, reg_desk) {
+            self.set_participant_and_reg_desk(participant, reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Save => shortcuts::click(&self.ui.save),
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
+}
+
+fn hospitality_error_to_str(err: &HospitalityError) -> &'static str {
+    match err {
+        HospitalityError::NotAllocated => "Save the room allocation first",
+        HospitalityError::AlreadyCheckedIn => "Already checked in",
+        HospitalityError::NotCheckedIn => "Not checked in yet",
+        HospitalityError::AlreadyCheckedOut => "Already checked out",
+        HospitalityError::NoHospitality => "This category has no hospitality",
+    }
 }
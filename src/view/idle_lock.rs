@@ -0,0 +1,155 @@
+use super::login::login_error_message;
+use super::{run_in_background, CONNECTION_LOST};
+use crate::repository::*;
+use gdk;
+use glib;
+use gtk;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const IDLE_CHECK_INTERVAL_MS: u32 = 10_000;
+
+/// Covers the window with a password prompt once nobody has touched the
+/// desk for a while. The current screen stays loaded underneath, so
+/// unlocking resumes exactly where the admin left off.
+pub struct IdleLock {
+    ui: IdleLockUI,
+    login_db: Arc<dyn ILogin>,
+    session: RefCell<Option<Session>>,
+    last_activity: Rc<Cell<Instant>>,
+    timeout: Duration,
+}
+
+ui_struct! {
+    struct IdleLockUI {
+        screens: gtk::Stack,
+        lock_message: gtk::Label,
+        lock_password: gtk::Entry,
+        unlock: gtk::Button,
+        unlock_error: gtk::Label
+    }
+}
+
+impl IdleLock {
+    /// Does nothing until a session is set. A zero timeout never locks.
+    pub fn new(builder: gtk::Builder, login_db: Arc<dyn ILogin>, timeout: Duration) -> Rc<Self> {
+        let ret = Rc::from(IdleLock {
+            ui: IdleLockUI::build(builder),
+            login_db,
+            session: RefCell::from(None),
+            last_activity: Rc::from(Cell::from(Instant::now())),
+            timeout,
+        });
+
+        Self::initialize_callbacks(ret.clone());
+        ret.ui.unlock_error.set_opacity(0.0);
+
+        ret
+    }
+
+    pub fn set_session(&self, session: Option<Session>) {
+        self.last_activity.set(Instant::now());
+        self.session.replace(session);
+        self.ui.screens.set_visible_child_name("view");
+    }
+
+    fn initialize_callbacks(this: Rc<Self>) {
+        let this_weak = Rc::downgrade(&this);
+
+        let last_activity = this.last_activity.clone();
+        gdk::Event::set_handler(Some(move |event: &mut gdk::Event| {
+            match event.get_event_type() {
+                gdk::EventType::KeyPress
+                | gdk::EventType::ButtonPress
+                | gdk::EventType::MotionNotify
+                | gdk::EventType::Scroll => last_activity.set(Instant::now()),
+                _ => {}
+            }
+            gtk::main_do_event(event);
+        }));
+
+        if this.timeout > Duration::from_secs(0) {
+            glib::timeout_add_local(IDLE_CHECK_INTERVAL_MS, clone! {this_weak => move || {
+                let this = match this_weak.upgrade() {
+                    Some(this) => this,
+                    None => return glib::source::Continue(false),
+                };
+                if this.session.borrow().is_some()
+                    && !this.is_locked()
+                    && this.last_activity.get().elapsed() >= this.timeout
+                {
+                    this.lock();
+                }
+                glib::source::Continue(true)
+            }});
+        }
+
+        this.ui.unlock.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.unlock(this_weak.clone());
+        }});
+
+        this.ui.lock_password.connect_activate(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.unlock(this_weak.clone());
+        }});
+    }
+
+    fn is_locked(&self) -> bool {
+        self.ui.screens.get_visible_child_name().as_ref().map(|n| n.as_str()) == Some("lock")
+    }
+
+    fn lock(&self) {
+        let name = match *self.session.borrow() {
+            Some(ref session) => session.admin.name.clone(),
+            None => return,
+        };
+        self.ui.lock_message.set_text(&format!(
+            "Locked after {} minutes without activity. Enter the password for {} to continue.",
+            self.timeout.as_secs() / 60,
+            name
+        ));
+        self.ui.lock_password.set_text("");
+        self.ui.unlock_error.set_opacity(0.0);
+        self.ui.screens.set_visible_child_name("lock");
+        self.ui.lock_password.grab_focus();
+    }
+
+    fn unlock(&self, this_weak: Weak<Self>) {
+        let session_id = match *self.session.borrow() {
+            Some(ref session) => session.id,
+            None => return,
+        };
+        let password = self.ui.lock_password.get_text().unwrap().to_string();
+        set_sensitive!(false, self.ui{unlock, lock_password});
+        self.ui.unlock_error.set_opacity(0.0);
+
+        let login_db = self.login_db.clone();
+        let work = move || login_db.session_unlock(session_id, &password);
+        run_in_background(work, move |unlocked| {
+            let this = this_weak.upgrade().unwrap();
+            set_sensitive!(true, this.ui{unlock, lock_password});
+            this.ui.lock_password.set_text("");
+            match unlocked {
+                Some(Ok(())) => {
+                    this.last_activity.set(Instant::now());
+                    this.ui.screens.set_visible_child_name("view");
+                }
+                Some(Err(LoginError::UnknownUser)) => {
+                    this.show_unlock_error("This session has ended. Restart to log in again.")
+                }
+                Some(Err(err)) => this.show_unlock_error(&login_error_message(&err)),
+                None => this.show_unlock_error(CONNECTION_LOST),
+            }
+        });
+    }
+
+    fn show_unlock_error(&self, message: &str) {
+        self.ui.unlock_error.set_text(message);
+        self.ui.unlock_error.set_opacity(1.0);
+        self.ui.lock_password.grab_focus();
+    }
+}
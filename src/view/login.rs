@@ -1,21 +1,18 @@
 use super::main_view::View;
+use super::router::{Message, Route};
+use super::run_in_background;
 use crate::repository::*;
 use gtk;
 use gtk::prelude::*;
 use std::rc::Rc;
 use std::sync::Arc;
-use std::thread;
 
 pub struct Login {
     ui: LoginUI,
     callback: Box<dyn Fn(Message)>,
     // Fixme: Arc -> Box
     login_db: Arc<dyn ILogin>,
-}
-
-pub enum Message {
-    LoginSuccessRegDesk(Box<dyn IRegDesk>),
-    LoginSuccessHospi(Box<dyn IRegDesk>),
+    desk_id: String,
 }
 
 ui_struct! {
@@ -26,12 +23,17 @@ ui_struct! {
         password: gtk::Entry,
         hospitality: gtk::RadioButton,
         reg_desk: gtk::RadioButton,
-        login_btn: gtk::Button
+        login_btn: gtk::Button,
+        login_error: gtk::Label
     }
 }
 
 impl Login {
-    pub fn new(callback: Box<dyn Fn(Message)>, login_db: Arc<dyn ILogin>) -> Rc<Login> {
+    pub fn new(
+        callback: Box<dyn Fn(Message)>,
+        login_db: Arc<dyn ILogin>,
+        desk_id: &str,
+    ) -> Rc<Login> {
         let glade_src = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ui/login.glade"));
         let builder = gtk::Builder::new_from_string(glade_src);
         let ui = LoginUI::build(builder);
@@ -39,6 +41,7 @@ impl Login {
             ui,
             callback,
             login_db,
+            desk_id: desk_id.to_owned(),
         };
         let ret = Rc::from(ret);
         Self::initialize_callbacks(ret.clone());
@@ -69,27 +72,30 @@ impl Login {
 
                 this.state_logging_in();
 
-                let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                {
+                let work = {
                     let username = String::from(username.as_str());
                     let password = String::from(password.as_str());
                     let login_db = this.login_db.clone();
-                    thread::spawn(move || tx.send(login_db.login_reg_desk(&username, &password)));
-                }
+                    let desk_id = this.desk_id.clone();
+                    move || login_db.login_reg_desk(&desk_id, &username, &password)
+                };
 
-                rx.attach(
-                    None,
-                    clone!{ this_weak => move |reg_desk: Result<Box<dyn IRegDesk>, ()>| {
+                run_in_background(
+                    work,
+                    clone!{ this_weak => move |reg_desk: Option<Result<Box<dyn IRegDesk>, LoginError>>| {
                         let this = this_weak.upgrade().unwrap();
-                        if let Ok(reg_desk) = reg_desk {
-                            if this.ui.reg_desk.get_active() {
-                                (this.callback)(Message::LoginSuccessRegDesk(reg_desk));
-                            } else {
-                                (this.callback)(Message::LoginSuccessHospi(reg_desk));
+                        match reg_desk.unwrap_or(Err(LoginError::Unreachable)) {
+                            Ok(reg_desk) => {
+                                this.state_default();
+                                this.ui.password.set_text("");
+                                if this.ui.reg_desk.get_active() {
+                                    (this.callback)(Message::LoggedIn(Route::Home, reg_desk));
+                                } else {
+                                    (this.callback)(Message::LoggedIn(Route::HomeHospi, reg_desk));
+                                }
                             }
+                            Err(err) => this.state_error(&err),
                         }
-                        this.state_default();
-                        glib::source::Continue(false)
                     }},
                 );
             }});
@@ -107,6 +113,7 @@ impl Login {
         });
         self.ui.progress_bar.set_fraction(0.0);
         self.ui.progress_bar.set_opacity(1.0);
+        self.ui.login_error.set_opacity(0.0);
 
         set_sensitive!(false, self.ui{
             username,
@@ -127,10 +134,39 @@ impl Login {
             login_btn
         });
     }
+
+    fn state_error(&self, err: &LoginError) {
+        self.state_default();
+        let message = login_error_message(err);
+        self.ui.login_error.set_text(&message);
+        self.ui.login_error.set_opacity(1.0);
+        self.ui.password.set_text("");
+        self.ui.password.grab_focus();
+    }
 }
 
 impl View for Login {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, _route: Route, _reg_desk: Option<Box<dyn IRegDesk>>) {}
+}
+
+/// Also shown when unlocking an idle desk fails.
+pub fn login_error_message(err: &LoginError) -> String {
+    match err {
+        LoginError::UnknownUser => String::from("No admin with that username."),
+        LoginError::WrongPassword { attempts_left } => format!(
+            "Wrong password. {} more attempt{} before the account is locked.",
+            attempts_left,
+            if *attempts_left == 1 { "" } else { "s" }
+        ),
+        LoginError::Disabled => String::from("This account is disabled."),
+        LoginError::Locked(remaining) => format!(
+            "Too many failed attempts. Try again in {} minutes.",
+            remaining.as_secs().div_ceil(60)
+        ),
+        LoginError::Unreachable => String::from("Could not reach the database."),
+    }
 }
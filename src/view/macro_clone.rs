@@ -1,5 +1,4 @@
 #[macro_export]
-
 macro_rules! clone {
     (@param _) => ( _ );
     (@param $x:ident) => ( $x );
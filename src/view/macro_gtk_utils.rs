@@ -1,5 +1,4 @@
 #[macro_export]
-
 macro_rules! set_sensitive {
     ($val:literal, $p:expr) => {
         $p.set_sensitive($val);
@@ -1,5 +1,3 @@
-#[macro_export]
-
 /**
  * Macro to add a constructor method named `build`
  * which takes a gtk::Builder and initialize the members
@@ -7,6 +5,7 @@
  * All members must have a corresponding widget in the UI file loaded into Builder,
  * such that the name of the member is the id of the widget.
  */
+#[macro_export]
 macro_rules! ui_struct {
     (struct $struct_name:ident {
         $($i:ident: $t:ty),+
@@ -1,39 +1,306 @@
+use super::idle_lock::IdleLock;
+use super::participant_code;
+use super::router::Route;
+use super::shortcuts::{self, Shortcut, HELP_ACCEL, SHORTCUTS};
+use crate::repository::{ILogin, IRegDesk, LastAction, LastCall, Session, UndoableAction};
+use gdk;
+use glib;
 use gtk;
 use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+
+const HEALTH_CHECK_INTERVAL_MS: u32 = 5000;
+/// How long a failed undo is explained for.
+const UNDO_ERROR_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct MainView {
-    app_container: gtk::Container,
-    last_widget: Option<gtk::Widget>,
+    view_container: gtk::Container,
+    idle_lock: Rc<IdleLock>,
+    connection_status: gtk::Label,
+    undo_bar: gtk::Widget,
+    undo_message: gtk::Label,
+    undo: gtk::Button,
+    // Bumped whenever the undo bar changes, so that the timeout of an earlier
+    // message does not hide a later one.
+    undo_shown: Rc<Cell<u32>>,
+    edition: u16,
+    // Shared with the shortcut handler on the window.
+    current: Rc<RefCell<Option<Rc<dyn View>>>>,
 }
 
 pub trait View {
     fn get_root_widget(&self) -> &gtk::Widget;
+
+    /// Sets the view up to show `route`, just before it is loaded. The
+    /// session is `None` only on the login screen.
+    fn enter(&self, route: Route, reg_desk: Option<Box<dyn IRegDesk>>);
+
+    /// Takes the desk session from the view, for work done outside it such
+    /// as undoing the last action. `None` if the view has no session or is
+    /// busy with it.
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        None
+    }
+
+    /// Hands back a session taken with `take_reg_desk`.
+    fn put_reg_desk(&self, _reg_desk: Box<dyn IRegDesk>) {}
+
+    /// Acts on a keyboard shortcut. Returns whether the screen has it, so
+    /// that other keys reach the focused widget.
+    fn shortcut(&self, _shortcut: Shortcut) -> bool {
+        false
+    }
+}
+
+enum ConnectionStatus {
+    /// With how long the last call took, once one has been made.
+    Connected(Option<Duration>),
+    Reconnecting,
+    Offline,
 }
 
 impl MainView {
-    pub fn new() -> Self {
+    pub fn new(
+        edition: u16,
+        desk_id: &str,
+        desk_name: &str,
+        login_db: Arc<dyn ILogin>,
+        idle_timeout: Duration,
+    ) -> Self {
         let glade_src = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ui/app.glade"));
 
         let builder = gtk::Builder::new_from_string(glade_src);
         let window: gtk::Window = builder.get_object("app_window").unwrap();
-        let app_container: gtk::Container = builder.get_object("app_container").unwrap();
+        let view_container: gtk::Container = builder.get_object("view_container").unwrap();
+        let connection_status: gtk::Label = builder.get_object("connection_status").unwrap();
+        let undo_bar: gtk::Widget = builder.get_object("undo_bar").unwrap();
+        let undo_message: gtk::Label = builder.get_object("undo_message").unwrap();
+        let undo: gtk::Button = builder.get_object("undo").unwrap();
+        let app_title: gtk::Label = builder.get_object("app_title").unwrap();
+        app_title.set_text(&format!("Ragam {}", edition));
+        window.set_title(&format!("Ragam {} - {} [{}]", edition, desk_name, desk_id));
         window.show_all();
         window.connect_destroy(|_| {
             gtk::main_quit();
         });
 
+        let shortcuts_help: gtk::Window = builder.get_object("shortcuts_help").unwrap();
+        let shortcuts_grid: gtk::Grid = builder.get_object("shortcuts_grid").unwrap();
+        fill_shortcuts_help(&shortcuts_grid);
+        let current: Rc<RefCell<Option<Rc<dyn View>>>> = Rc::from(RefCell::from(None));
+        connect_shortcuts(&window, &shortcuts_help, &view_container, &current);
+
+        let idle_lock = IdleLock::new(builder, login_db, idle_timeout);
+
         Self {
-            app_container,
-            last_widget: None,
+            view_container,
+            idle_lock,
+            connection_status,
+            undo_bar,
+            undo_message,
+            undo,
+            undo_shown: Rc::from(Cell::from(0)),
+            edition,
+            current,
+        }
+    }
+
+    pub fn load(&mut self, view: Rc<dyn View>) {
+        if let Some(current) = self.current.borrow().as_ref() {
+            self.view_container.remove(current.get_root_widget());
         }
+
+        self.view_container.add(view.get_root_widget());
+        self.current.replace(Some(view));
+    }
+
+    /// The view on screen.
+    pub fn current(&self) -> Option<Rc<dyn View>> {
+        self.current.borrow().clone()
+    }
+
+    /// Keeps the view on screen from being used while work is done with its
+    /// session elsewhere.
+    pub fn set_busy(&self, busy: bool) {
+        self.view_container.set_sensitive(!busy);
+    }
+
+    pub fn connect_undo(&self, f: Box<dyn Fn()>) {
+        self.undo.connect_clicked(move |_| f());
+    }
+
+    /// Offers to undo the action, on whichever screen is shown, until its
+    /// time runs out.
+    pub fn show_undo(&self, action: &LastAction) {
+        let done = match action.action {
+            UndoableAction::Verify => "Verified",
+            UndoableAction::Update => "Updated",
+            UndoableAction::Allocate => "Allocated a room to",
+        };
+        let message = format!(
+            "{} {} {}",
+            done,
+            participant_code(&action.participant, self.edition),
+            action.participant.info.name
+        );
+        self.show_undo_bar(&message, true, action.remaining);
+    }
+
+    /// Explains why an undo did not happen.
+    pub fn show_undo_error(&self, message: &str) {
+        self.show_undo_bar(message, false, UNDO_ERROR_TIMEOUT);
+    }
+
+    pub fn hide_undo(&self) {
+        self.undo_shown.set(self.undo_shown.get() + 1);
+        self.undo_bar.hide();
+    }
+
+    fn show_undo_bar(&self, message: &str, can_undo: bool, timeout: Duration) {
+        let shown = self.undo_shown.get() + 1;
+        self.undo_shown.set(shown);
+        self.undo_message.set_text(message);
+        self.undo.set_visible(can_undo);
+        self.undo_bar.show();
+
+        let undo_bar = self.undo_bar.clone();
+        let undo_shown = self.undo_shown.clone();
+        glib::timeout_add_local(timeout.as_millis() as u32, move || {
+            if undo_shown.get() == shown {
+                undo_bar.hide();
+            }
+            glib::source::Continue(false)
+        });
+    }
+
+    /// The session the idle lock asks the password for. `None` after logout.
+    pub fn set_session(&self, session: Option<Session>) {
+        self.idle_lock.set_session(session);
     }
 
-    pub fn load(&mut self, view: &dyn View) {
-        if let Some(child) = &self.last_widget {
-            self.app_container.remove(child);
+    /// Pings the backend periodically and shows how long it took to answer,
+    /// reconnecting when it does not.
+    pub fn watch_connection(&self, login_db: Arc<dyn ILogin>) {
+        let label = self.connection_status.clone();
+        let checking = Rc::from(Cell::from(false));
+        check_connection(&label, &login_db, &checking);
+        glib::timeout_add_local(HEALTH_CHECK_INTERVAL_MS, move || {
+            check_connection(&label, &login_db, &checking);
+            glib::source::Continue(true)
+        });
+    }
+}
+
+/// Catches the shortcuts on the window, before the focused widget sees the
+/// keys, and passes them to the view on screen.
+fn connect_shortcuts(
+    window: &gtk::Window,
+    help: &gtk::Window,
+    view_container: &gtk::Container,
+    current: &Rc<RefCell<Option<Rc<dyn View>>>>,
+) {
+    window.connect_key_press_event(clone! {help, view_container, current => move |_, event| {
+        if shortcuts::matches(event, HELP_ACCEL) {
+            help.show();
+            return glib::signal::Inhibit(true);
+        }
+        // The view is hidden while the desk is locked.
+        if !view_container.get_mapped() {
+            return glib::signal::Inhibit(false);
+        }
+        let view = current.borrow().clone();
+        match (view, shortcuts::shortcut_for(event)) {
+            (Some(view), Some(shortcut)) => glib::signal::Inhibit(view.shortcut(shortcut)),
+            _ => glib::signal::Inhibit(false),
+        }
+    }});
+
+    help.connect_key_press_event(|help, event| {
+        if event.get_keyval() == gdk::enums::key::Escape || shortcuts::matches(event, HELP_ACCEL) {
+            help.hide();
+            glib::signal::Inhibit(true)
+        } else {
+            glib::signal::Inhibit(false)
         }
+    });
+    help.connect_delete_event(|help, _| {
+        help.hide();
+        glib::signal::Inhibit(true)
+    });
+}
 
-        self.app_container.add(view.get_root_widget());
-        self.last_widget.replace(view.get_root_widget().clone());
+fn fill_shortcuts_help(grid: &gtk::Grid) {
+    let rows = SHORTCUTS
+        .iter()
+        .map(|(_, accel, description)| (*accel, *description))
+        .chain(std::iter::once((HELP_ACCEL, "Show this help")));
+    for (row, (accel, description)) in rows.enumerate() {
+        let key = gtk::Label::new(Some(shortcuts::accel_label(accel).as_str()));
+        key.set_halign(gtk::Align::Start);
+        key.get_style_context().add_class("dim-label");
+        let description = gtk::Label::new(Some(description));
+        description.set_halign(gtk::Align::Start);
+        grid.attach(&key, 0, row as i32, 1, 1);
+        grid.attach(&description, 1, row as i32, 1, 1);
     }
+    grid.show_all();
+}
+
+fn check_connection(label: &gtk::Label, login_db: &Arc<dyn ILogin>, checking: &Rc<Cell<bool>>) {
+    // The previous check, possibly a slow reconnect, is still running.
+    if checking.replace(true) {
+        return;
+    }
+
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let login_db = login_db.clone();
+    std::thread::spawn(move || {
+        let connected = login_db.ping() || {
+            let _ = tx.send(ConnectionStatus::Reconnecting);
+            login_db.reconnect()
+        };
+        let status = if connected {
+            // The ping or reconnect is itself timed.
+            match login_db.last_call() {
+                Some(LastCall::Done(latency)) => ConnectionStatus::Connected(Some(latency)),
+                _ => ConnectionStatus::Connected(None),
+            }
+        } else {
+            ConnectionStatus::Offline
+        };
+        let _ = tx.send(status);
+    });
+
+    let label = label.clone();
+    let checking = checking.clone();
+    rx.attach(None, move |status| {
+        show_connection_status(&label, &status);
+        match status {
+            ConnectionStatus::Reconnecting => glib::source::Continue(true),
+            _ => {
+                checking.set(false);
+                glib::source::Continue(false)
+            }
+        }
+    });
+}
+
+fn show_connection_status(label: &gtk::Label, status: &ConnectionStatus) {
+    let markup = match status {
+        ConnectionStatus::Connected(Some(latency)) => format!(
+            "<span foreground=\"#4e9a06\">●</span> Connected · {} ms",
+            latency.as_millis()
+        ),
+        ConnectionStatus::Connected(None) => {
+            String::from("<span foreground=\"#4e9a06\">●</span> Connected")
+        }
+        ConnectionStatus::Reconnecting => {
+            String::from("<span foreground=\"#c4a000\">●</span> Reconnecting..")
+        }
+        ConnectionStatus::Offline => String::from("<span foreground=\"#cc0000\">●</span> Offline"),
+    };
+    label.set_markup(&markup);
 }
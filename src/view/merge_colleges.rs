@@ -0,0 +1,273 @@
+use super::main_view::View;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::{run_with_desk, CONNECTION_LOST};
+use crate::repository::*;
+use gtk;
+use gtk::prelude::*;
+use std::cell::Cell;
+use std::rc::{Rc, Weak};
+
+/// Lists colleges that were probably entered more than once and folds each
+/// group into the college picked to keep.
+pub struct MergeColleges {
+    ui: MergeCollegesUI,
+    reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
+    callback: Box<dyn Fn(Message)>,
+}
+
+ui_struct! {
+    struct MergeCollegesUI {
+        root: gtk::Widget,
+        groups: gtk::ListBox,
+        history: gtk::ListBox,
+        status: gtk::Label,
+        back: gtk::Button
+    }
+}
+
+// A college in a group: the radio button picks it as the one to keep, the
+// check button includes it in the merge.
+type GroupRow = (College, gtk::RadioButton, gtk::CheckButton);
+
+impl MergeColleges {
+    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+        let glade_src = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/ui/merge_colleges.glade"
+        ));
+        let builder = gtk::Builder::new_from_string(glade_src);
+
+        let ret = Rc::from(MergeColleges {
+            ui: MergeCollegesUI::build(builder),
+            reg_desk: Cell::from(None),
+            callback,
+        });
+
+        Self::initialize_callbacks(ret.clone());
+
+        ret
+    }
+
+    pub fn set_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+        self.ui.status.set_opacity(0.0);
+    }
+
+    fn initialize_callbacks(this: Rc<Self>) {
+        let this_weak = Rc::downgrade(&this);
+
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.run(this_weak.clone(), None);
+        }});
+
+        this.ui.back.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            let reg_desk = this.reg_desk.take().unwrap();
+            (this.callback)(Message::Back(reg_desk));
+        }});
+    }
+
+    /// Merges if asked to, then reloads the groups and the history.
+    fn run(&self, this_weak: Weak<Self>, merge: Option<(i32, Vec<i32>)>) {
+        let reg_desk = self.reg_desk.take().unwrap();
+        self.state_busy();
+
+        let work = move |reg_desk: &mut dyn IRegDesk| {
+            let result = merge.map(|(into, merged)| reg_desk.college_merge(into, &merged));
+            let groups = reg_desk.college_duplicates();
+            let history = reg_desk.college_merge_history();
+            (result, groups, history)
+        };
+        run_with_desk(reg_desk, work, move |loaded, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            let (result, groups, history) = match loaded {
+                Some(loaded) => loaded,
+                None => {
+                    this.show_status(CONNECTION_LOST);
+                    this.state_default();
+                    return;
+                }
+            };
+            this.load_groups(this_weak.clone(), groups);
+            this.load_history(&history);
+            match result {
+                Some(Ok(merges)) => {
+                    let participants: i64 = merges.iter().map(|m| m.participants).sum();
+                    let into = merges.first().map(|m| m.into.name.as_str()).unwrap_or("");
+                    this.show_status(&format!(
+                        "Merged {} colleges into {}, moving {} participants",
+                        merges.len(),
+                        into,
+                        participants
+                    ));
+                }
+                Some(Err(err)) => this.show_status(merge_error_to_str(&err)),
+                None => {}
+            }
+            this.state_default();
+        });
+    }
+
+    fn load_groups(&self, this_weak: Weak<Self>, groups: Vec<Vec<CollegeUsage>>) {
+        let list = &self.ui.groups;
+        list.foreach(|child| list.remove(child));
+        if groups.is_empty() {
+            self.show_status("No colleges look like duplicates");
+        }
+        for group in groups {
+            let row = gtk::Box::new(gtk::Orientation::Vertical, 5);
+            row.set_margin_top(5);
+            row.set_margin_bottom(5);
+
+            let mut rows: Vec<GroupRow> = Vec::new();
+            for usage in &group {
+                let label = format!(
+                    "{} ({} participants)",
+                    usage.college.label(),
+                    usage.participants
+                );
+                let keep = match rows.first() {
+                    Some((_, first, _)) => gtk::RadioButton::new_with_label_from_widget(first, &label),
+                    None => gtk::RadioButton::new_with_label(&label),
+                };
+                let merge = gtk::CheckButton::new_with_label("Merge");
+                merge.set_active(true);
+                // The college kept is never merged away.
+                merge.set_sensitive(!rows.is_empty());
+                keep.connect_toggled(clone! {merge => move |keep| {
+                    merge.set_sensitive(!keep.get_active());
+                }});
+
+                let line = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+                line.pack_start(&keep, true, true, 0);
+                line.pack_end(&merge, false, false, 0);
+                row.add(&line);
+                rows.push((usage.college.clone(), keep, merge));
+            }
+
+            let merge_button = gtk::Button::new_with_label("Merge into Selected");
+            merge_button.set_halign(gtk::Align::End);
+            merge_button.connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                this.confirm_merge(this_weak.clone(), &rows);
+            }});
+            row.add(&merge_button);
+            row.show_all();
+            list.add(&row);
+        }
+    }
+
+    fn confirm_merge(&self, this_weak: Weak<Self>, rows: &[GroupRow]) {
+        let into = match rows.iter().find(|(_, keep, _)| keep.get_active()) {
+            Some((college, _, _)) => college,
+            None => return,
+        };
+        let merged: Vec<i32> = rows
+            .iter()
+            .filter(|(college, _, merge)| college.id() != into.id() && merge.get_active())
+            .map(|(college, _, _)| college.id())
+            .collect();
+        if merged.is_empty() {
+            self.show_status("Tick the colleges to merge");
+            return;
+        }
+
+        let window = self
+            .ui
+            .root
+            .get_toplevel()
+            .and_then(|w| w.downcast::<gtk::Window>().ok());
+        let dialog = gtk::MessageDialog::new(
+            window.as_ref(),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("Merge {} colleges into {}?", merged.len(), into.name),
+        );
+        dialog.set_property_secondary_text(Some(
+            "Their participants and teams move to the college kept. This cannot be undone.",
+        ));
+        dialog.add_button("Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("Merge", gtk::ResponseType::Accept);
+        let response = dialog.run();
+        dialog.destroy();
+
+        if gtk::ResponseType::from(response) == gtk::ResponseType::Accept {
+            self.run(this_weak, Some((into.id(), merged)));
+        }
+    }
+
+    fn load_history(&self, history: &[CollegeMerge]) {
+        let list = &self.ui.history;
+        list.foreach(|child| list.remove(child));
+        for merge in history {
+            let label = gtk::Label::new(Some(
+                format!(
+                    "{}  {} \u{2192} {}  ({} participants, by {} at {})",
+                    merge.merged_at,
+                    merge.merged,
+                    merge.into.name,
+                    merge.participants,
+                    merge.admin.name,
+                    merge.desk
+                )
+                .as_str(),
+            ));
+            label.set_halign(gtk::Align::Start);
+            label.show();
+            list.add(&label);
+        }
+    }
+
+    fn show_status(&self, message: &str) {
+        self.ui.status.set_text(message);
+        self.ui.status.set_opacity(1.0);
+    }
+
+    fn state_busy(&self) {
+        self.ui.status.set_opacity(0.0);
+        set_sensitive!(false, self.ui{groups, back});
+    }
+
+    fn state_default(&self) {
+        set_sensitive!(true, self.ui{groups, back});
+    }
+}
+
+fn merge_error_to_str(err: &MergeError) -> &'static str {
+    match err {
+        MergeError::UnknownCollege => "A college was already merged at another desk",
+        MergeError::MergeIntoSelf => "The college kept cannot also be merged",
+        MergeError::NotSupervisor => "Only supervisors can merge colleges",
+    }
+}
+
+impl View for MergeColleges {
+    fn get_root_widget(&self) -> &gtk::Widget {
+        &self.ui.root
+    }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
+}
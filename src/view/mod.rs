@@ -6,9 +6,134 @@ mod macro_clone;
 mod macro_gtk_utils;
 
 pub mod create_update_participant;
+pub mod dashboard;
+pub mod event_picker;
+pub mod fuzzy;
 pub mod home;
 pub mod home_hospi;
+pub mod idle_lock;
 pub mod login;
 pub mod main_view;
+pub mod merge_colleges;
+pub mod photo;
+pub mod recent_activity;
+pub mod router;
 pub mod hospi_reg;
+pub mod shift_report;
+pub mod shortcuts;
+pub mod startup_error;
+pub mod team_reg;
 pub mod verify_reg;
+
+use crate::repository::validation::normalise_phone;
+use crate::repository::{IRegDesk, Participant};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Shown when a backend call fails; the connection status says when it is
+/// back.
+pub const CONNECTION_LOST: &str = "Connection lost, try again once it is back";
+
+/// Runs `work` on a worker thread and `done` with its result on the main
+/// loop. A backend call that fails, as on a lost connection, panics; `done`
+/// then gets `None`.
+pub fn run_in_background<T, W, D>(work: W, done: D)
+where
+    T: Send + 'static,
+    W: FnOnce() -> T + Send + 'static,
+    D: FnOnce(Option<T>) + 'static,
+{
+    let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    std::thread::spawn(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(work)).ok();
+        // Nobody is left to tell once the main loop has quit.
+        let _ = tx.send(result);
+    });
+
+    let mut done = Some(done);
+    rx.attach(None, move |result| {
+        if let Some(done) = done.take() {
+            done(result);
+        }
+        glib::source::Continue(false)
+    });
+}
+
+/// `run_in_background` for work with the desk session. The session comes
+/// back to `done` even when the work fails, so that the screen keeps it.
+pub fn run_with_desk<T, W, D>(mut reg_desk: Box<dyn IRegDesk>, work: W, done: D)
+where
+    T: Send + 'static,
+    W: FnOnce(&mut dyn IRegDesk) -> T + Send + 'static,
+    D: FnOnce(Option<T>, Box<dyn IRegDesk>) + 'static,
+{
+    run_in_background(
+        move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| work(&mut *reg_desk))).ok();
+            (result, reg_desk)
+        },
+        move |outcome| {
+            // The session is always sent back; only the work can fail.
+            if let Some((result, reg_desk)) = outcome {
+                done(result, reg_desk);
+            }
+        },
+    );
+}
+
+/// Participant ID as printed on badges: category prefix, last two digits of
+/// the edition year, then the number. For example `R19001234`.
+pub fn participant_code(participant: &Participant, edition: u16) -> String {
+    format!(
+        "{}{:02}{:06}",
+        participant.info.category.prefix,
+        edition % 100,
+        participant.id()
+    )
+}
+
+/// Accepts either a bare number or a full participant code.
+pub fn parse_participant_code(code: &str, edition: u16) -> Option<i32> {
+    let code = code.trim();
+    let year = format!("{:02}", edition % 100);
+    let rest = code.trim_start_matches(|c: char| c.is_ascii_alphabetic());
+    let number = if rest.len() < code.len() && rest.starts_with(&year) {
+        &rest[2..]
+    } else {
+        code
+    };
+    number.parse().ok()
+}
+
+/// What the operator typed into a participant search box.
+pub enum ParticipantQuery {
+    Code(i32),
+    /// A mobile number in E.164 form.
+    Phone(String),
+}
+
+/// Ten digit mobile numbers are too large to be participant numbers, so the
+/// two never clash.
+pub fn parse_participant_query(text: &str, edition: u16) -> Option<ParticipantQuery> {
+    parse_participant_code(text, edition)
+        .map(ParticipantQuery::Code)
+        .or_else(|| normalise_phone(text).map(ParticipantQuery::Phone))
+}
+
+/// Returns the message to show when the search does not find exactly one
+/// participant.
+pub fn find_participant(
+    reg_desk: &dyn IRegDesk,
+    query: ParticipantQuery,
+) -> Result<Participant, &'static str> {
+    match query {
+        ParticipantQuery::Code(id) => reg_desk.participant_get(id).ok_or("Ragam ID not found"),
+        ParticipantQuery::Phone(phone) => {
+            let mut found = reg_desk.participant_find_by_phone(&phone);
+            match found.len() {
+                0 => Err("Phone number not found"),
+                1 => Ok(found.remove(0)),
+                _ => Err("Phone number is shared, search by Ragam ID"),
+            }
+        }
+    }
+}
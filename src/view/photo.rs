@@ -0,0 +1,63 @@
+use crate::repository::{Photo, PHOTO_MAX_SIZE};
+use gdk_pixbuf::{Pixbuf, PixbufLoader, PixbufLoaderExt};
+use gtk;
+use gtk::prelude::*;
+use std::path::Path;
+
+/// Longest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: i32 = 120;
+
+/// Reads an image file and makes its thumbnail.
+pub fn read_photo(path: &Path) -> Result<Photo, &'static str> {
+    let image = std::fs::read(path).map_err(|_| "Could not read the file")?;
+    if image.len() > PHOTO_MAX_SIZE {
+        return Err("The photo must be under 2 MB");
+    }
+    let thumbnail = Pixbuf::new_from_file_at_scale(path, THUMBNAIL_SIZE, THUMBNAIL_SIZE, true)
+        .and_then(|pixbuf| pixbuf.save_to_bufferv("png", &[]))
+        .map_err(|_| "The file is not an image")?;
+    Ok(Photo { image, thumbnail })
+}
+
+/// Shows a thumbnail from the backend, or a placeholder without one.
+pub fn show_thumbnail(image: &gtk::Image, thumbnail: Option<&[u8]>) {
+    let pixbuf = thumbnail.and_then(|bytes| {
+        let loader = PixbufLoader::new();
+        loader.write(bytes).ok()?;
+        loader.close().ok()?;
+        loader.get_pixbuf()
+    });
+    match pixbuf {
+        Some(pixbuf) => image.set_from_pixbuf(&pixbuf),
+        None => image.set_from_icon_name("avatar-default", gtk::IconSize::Dialog),
+    }
+}
+
+/// Lets the operator pick an image file.
+pub fn choose_photo_file(parent: &gtk::Widget) -> Option<std::path::PathBuf> {
+    let window = parent
+        .get_toplevel()
+        .and_then(|w| w.downcast::<gtk::Window>().ok());
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some("Choose Photo"),
+        window.as_ref(),
+        gtk::FileChooserAction::Open,
+        &[
+            ("Cancel", gtk::ResponseType::Cancel),
+            ("Open", gtk::ResponseType::Accept),
+        ],
+    );
+    let filter = gtk::FileFilter::new();
+    filter.set_name("Images");
+    filter.add_pixbuf_formats();
+    dialog.add_filter(&filter);
+    let response = dialog.run();
+    let path = dialog.get_filename();
+    dialog.destroy();
+
+    if gtk::ResponseType::from(response) == gtk::ResponseType::Accept {
+        path
+    } else {
+        None
+    }
+}
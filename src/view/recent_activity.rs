@@ -0,0 +1,82 @@
+use super::participant_code;
+use crate::repository::*;
+use gtk;
+use gtk::prelude::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Participants listed on the home screens.
+pub const RECENT_ACTIVITY_LIMIT: usize = 8;
+
+/// The participants this desk handled last, most recent first. Clicking one
+/// reopens them.
+pub struct RecentActivity {
+    list: gtk::ListBox,
+    empty: gtk::Label,
+    activity: Rc<RefCell<Vec<DeskActivity>>>,
+    edition: u16,
+}
+
+impl RecentActivity {
+    pub fn new(list: gtk::ListBox, empty: gtk::Label, edition: u16) -> Self {
+        RecentActivity {
+            list,
+            empty,
+            activity: Rc::from(RefCell::from(Vec::new())),
+            edition,
+        }
+    }
+
+    pub fn load(&self, activity: Vec<DeskActivity>) {
+        self.list.foreach(|child| self.list.remove(child));
+        self.empty.set_visible(activity.is_empty());
+        for item in &activity {
+            let code = gtk::Label::new(Some(
+                participant_code(&item.participant, self.edition).as_str(),
+            ));
+            let name = gtk::Label::new(Some(item.participant.info.name.as_str()));
+            name.set_halign(gtk::Align::Start);
+            let action = gtk::Label::new(Some(desk_action_to_str(item.action)));
+            // Only the time; the list is of the last few minutes or hours.
+            let at = gtk::Label::new(Some(item.at.rsplit(' ').next().unwrap_or(&item.at)));
+            at.get_style_context().add_class("dim-label");
+
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+            row.set_margin_top(3);
+            row.set_margin_bottom(3);
+            row.pack_start(&code, false, false, 0);
+            row.pack_start(&name, true, true, 0);
+            row.pack_end(&at, false, false, 0);
+            row.pack_end(&action, false, false, 0);
+            row.show_all();
+            self.list.add(&row);
+        }
+        self.activity.replace(activity);
+    }
+
+    /// Calls `f` with the participant of the row clicked.
+    pub fn connect_activated<F: Fn(Participant) + 'static>(&self, f: F) {
+        let activity = self.activity.clone();
+        self.list.connect_row_activated(move |_, row| {
+            let participant = activity
+                .borrow()
+                .get(row.get_index() as usize)
+                .map(|item| item.participant.clone());
+            if let Some(participant) = participant {
+                f(participant);
+            }
+        });
+    }
+
+    pub fn set_sensitive(&self, sensitive: bool) {
+        self.list.set_sensitive(sensitive);
+    }
+}
+
+fn desk_action_to_str(action: DeskAction) -> &'static str {
+    match action {
+        DeskAction::Created => "Registered",
+        DeskAction::Verified => "Verified",
+        DeskAction::Allocated => "Room allocated",
+    }
+}
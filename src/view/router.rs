@@ -0,0 +1,276 @@
+use super::create_update_participant::CreateUpdateParticipant;
+use super::dashboard::Dashboard;
+use super::home::Home;
+use super::home_hospi::HomeHospi;
+use super::hospi_reg::HospiReg;
+use super::login::Login;
+use super::main_view::{MainView, View};
+use super::merge_colleges::MergeColleges;
+use super::shift_report::ShiftReport;
+use super::team_reg::TeamReg;
+use super::verify_reg::VerifyReg;
+use super::{run_with_desk, CONNECTION_LOST};
+use crate::repository::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+/// A screen and what it shows. The router keeps the routes of the screens
+/// passed through, so that Back returns to the previous one.
+#[derive(Clone)]
+pub enum Route {
+    Login,
+    Home,
+    HomeHospi,
+    VerifyReg(Participant),
+    HospiReg(Participant),
+    /// New registration in the category.
+    NewReg(Category),
+    UpdateParticipant(Participant),
+    Dashboard,
+    MergeColleges,
+    TeamReg,
+    ShiftReport,
+}
+
+impl Route {
+    /// The screen showing the route. Routes of one screen share it.
+    fn screen(&self) -> &'static str {
+        match self {
+            Route::Login => "login",
+            Route::Home => "home",
+            Route::HomeHospi => "home_hospi",
+            Route::VerifyReg(_) => "verify_reg",
+            Route::HospiReg(_) => "hospi_reg",
+            Route::NewReg(_) | Route::UpdateParticipant(_) => "create_update",
+            Route::Dashboard => "dashboard",
+            Route::MergeColleges => "merge_colleges",
+            Route::TeamReg => "team_reg",
+            Route::ShiftReport => "shift_report",
+        }
+    }
+}
+
+/// What screens ask of the router.
+pub enum Message {
+    /// Shows the route; Back returns to the screen on display.
+    Open(Route, Box<dyn IRegDesk>),
+    /// Shows the route in place of the screen on display.
+    Replace(Route, Box<dyn IRegDesk>),
+    /// Returns to the screen the one on display was opened from.
+    Back(Box<dyn IRegDesk>),
+    /// Starts a session on the route, which Back never leaves.
+    LoggedIn(Route, Box<dyn IRegDesk>),
+    /// Ends the session and returns to the login screen.
+    Logout(Box<dyn IRegDesk>),
+    /// Offers to undo the action just done.
+    Undoable(LastAction),
+}
+
+/// Shows screens in `MainView`, building each the first time it is needed,
+/// and remembers the way back.
+pub struct Router {
+    main_view: RefCell<MainView>,
+    screens: RefCell<HashMap<&'static str, Rc<dyn View>>>,
+    // The route on display is last.
+    stack: RefCell<Vec<Route>>,
+    login_db: Arc<dyn ILogin>,
+    desk_id: String,
+    edition: u16,
+}
+
+impl Router {
+    /// Starts on the login screen.
+    pub fn new(
+        main_view: MainView,
+        login_db: Arc<dyn ILogin>,
+        desk_id: &str,
+        edition: u16,
+    ) -> Rc<Self> {
+        let this = Rc::from(Router {
+            main_view: RefCell::from(main_view),
+            screens: RefCell::from(HashMap::new()),
+            stack: RefCell::from(vec![Route::Login]),
+            login_db,
+            desk_id: desk_id.to_owned(),
+            edition,
+        });
+
+        let this_weak = Rc::downgrade(&this);
+        this.main_view
+            .borrow()
+            .connect_undo(Box::from(move || {
+                if let Some(this) = this_weak.upgrade() {
+                    Self::undo(&this);
+                }
+            }));
+        Self::show(&this, Route::Login, None);
+
+        this
+    }
+
+    pub fn handle(this: &Rc<Self>, message: Message) {
+        match message {
+            Message::Open(route, reg_desk) => {
+                push(&mut this.stack.borrow_mut(), route.clone());
+                Self::show(this, route, Some(reg_desk));
+            }
+            Message::Replace(route, reg_desk) => {
+                {
+                    let mut stack = this.stack.borrow_mut();
+                    stack.pop();
+                    push(&mut stack, route.clone());
+                }
+                Self::show(this, route, Some(reg_desk));
+            }
+            Message::Back(reg_desk) => {
+                let route = {
+                    let mut stack = this.stack.borrow_mut();
+                    if stack.len() > 1 {
+                        stack.pop();
+                    }
+                    stack.last().cloned().unwrap_or(Route::Login)
+                };
+                Self::show(this, route, Some(reg_desk));
+            }
+            Message::LoggedIn(route, reg_desk) => {
+                this.main_view
+                    .borrow()
+                    .set_session(Some(reg_desk.session().clone()));
+                this.stack.replace(vec![route.clone()]);
+                Self::show(this, route, Some(reg_desk));
+            }
+            Message::Logout(mut reg_desk) => {
+                // Ending the session is a single update; the login screen need not wait for it.
+                std::thread::spawn(move || reg_desk.logout());
+                {
+                    let main_view = this.main_view.borrow();
+                    main_view.hide_undo();
+                    main_view.set_session(None);
+                }
+                this.stack.replace(vec![Route::Login]);
+                Self::show(this, Route::Login, None);
+            }
+            Message::Undoable(last_action) => {
+                this.main_view.borrow().show_undo(&last_action);
+            }
+        }
+    }
+
+    fn show(this: &Rc<Self>, route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        let screen = route.screen();
+        let existing = this.screens.borrow().get(screen).cloned();
+        let view = match existing {
+            Some(view) => view,
+            None => {
+                let view = Self::build(this, &route);
+                this.screens.borrow_mut().insert(screen, view.clone());
+                view
+            }
+        };
+        view.enter(route, reg_desk);
+        this.main_view.borrow_mut().load(view);
+    }
+
+    /// Builds the screen for `route`. Screens are kept once built.
+    fn build(this: &Rc<Self>, route: &Route) -> Rc<dyn View> {
+        let callback = Self::callback(Rc::downgrade(this));
+        let edition = this.edition;
+        match route {
+            Route::Login => Login::new(callback, this.login_db.clone(), &this.desk_id),
+            Route::Home => Home::new(callback, edition),
+            Route::HomeHospi => HomeHospi::new(callback, edition),
+            Route::VerifyReg(_) => VerifyReg::new(callback, edition),
+            Route::HospiReg(_) => HospiReg::new(callback, edition),
+            Route::NewReg(_) | Route::UpdateParticipant(_) => {
+                CreateUpdateParticipant::new(callback, edition)
+            }
+            Route::Dashboard => Dashboard::new(callback),
+            Route::MergeColleges => MergeColleges::new(callback),
+            Route::TeamReg => TeamReg::new(callback, edition),
+            Route::ShiftReport => ShiftReport::new(callback),
+        }
+    }
+
+    fn callback(this_weak: Weak<Self>) -> Box<dyn Fn(Message)> {
+        Box::from(move |message| {
+            let this = this_weak
+                .upgrade()
+                .expect("Router: Reference to Router dropped unexpectedly.");
+            Self::handle(&this, message);
+        })
+    }
+
+    /// Reverses the last action of the session on screen and shows the
+    /// participant as they are now.
+    fn undo(this: &Rc<Self>) {
+        let view = {
+            let main_view = this.main_view.borrow();
+            main_view.hide_undo();
+            main_view.current()
+        };
+        let view = match view {
+            Some(view) => view,
+            None => return,
+        };
+        let reg_desk = match view.take_reg_desk() {
+            Some(reg_desk) => reg_desk,
+            None => {
+                this.main_view
+                    .borrow()
+                    .show_undo_error("Busy; try again in a moment");
+                return;
+            }
+        };
+        this.main_view.borrow().set_busy(true);
+
+        let work = |reg_desk: &mut dyn IRegDesk| {
+            let action = reg_desk.last_action().map(|last_action| last_action.action);
+            (action, reg_desk.undo_last())
+        };
+        let this = this.clone();
+        run_with_desk(reg_desk, work, move |undone, reg_desk| {
+            this.main_view.borrow().set_busy(false);
+            let (action, result) = match undone {
+                Some(undone) => undone,
+                None => {
+                    view.put_reg_desk(reg_desk);
+                    this.main_view.borrow().show_undo_error(CONNECTION_LOST);
+                    return;
+                }
+            };
+            match result {
+                Ok(participant) => {
+                    let route = match action {
+                        Some(UndoableAction::Allocate) => Route::HospiReg(participant),
+                        _ => Route::VerifyReg(participant),
+                    };
+                    Self::handle(&this, Message::Open(route, reg_desk));
+                }
+                Err(err) => {
+                    view.put_reg_desk(reg_desk);
+                    this.main_view
+                        .borrow()
+                        .show_undo_error(undo_error_to_str(&err));
+                }
+            }
+        });
+    }
+}
+
+/// Opening a screen already on the way back goes back to it, so that each
+/// screen is on the stack at most once.
+fn push(stack: &mut Vec<Route>, route: Route) {
+    if let Some(i) = stack.iter().position(|r| r.screen() == route.screen()) {
+        stack.truncate(i);
+    }
+    stack.push(route);
+}
+
+fn undo_error_to_str(err: &UndoError) -> &'static str {
+    match err {
+        UndoError::NothingToUndo => "Nothing to undo; it may have timed out",
+        UndoError::Changed(_) => "Not undone; the participant has changed since",
+    }
+}
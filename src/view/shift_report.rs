@@ -0,0 +1,330 @@
+use super::main_view::View;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::verify_reg::payment_mode_to_str;
+use super::{run_with_desk, CONNECTION_LOST};
+use crate::repository::*;
+use gtk;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+/// Shown on logout so the admin can hand over the cash collected in the
+/// session before leaving the desk.
+pub struct ShiftReport {
+    ui: ShiftReportUI,
+    reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
+    callback: Box<dyn Fn(Message)>,
+    summary: RefCell<Option<ShiftSummary>>,
+}
+
+ui_struct! {
+    struct ShiftReportUI {
+        root: gtk::Widget,
+        admin: gtk::Label,
+        desk: gtk::Label,
+        started_at: gtk::Label,
+        verifications: gtk::Label,
+        hospitality: gtk::Label,
+        payment_count: gtk::Label,
+        expected_cash: gtk::Label,
+        expected_upi: gtk::Label,
+        expected_card: gtk::Label,
+        payments: gtk::ListBox,
+        counted_cash: gtk::Entry,
+        record_count: gtk::Button,
+        discrepancy: gtk::Label,
+        status: gtk::Label,
+        back: gtk::Button,
+        export: gtk::Button,
+        logout: gtk::Button
+    }
+}
+
+impl ShiftReport {
+    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+        let glade_src = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/src/ui/shift_report.glade"
+        ));
+        let builder = gtk::Builder::new_from_string(glade_src);
+
+        let ret = Rc::from(ShiftReport {
+            ui: ShiftReportUI::build(builder),
+            reg_desk: Cell::from(None),
+            callback,
+            summary: RefCell::from(None),
+        });
+
+        Self::initialize_callbacks(ret.clone());
+
+        ret
+    }
+
+    pub fn set_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+        self.summary.replace(None);
+        self.ui.counted_cash.set_text("");
+        self.ui.discrepancy.set_text("");
+        self.ui.status.set_opacity(0.0);
+    }
+
+    fn initialize_callbacks(this: Rc<Self>) {
+        let this_weak = Rc::downgrade(&this);
+
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.load(this_weak.clone(), None);
+        }});
+
+        this.ui.counted_cash.connect_activate(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.ui.record_count.emit_clicked();
+        }});
+
+        this.ui.record_count.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            match this.ui.counted_cash.get_text().unwrap().trim().parse() {
+                Ok(counted) if counted >= 0 => this.load(this_weak.clone(), Some(counted)),
+                _ => this.show_status("Enter the cash counted, in rupees"),
+            }
+        }});
+
+        this.ui.export.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.export();
+        }});
+
+        this.ui.back.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            (this.callback)(Message::Back(this.reg_desk.take().unwrap()));
+        }});
+
+        this.ui.logout.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            (this.callback)(Message::Logout(this.reg_desk.take().unwrap()));
+        }});
+    }
+
+    /// Loads the summary, first recording `counted_cash` if given.
+    fn load(&self, this_weak: Weak<Self>, counted_cash: Option<i64>) {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+
+        let work = move |reg_desk: &mut dyn IRegDesk| match counted_cash {
+            Some(counted) => reg_desk.shift_record_cash(counted),
+            None => reg_desk.shift_summary(),
+        };
+        run_with_desk(reg_desk, work, move |summary, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            let summary = match summary {
+                Some(summary) => summary,
+                None => {
+                    this.show_status(CONNECTION_LOST);
+                    this.state_default();
+                    return;
+                }
+            };
+            this.load_summary(&summary);
+            this.summary.replace(Some(summary));
+            if counted_cash.is_some() {
+                this.show_status("Count recorded");
+            }
+            this.state_default();
+        });
+    }
+
+    fn load_summary(&self, summary: &ShiftSummary) {
+        self.ui.admin.set_text(&summary.session.admin.name);
+        self.ui.desk.set_text(&summary.session.desk);
+        self.ui.started_at.set_text(&summary.started_at);
+        self.ui
+            .verifications
+            .set_text(&summary.verifications.to_string());
+        self.ui
+            .hospitality
+            .set_text(&summary.hospitality_allocations.to_string());
+        self.ui
+            .payment_count
+            .set_text(&summary.payments.len().to_string());
+        self.ui
+            .expected_cash
+            .set_text(&rupees(summary.expected(PaymentMode::Cash)));
+        self.ui
+            .expected_upi
+            .set_text(&rupees(summary.expected(PaymentMode::Upi)));
+        self.ui
+            .expected_card
+            .set_text(&rupees(summary.expected(PaymentMode::Card)));
+
+        self.ui.payments.foreach(|child| self.ui.payments.remove(child));
+        for payment in summary.payments.iter() {
+            let row = gtk::Label::new(Some(payment_line(payment).as_str()));
+            row.set_halign(gtk::Align::Start);
+            row.show();
+            self.ui.payments.add(&row);
+        }
+
+        if let Some(counted) = summary.counted_cash {
+            self.ui.counted_cash.set_text(&counted.to_string());
+        }
+        self.ui
+            .discrepancy
+            .set_text(&discrepancy_text(summary).unwrap_or_default());
+    }
+
+    fn export(&self) {
+        let report = match *self.summary.borrow() {
+            Some(ref summary) => report_text(summary),
+            None => return,
+        };
+        let window = self
+            .ui
+            .root
+            .get_toplevel()
+            .and_then(|w| w.downcast::<gtk::Window>().ok());
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Export Shift Report"),
+            window.as_ref(),
+            gtk::FileChooserAction::Save,
+            &[
+                ("Cancel", gtk::ResponseType::Cancel),
+                ("Save", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name("shift-report.txt");
+        let response = dialog.run();
+        let path = dialog.get_filename();
+        dialog.destroy();
+
+        if gtk::ResponseType::from(response) != gtk::ResponseType::Accept {
+            return;
+        }
+        if let Some(path) = path {
+            match std::fs::write(&path, report) {
+                Ok(()) => self.show_status(&format!("Saved to {}", path.display())),
+                Err(err) => self.show_status(&format!("Could not save the report: {}", err)),
+            }
+        }
+    }
+
+    fn show_status(&self, message: &str) {
+        self.ui.status.set_text(message);
+        self.ui.status.set_opacity(1.0);
+    }
+
+    fn state_busy(&self) {
+        set_sensitive!(false, self.ui{
+            counted_cash,
+            record_count,
+            back,
+            export,
+            logout
+        });
+    }
+
+    fn state_default(&self) {
+        set_sensitive!(true, self.ui{
+            counted_cash,
+            record_count,
+            back,
+            export,
+            logout
+        });
+    }
+}
+
+fn rupees(amount: i64) -> String {
+    format!("\u{20b9}{}", amount)
+}
+
+fn payment_line(payment: &Payment) -> String {
+    let mut line = format!(
+        "{}  {}  {}",
+        payment.receipt_no,
+        rupees(payment.info.amount),
+        payment_mode_to_str(payment.info.mode)
+    );
+    if !payment.info.reference.is_empty() {
+        line += &format!(" ({})", payment.info.reference);
+    }
+        if payment.purpose == PaymentPurpose::Hospitality {
+        line += "  hospitality";
+    }
+    line
+}
+
+fn discrepancy_text(summary: &ShiftSummary) -> Option<String> {
+    Some(match summary.discrepancy()? {
+        0 => String::from("Cash matches"),
+        d if d > 0 => format!("{} over", rupees(d)),
+        d => format!("{} short", rupees(-d)),
+    })
+}
+
+/// Plain text version of the report, for printing or filing.
+fn report_text(summary: &ShiftSummary) -> String {
+    let mut text = format!(
+        "Shift report\n\n\
+         Admin:                   {}\n\
+         Desk:                    {}\n\
+         Started:                 {}\n\
+         Verifications:           {}\n\
+         Hospitality allocations: {}\n\
+         Payments:                {}\n\
+         Expected cash:           {}\n\
+         Expected UPI:            {}\n\
+         Expected card:           {}\n",
+        summary.session.admin.name,
+        summary.session.desk,
+        summary.started_at,
+        summary.verifications,
+        summary.hospitality_allocations,
+        summary.payments.len(),
+        rupees(summary.expected(PaymentMode::Cash)),
+        rupees(summary.expected(PaymentMode::Upi)),
+        rupees(summary.expected(PaymentMode::Card)),
+    );
+    if let Some(counted) = summary.counted_cash {
+        text += &format!("Counted cash:            {}\n", rupees(counted));
+    }
+    if let Some(discrepancy) = discrepancy_text(summary) {
+        text += &format!("Discrepancy:             {}\n", discrepancy);
+    }
+    text += "\nReceipts\n";
+    for payment in summary.payments.iter() {
+        text += &payment_line(payment);
+        text += "\n";
+    }
+    text
+}
+
+impl View for ShiftReport {
+    fn get_root_widget(&self) -> &gtk::Widget {
+        &self.ui.root
+    }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            Shortcut::Logout => shortcuts::click(&self.ui.logout),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use gdk;
+use gtk;
+use gtk::prelude::*;
+
+/// Application-wide keyboard shortcuts. `MainView` catches them on the window
+/// and hands them to the screen on display, which acts on those it has.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Shortcut {
+    /// Focus the participant search field.
+    Search,
+    /// New registration in the category with this prefix.
+    NewRegistration(&'static str),
+    Verify,
+    Save,
+    Back,
+    Logout,
+}
+
+/// Opens and closes the shortcut help.
+pub const HELP_ACCEL: &str = "F1";
+
+/// Each shortcut with its accelerator and description, in the order the help
+/// lists them.
+pub const SHORTCUTS: &[(Shortcut, &str, &str)] = &[
+    (Shortcut::Search, "<Primary>f", "Find a participant"),
+    (
+        Shortcut::NewRegistration("R"),
+        "<Primary>r",
+        "New Ragam registration",
+    ),
+    (
+        Shortcut::NewRegistration("K"),
+        "<Primary>k",
+        "New Kalotsavam registration",
+    ),
+    (Shortcut::Verify, "<Primary>Return", "Verify"),
+    (Shortcut::Save, "<Primary>s", "Save"),
+    (Shortcut::Back, "<Alt>Left", "Back"),
+    (Shortcut::Logout, "<Primary>q", "Log out"),
+];
+
+/// Whether the key pressed is `accel`, ignoring Caps Lock and the like.
+pub fn matches(event: &gdk::EventKey, accel: &str) -> bool {
+    let (key, mods) = gtk::accelerator_parse(accel);
+    gdk::keyval_to_lower(event.get_keyval()) == key
+        && event.get_state() & gtk::accelerator_get_default_mod_mask() == mods
+}
+
+pub fn shortcut_for(event: &gdk::EventKey) -> Option<Shortcut> {
+    SHORTCUTS
+        .iter()
+        .find(|(_, accel, _)| matches(event, accel))
+        .map(|(shortcut, _, _)| *shortcut)
+}
+
+/// How `accel` is shown to the operator, e.g. "Ctrl+F".
+pub fn accel_label(accel: &str) -> String {
+    let (key, mods) = gtk::accelerator_parse(accel);
+    gtk::accelerator_get_label(key, mods)
+        .map(|label| label.as_str().to_owned())
+        .unwrap_or_default()
+}
+
+/// Clicks `button` if the operator could, that is when it is on screen and
+/// sensitive. Returns whether it was clicked.
+pub fn click(button: &gtk::Button) -> bool {
+    let usable = button.get_mapped() && button.is_sensitive();
+    if usable {
+        button.clicked();
+    }
+    usable
+}
+
+/// Moves the focus to `widget` if it is on screen and sensitive.
+pub fn focus<W: IsA<gtk::Widget>>(widget: &W) -> bool {
+    let usable = widget.get_mapped() && widget.is_sensitive();
+    if usable {
+        widget.grab_focus();
+    }
+    usable
+}
@@ -0,0 +1,21 @@
+use gtk;
+use gtk::prelude::*;
+
+/// Shows a startup failure before the main window exists.
+/// Returns true if the operator chose to retry.
+pub fn ask_retry(message: &str) -> bool {
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>,
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Error,
+        gtk::ButtonsType::None,
+        "Could not start the registration desk",
+    );
+    dialog.set_property_secondary_text(Some(message));
+    dialog.add_button("Quit", gtk::ResponseType::Cancel);
+    dialog.add_button("Retry", gtk::ResponseType::Accept);
+    dialog.set_default_response(gtk::ResponseType::Accept);
+    let response = dialog.run();
+    dialog.destroy();
+    gtk::ResponseType::from(response) == gtk::ResponseType::Accept
+}
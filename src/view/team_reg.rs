@@ -0,0 +1,429 @@
+use super::main_view::View;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::{parse_participant_code, participant_code, run_with_desk, CONNECTION_LOST};
+use crate::repository::*;
+use gtk;
+use gtk::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::rc::{Rc, Weak};
+
+/// Registers a group event team in one go: the leader, the members and
+/// verification of everyone's registration. An existing team can be opened
+/// to add or remove members.
+pub struct TeamReg {
+    ui: TeamRegUI,
+    reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
+    callback: Box<dyn Fn(Message)>,
+    // The team being edited, once registered or opened.
+    team: RefCell<Option<Team>>,
+    // Members picked for a team not registered yet, leader excluded.
+    pending: RefCell<Vec<Participant>>,
+    edition: u16,
+}
+
+// Removes a member's row from the team being edited.
+type RemoveMember = Box<dyn Fn(&TeamReg, Weak<TeamReg>)>;
+
+ui_struct! {
+    struct TeamRegUI {
+        root: gtk::Widget,
+        team_id: gtk::Entry,
+        load_team: gtk::Button,
+        team_name: gtk::Entry,
+        event: gtk::ComboBoxText,
+        leader: gtk::Entry,
+        member: gtk::Entry,
+        add_member: gtk::Button,
+        members: gtk::ListBox,
+        status: gtk::Label,
+        register: gtk::Button,
+        back: gtk::Button
+    }
+}
+
+impl TeamReg {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
+        let glade_src = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/ui/team_reg.glade"));
+        let builder = gtk::Builder::new_from_string(glade_src);
+
+        let ret = Rc::from(TeamReg {
+            ui: TeamRegUI::build(builder),
+            reg_desk: Cell::from(None),
+            callback,
+            team: RefCell::from(None),
+            pending: RefCell::from(Vec::new()),
+            edition,
+        });
+
+        Self::initialize_callbacks(ret.clone());
+
+        ret
+    }
+
+    /// Starts a new team.
+    pub fn set_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+        self.team.replace(None);
+        self.pending.borrow_mut().clear();
+        self.ui.team_id.set_text("");
+        self.ui.team_name.set_text("");
+        self.ui.leader.set_text("");
+        self.ui.member.set_text("");
+        self.ui.status.set_opacity(0.0);
+        self.ui.members.foreach(|child| self.ui.members.remove(child));
+        self.state_default();
+    }
+
+    fn initialize_callbacks(this: Rc<Self>) {
+        let this_weak = Rc::downgrade(&this);
+
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.load_events(this_weak.clone());
+        }});
+
+        this.ui.team_id.connect_activate(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.ui.load_team.emit_clicked();
+        }});
+
+        this.ui.load_team.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            let code = this.ui.team_id.get_text().unwrap();
+            let team_id = match parse_team_code(code.as_str()) {
+                Some(team_id) => team_id,
+                None => return this.show_status("Team ID invalid"),
+            };
+            this.run(this_weak.clone(), move |reg_desk| {
+                reg_desk.team_get(team_id).ok_or(TeamError::UnknownTeam)
+            });
+        }});
+
+        this.ui.member.connect_activate(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.ui.add_member.emit_clicked();
+        }});
+
+        this.ui.add_member.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            let code = this.ui.member.get_text().unwrap();
+            let participant_id = match parse_participant_code(code.as_str(), this.edition) {
+                Some(participant_id) => participant_id,
+                None => return this.show_status("Ragam ID invalid"),
+            };
+            this.ui.member.set_text("");
+
+            let team_id = this.team.borrow().as_ref().map(|team| team.id);
+            match team_id {
+                Some(team_id) => this.run(this_weak.clone(), move |reg_desk| {
+                    reg_desk.team_add_member(team_id, participant_id)
+                }),
+                None => this.add_pending(this_weak.clone(), participant_id),
+            }
+        }});
+
+        this.ui.register.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+
+            let team_id = this.team.borrow().as_ref().map(|team| team.id);
+            if let Some(team_id) = team_id {
+                this.run(this_weak.clone(), move |reg_desk| reg_desk.team_verify(team_id));
+                return;
+            }
+
+            let name = this.ui.team_name.get_text().unwrap().to_string();
+            let event_id = this.ui.event.get_active_id().and_then(|id| id.parse().ok());
+            let leader = parse_participant_code(this.ui.leader.get_text().unwrap().as_str(), this.edition);
+            let (event_id, leader) = match (name.is_empty(), event_id, leader) {
+                (false, Some(event_id), Some(leader)) => (event_id, leader),
+                _ => return this.show_status("Enter the team name, event and leader"),
+            };
+            let members: Vec<i32> = this.pending.borrow().iter().map(Participant::id).collect();
+            this.run(this_weak.clone(), move |reg_desk| {
+                let team = reg_desk.team_create(&name, event_id, leader, &members)?;
+                reg_desk.team_verify(team.id)
+            });
+        }});
+
+        this.ui.back.connect_clicked(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            (this.callback)(Message::Back(this.reg_desk.take().unwrap()));
+        }});
+    }
+
+    fn load_events(&self, this_weak: Weak<Self>) {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+
+        run_with_desk(reg_desk, |reg_desk| reg_desk.event_list(), move |events, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            let events = match events {
+                Some(events) => events,
+                None => {
+                    this.reg_desk.set(Some(reg_desk));
+                    this.state_default();
+                    this.show_status(CONNECTION_LOST);
+                    return;
+                }
+            };
+            this.ui.event.remove_all();
+            for event in events.iter() {
+                if let Some((min, max)) = event.team_size {
+                    let label = format!("{}  ({} to {} members)", event.name, min, max);
+                    this.ui.event.append(Some(event.id.to_string().as_str()), &label);
+                }
+            }
+            this.reg_desk.set(Some(reg_desk));
+            this.state_default();
+        });
+    }
+
+    fn add_pending(&self, this_weak: Weak<Self>, participant_id: i32) {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+
+        let work = move |reg_desk: &mut dyn IRegDesk| reg_desk.participant_get(participant_id);
+        run_with_desk(reg_desk, work, move |participant, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            this.state_default();
+            match participant {
+                Some(Some(participant)) => {
+                    let mut pending = this.pending.borrow_mut();
+                    if pending.iter().all(|p| p.id() != participant.id()) {
+                        pending.push(participant);
+                    }
+                }
+                Some(None) => this.show_status("Ragam ID not found"),
+                None => this.show_status(CONNECTION_LOST),
+            }
+            this.show_pending(this_weak.clone());
+        });
+    }
+
+    /// Runs a team operation off the main loop and shows the team it returns.
+    fn run<F>(&self, this_weak: Weak<Self>, op: F)
+    where
+        F: FnOnce(&mut dyn IRegDesk) -> Result<Team, TeamError> + Send + 'static,
+    {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+
+        let work = move |reg_desk: &mut dyn IRegDesk| {
+            let result = op(reg_desk);
+            let members: Vec<Participant> = match result {
+                Ok(ref team) => team
+                    .members
+                    .iter()
+                    .filter_map(|&id| reg_desk.participant_get(id))
+                    .collect(),
+                Err(_) => Vec::new(),
+            };
+            (result, members)
+        };
+        run_with_desk(reg_desk, work, move |outcome, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            this.reg_desk.set(Some(reg_desk));
+            match outcome {
+                Some((Ok(team), members)) => {
+                    this.load_team(this_weak.clone(), &team, &members);
+                    this.team.replace(Some(team));
+                }
+                Some((Err(err), _)) => this.show_status(&this.team_error_to_str(&err)),
+                None => this.show_status(CONNECTION_LOST),
+            }
+            this.state_default();
+        });
+    }
+
+    fn load_team(&self, this_weak: Weak<Self>, team: &Team, members: &[Participant]) {
+        self.ui.team_id.set_text(&team_code(team.id));
+        self.ui.team_name.set_text(&team.name);
+        self.ui.event.set_active_id(Some(team.event.id.to_string().as_str()));
+        if let Some(leader) = members.iter().find(|p| p.id() == team.leader) {
+            self.ui
+                .leader
+                .set_text(&participant_code(leader, self.edition));
+        }
+        let unverified = members.iter().filter(|p| p.reg_status.is_err()).count();
+        self.show_status(&format!(
+            "{} of {}, {} members, {}",
+            team_code(team.id),
+            team.college.name,
+            members.len(),
+            if unverified == 0 {
+                String::from("all verified")
+            } else {
+                format!("{} not verified", unverified)
+            }
+        ));
+
+        self.ui.members.foreach(|child| self.ui.members.remove(child));
+        for member in members {
+            let is_leader = member.id() == team.leader;
+            let team_id = team.id;
+            let participant_id = member.id();
+            let remove: RemoveMember = Box::from(move |this: &Self, this_weak| {
+                this.run(this_weak, move |reg_desk| {
+                    reg_desk.team_remove_member(team_id, participant_id)
+                })
+            });
+            self.add_member_row(this_weak.clone(), member, is_leader, remove);
+        }
+    }
+
+    fn show_pending(&self, this_weak: Weak<Self>) {
+        self.ui.members.foreach(|child| self.ui.members.remove(child));
+        for member in self.pending.borrow().iter() {
+            let participant_id = member.id();
+            let remove: RemoveMember = Box::from(move |this: &Self, this_weak| {
+                this.pending
+                    .borrow_mut()
+                    .retain(|p| p.id() != participant_id);
+                this.show_pending(this_weak);
+            });
+            self.add_member_row(this_weak.clone(), member, false, remove);
+        }
+    }
+
+    /// The leader's row has no remove button.
+    fn add_member_row(
+        &self,
+        this_weak: Weak<Self>,
+        member: &Participant,
+        is_leader: bool,
+        remove: RemoveMember,
+    ) {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 10);
+        let name = gtk::Label::new(Some(
+            format!(
+                "{}  {}{}",
+                participant_code(member, self.edition),
+                member.info.name,
+                if is_leader { " (leader)" } else { "" }
+            )
+            .as_str(),
+        ));
+        name.set_halign(gtk::Align::Start);
+        row.pack_start(&name, true, true, 0);
+        let status = match member.reg_status {
+            Ok(_) => "Verified",
+            Err(_) => "Not verified",
+        };
+        if !is_leader {
+            let button = gtk::Button::new_with_label("Remove");
+            button.connect_clicked(move |_| {
+                let this = this_weak.upgrade().unwrap();
+                remove(&this, this_weak.clone());
+            });
+            row.pack_end(&button, false, false, 0);
+        }
+        row.pack_end(&gtk::Label::new(Some(status)), false, false, 0);
+        row.show_all();
+        self.ui.members.add(&row);
+    }
+
+    fn show_status(&self, message: &str) {
+        self.ui.status.set_text(message);
+        self.ui.status.set_opacity(1.0);
+    }
+
+    fn team_error_to_str(&self, err: &TeamError) -> String {
+        match err {
+            TeamError::NotAGroupEvent => String::from("That event is not a group event"),
+            TeamError::TeamSize { min, max } => {
+                format!("A team needs {} to {} members, leader included", min, max)
+            }
+            TeamError::EventFull => String::from("The event is full"),
+            TeamError::UnknownEvent => String::from("Event not found"),
+            TeamError::UnknownTeam => String::from("Team not found"),
+            TeamError::UnknownParticipant(id) => format!("Participant {} not found", id),
+            TeamError::AlreadyInTeam(id) => {
+                format!("Participant {} is already in a team for this event", id)
+            }
+            TeamError::RemoveLeader => String::from("The leader cannot be removed"),
+            TeamError::AmountDue(id) => format!(
+                "Participant {} still owes part of the fee; collect it on the verification screen",
+                id
+            ),
+            TeamError::NoEvents(id) => format!("Participant {} is not registered for events", id),
+        }
+    }
+
+    fn state_busy(&self) {
+        set_sensitive!(false, self.ui{
+            team_id,
+            load_team,
+            team_name,
+            event,
+            leader,
+            member,
+            add_member,
+            members,
+            register,
+            back
+        });
+    }
+
+    fn state_default(&self) {
+        set_sensitive!(true, self.ui{
+            team_id,
+            load_team,
+            member,
+            add_member,
+            members,
+            register,
+            back
+        });
+        // The name, event and leader of a registered team cannot change.
+        let registered = self.team.borrow().is_some();
+        self.ui.team_name.set_sensitive(!registered);
+        self.ui.event.set_sensitive(!registered);
+        self.ui.leader.set_sensitive(!registered);
+        self.ui.register.set_label(if registered {
+            "Verify Team"
+        } else {
+            "Register Team"
+        });
+    }
+}
+
+fn team_code(id: i32) -> String {
+    format!("T{}", id)
+}
+
+fn parse_team_code(code: &str) -> Option<i32> {
+    let code = code.trim();
+    code.strip_prefix('T').unwrap_or(code).parse().ok()
+}
+
+impl View for TeamReg {
+    fn get_root_widget(&self) -> &gtk::Widget {
+        &self.ui.root
+    }
+
+    fn enter(&self, _route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let Some(reg_desk) = reg_desk {
+            self.set_reg_desk(reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Search => shortcuts::focus(&self.ui.team_id),
+            // Registering the team verifies its members.
+            Shortcut::Verify | Shortcut::Save => shortcuts::click(&self.ui.register),
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
+}
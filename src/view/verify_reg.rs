@@ -1,33 +1,49 @@
+use super::event_picker::{self, EventPicker};
 use super::main_view::View;
+use super::participant_code;
+use super::photo::show_thumbnail;
+use super::router::{Message, Route};
+use super::shortcuts::{self, Shortcut};
+use super::{run_with_desk, CONNECTION_LOST};
 use crate::repository::*;
-use glib;
 use gtk;
 use gtk::prelude::*;
 use std::cell::Cell;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct VerifyReg {
     ui: VerifyRegUI,
     participant: Cell<Option<Participant>>,
     reg_desk: Cell<Option<Box<dyn IRegDesk>>>,
     callback: Box<dyn Fn(Message)>,
-}
-
-pub enum Message {
-    Back(Participant, Box<dyn IRegDesk>),
-    UpdateDetails(Participant, Box<dyn IRegDesk>),
-    ResetPassword(Participant, Box<dyn IRegDesk>),
+    event_picker: EventPicker,
+    // `None` until the payments are loaded.
+    amount_due: Cell<Option<i64>>,
+    edition: u16,
 }
 
 ui_struct! {
     struct VerifyRegUI {
         root: gtk::Widget,
         ragam_id: gtk::Label,
+        photo: gtk::Image,
         name: gtk::Label,
         gender: gtk::Label,
         college: gtk::Label,
         email: gtk::Label,
         reg_status: gtk::Label,
+        events_box: gtk::Box,
+        events: gtk::ListBox,
+        events_error: gtk::Label,
+        save_events: gtk::Button,
+        payment_box: gtk::Box,
+        fee_due: gtk::Label,
+        payments: gtk::ListBox,
+        amount: gtk::Entry,
+        payment_mode: gtk::ComboBoxText,
+        reference: gtk::Entry,
+        record_payment: gtk::Button,
+        payment_error: gtk::Label,
         back: gtk::Button,
         verify_reg: gtk::Button,
         update_details: gtk::Button,
@@ -36,18 +52,23 @@ ui_struct! {
 }
 
 impl VerifyReg {
-    pub fn new(callback: Box<dyn Fn(Message)>) -> Rc<Self> {
+    pub fn new(callback: Box<dyn Fn(Message)>, edition: u16) -> Rc<Self> {
         let glade_src = include_str!(concat!(
             env!("CARGO_MANIFEST_DIR"),
             "/src/ui/verify_reg.glade"
         ));
         let builder = gtk::Builder::new_from_string(glade_src);
 
+        let ui = VerifyRegUI::build(builder);
+        let event_picker = EventPicker::new(ui.events.clone());
         let ret = Rc::from(VerifyReg {
-            ui: VerifyRegUI::build(builder),
+            ui,
             callback,
+            event_picker,
+            amount_due: Cell::from(None),
             participant: Cell::from(None),
             reg_desk: Cell::from(None),
+            edition,
         });
 
         Self::initialize_callbacks(ret.clone());
@@ -60,68 +81,284 @@ impl VerifyReg {
         participant: Participant,
         reg_desk: Box<dyn IRegDesk>,
     ) {
+        self.amount_due.set(None);
         self.state_default();
         self.load_participant(&participant);
+        self.ui.events_error.set_opacity(0.0);
+        self.event_picker.clear();
+        self.ui.payment_error.set_opacity(0.0);
+        self.ui.payments.foreach(|child| self.ui.payments.remove(child));
+        self.ui.fee_due.set_text("");
+        show_thumbnail(&self.ui.photo, None);
+        // Photo, events and payments are loaded once the screen is shown; see connect_map.
+        self.ui
+            .events_box
+            .set_visible(participant.info.category.events);
         self.participant.replace(Some(participant));
         self.reg_desk.replace(Some(reg_desk));
     }
 
+    fn load_details(&self, this_weak: Weak<Self>) {
+        self.state_busy();
+        let reg_desk = self.reg_desk.take().unwrap();
+        let participant = self.participant.take().unwrap();
+        let load_events = self.ui.events_box.get_visible();
+
+        let shown = participant.clone();
+        let work = move |reg_desk: &mut dyn IRegDesk| {
+            // Back from another screen shows the participant as they are now.
+            let participant = reg_desk.participant_get(shown.id()).unwrap_or(shown);
+            let events = if load_events {
+                Some((reg_desk.event_list(), reg_desk.participant_events(participant.id())))
+            } else {
+                None
+            };
+            let payments = reg_desk.participant_payments(participant.id());
+            let due = reg_desk.participant_amount_due(participant.id());
+            let thumbnail = reg_desk.participant_thumbnail(participant.id());
+            (events, payments, due, thumbnail, participant)
+        };
+        run_with_desk(reg_desk, work, move |details, reg_desk| {
+            let this = this_weak.upgrade().unwrap();
+            let (events, payments, due, thumbnail, participant) = match details {
+                Some(details) => details,
+                None => {
+                    this.state_default();
+                    this.load_participant(&participant);
+                    this.participant.set(Some(participant));
+                    this.reg_desk.set(Some(reg_desk));
+                    this.show_connection_lost();
+                    return;
+                }
+            };
+            show_thumbnail(&this.ui.photo, thumbnail.as_deref());
+            if let Some((events, enrolled)) = events {
+                this.event_picker.load(events, &enrolled);
+            }
+            this.load_payments(&payments, due);
+            this.state_default();
+            this.load_participant(&participant);
+            this.participant.set(Some(participant));
+            this.reg_desk.set(Some(reg_desk));
+        });
+    }
+
+    fn load_payments(&self, payments: &[Payment], due: i64) {
+        self.amount_due.set(Some(due));
+        self.ui.payments.foreach(|child| self.ui.payments.remove(child));
+        for payment in payments {
+            let mut text = format!(
+                "{}  \u{20b9}{}  {}",
+                payment.receipt_no,
+                payment.info.amount,
+                payment_mode_to_str(payment.info.mode)
+            );
+            if !payment.info.reference.is_empty() {
+                text += &format!(" ({})", payment.info.reference);
+            }
+            text += &format!("  by {}", payment.admin.name);
+            let row = gtk::Label::new(Some(text.as_str()));
+            row.set_halign(gtk::Align::Start);
+            row.show();
+            self.ui.payments.add(&row);
+        }
+        if due > 0 {
+            self.ui.fee_due.set_text(&format!("Amount due: \u{20b9}{}", due));
+            self.ui.amount.set_text(&due.to_string());
+        } else {
+            self.ui.fee_due.set_text("Fee paid");
+            self.ui.amount.set_text("");
+        }
+        self.ui.reference.set_text("");
+        self.ui.payment_box.set_visible(due > 0 || !payments.is_empty());
+    }
+
+    fn show_payment_error(&self, message: &str) {
+        self.ui.payment_error.set_text(message);
+        self.ui.payment_error.set_opacity(1.0);
+    }
+
+    /// Shown under the payments, which are brought on screen if hidden.
+    fn show_connection_lost(&self) {
+        self.ui.payment_box.set_visible(true);
+        self.show_payment_error(CONNECTION_LOST);
+    }
+
+    fn payment_from_fields(&self) -> Result<PaymentInfo, &'static str> {
+        let amount = self
+            .ui
+            .amount
+            .get_text()
+            .unwrap()
+            .trim()
+            .parse()
+            .map_err(|_| "Enter the amount in rupees")?;
+        let mode = match self.ui.payment_mode.get_active_id().as_ref().map(|id| id.as_str()) {
+            Some("upi") => PaymentMode::Upi,
+            Some("card") => PaymentMode::Card,
+            _ => PaymentMode::Cash,
+        };
+        Ok(PaymentInfo {
+            amount,
+            mode,
+            reference: self.ui.reference.get_text().unwrap().to_string(),
+        })
+    }
+
+    fn show_enrolment_errors(&self, errors: &[String]) {
+        self.ui.events_error.set_text(&errors.join(" "));
+        self.ui
+            .events_error
+            .set_opacity(if errors.is_empty() { 0.0 } else { 1.0 });
+    }
+
     fn initialize_callbacks(this: Rc<Self>) {
         let this_weak = Rc::downgrade(&this);
 
+        this.ui.root.connect_map(clone! {this_weak => move |_| {
+            let this = this_weak.upgrade().unwrap();
+            this.load_details(this_weak.clone());
+        }});
+
         this.ui
-            .verify_reg
-            .connect_clicked(clone! {this_weak => move |_|{
+            .save_events
+            .connect_clicked(clone! {this_weak => move |_| {
                 let this = this_weak.upgrade().unwrap();
-                this.state_verifying();
+                this.state_busy();
 
-                let mut reg_desk = this.reg_desk.take().unwrap();
+                let reg_desk = this.reg_desk.take().unwrap();
                 let participant = this.participant.take().unwrap();
-                let reg_not_verfied: ParticipantRegNotVerified = participant.reg_status.err().unwrap();
+                let selected = this.event_picker.selected();
 
-                let (tx, rx) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
-                std::thread::spawn(move || {
-                    tx.send((reg_desk.participant_verify_reg(reg_not_verfied), reg_desk))
+                let participant_id = participant.id();
+                let work = move |reg_desk: &mut dyn IRegDesk| {
+                    let errors = event_picker::save_enrolment(reg_desk, participant_id, &selected);
+                    let events = reg_desk.event_list();
+                    let enrolled = reg_desk.participant_events(participant_id);
+                    (errors, events, enrolled)
+                };
+                let this_weak = this_weak.clone();
+                run_with_desk(reg_desk, work, move |saved, reg_desk| {
+                    let this = this_weak.upgrade().unwrap();
+                    match saved {
+                        Some((errors, events, enrolled)) => {
+                            this.event_picker.load(events, &enrolled);
+                            this.show_enrolment_errors(&errors);
+                        }
+                        None => this.show_enrolment_errors(&[String::from(CONNECTION_LOST)]),
+                    }
+                    this.state_default();
+                    this.load_participant(&participant);
+                    this.participant.set(Some(participant));
+                    this.reg_desk.set(Some(reg_desk));
                 });
+            }});
 
+        this.ui
+            .record_payment
+            .connect_clicked(clone! {this_weak => move |_| {
+                let this = this_weak.upgrade().unwrap();
+                let info = match this.payment_from_fields() {
+                    Ok(info) => info,
+                    Err(message) => return this.show_payment_error(message),
+                };
+                this.ui.payment_error.set_opacity(0.0);
+                this.state_busy();
+
+                let reg_desk = this.reg_desk.take().unwrap();
+                let participant = this.participant.take().unwrap();
+
+                let participant_id = participant.id();
+                let work = move |reg_desk: &mut dyn IRegDesk| {
+                    let result = reg_desk.payment_add(participant_id, info).map(|_| ());
+                    let payments = reg_desk.participant_payments(participant_id);
+                    let due = reg_desk.participant_amount_due(participant_id);
+                    (result, payments, due)
+                };
                 let this_weak = this_weak.clone();
-                rx.attach(None, move |(participant, reg_desk)| {
+                run_with_desk(reg_desk, work, move |recorded, reg_desk| {
                     let this = this_weak.upgrade().unwrap();
+                    match recorded {
+                        Some((result, payments, due)) => {
+                            this.load_payments(&payments, due);
+                            if let Err(err) = result {
+                                this.show_payment_error(&payment_error_to_str(&err));
+                            }
+                        }
+                        None => this.show_connection_lost(),
+                    }
+                    this.state_default();
                     this.load_participant(&participant);
                     this.participant.set(Some(participant));
                     this.reg_desk.set(Some(reg_desk));
-                    this.state_verified();
-                    glib::source::Continue(false)
+                });
+            }});
+
+        this.ui
+            .verify_reg
+            .connect_clicked(clone! {this_weak => move |_|{
+                let this = this_weak.upgrade().unwrap();
+                this.state_verifying();
+
+                let reg_desk = this.reg_desk.take().unwrap();
+                let participant = this.participant.take().unwrap();
+                let reg_not_verfied: ParticipantRegNotVerified = participant.reg_status.clone().err().unwrap();
+
+                let work = move |reg_desk: &mut dyn IRegDesk| reg_desk.participant_verify_reg(reg_not_verfied);
+                let this_weak = this_weak.clone();
+                run_with_desk(reg_desk, work, move |result, reg_desk| {
+                    let this = this_weak.upgrade().unwrap();
+                    let last_action = reg_desk.last_action();
+                    this.reg_desk.set(Some(reg_desk));
+                    let result = match result {
+                        Some(result) => result,
+                        None => {
+                            this.participant.set(Some(participant));
+                            this.state_default();
+                            this.show_connection_lost();
+                            return;
+                        }
+                    };
+                    match result {
+                        Ok(participant) => {
+                            this.load_participant(&participant);
+                            this.participant.set(Some(participant));
+                            this.state_verified();
+                            if let Some(last_action) = last_action {
+                                (this.callback)(Message::Undoable(last_action));
+                            }
+                        }
+                        Err(VerifyError::AmountDue(due)) => {
+                            // Another desk may have changed the payments; show them again.
+                            this.participant.set(Some(participant));
+                            this.show_payment_error(&format!("\u{20b9}{} is still due", due));
+                            this.load_details(this_weak.clone());
+                        }
+                    }
                 });
             }});
 
         this.ui.back.connect_clicked(clone!{this_weak => move |_| {
             let this = this_weak.upgrade().unwrap();
-            (this.callback)(Message::Back(this.participant.take().unwrap(), this.reg_desk.take().unwrap()));
+            (this.callback)(Message::Back(this.reg_desk.take().unwrap()));
         }});
 
         this.ui.update_details.connect_clicked(clone!{this_weak => move |_| {
             let this = this_weak.upgrade().unwrap();
-            (this.callback)(Message::UpdateDetails(this.participant.take().unwrap(), this.reg_desk.take().unwrap()));
-        }});
-
-        this.ui.reset_password.connect_clicked(clone!{this_weak => move |_| {
-            let this = this_weak.upgrade().unwrap();
-            (this.callback)(Message::ResetPassword(this.participant.take().unwrap(), this.reg_desk.take().unwrap()));
+            let route = Route::UpdateParticipant(this.participant.take().unwrap());
+            (this.callback)(Message::Open(route, this.reg_desk.take().unwrap()));
         }});
     }
 
     fn load_participant(&self, participant: &Participant) {
-        let id = match participant.info.category {
-            ParticipantCategory::Kalotsavam => format!("K19{:06}", participant.id()),
-            ParticipantCategory::Ragam => format!("R19{:06}", participant.id()),
-        };
-        self.ui.ragam_id.set_text(&id);
+        self.ui
+            .ragam_id
+            .set_text(&participant_code(participant, self.edition));
         self.ui.name.set_text(&participant.info.name);
         self.ui
             .gender
             .set_text(gender_to_str(&participant.info.gender));
-        self.ui.college.set_text(&participant.college.name);
+        self.ui.college.set_text(&participant.college.label());
         self.ui.email.set_text(&participant.info.email);
         match participant.reg_status {
             Ok(ref reg_verified) => {
@@ -134,7 +371,9 @@ impl VerifyReg {
             Err(ref _reg_not_verified) => {
                 self.ui.reg_status.set_text("Unverifed");
                 self.ui.verify_reg.set_label("Verify");
-                self.ui.verify_reg.set_sensitive(true);
+                self.ui
+                    .verify_reg
+                    .set_sensitive(self.amount_due.get() == Some(0));
             }
         }
     }
@@ -145,7 +384,13 @@ impl VerifyReg {
             back,
             verify_reg,
             update_details,
-            reset_password
+            reset_password,
+            events,
+            save_events,
+            amount,
+            payment_mode,
+            reference,
+            record_payment
         });
     }
 
@@ -155,7 +400,23 @@ impl VerifyReg {
             back,
             verify_reg,
             update_details,
-            reset_password
+            reset_password,
+            record_payment
+        });
+    }
+
+    fn state_busy(&self) {
+        set_sensitive!(false, self.ui{
+            back,
+            verify_reg,
+            update_details,
+            reset_password,
+            events,
+            save_events,
+            amount,
+            payment_mode,
+            reference,
+            record_payment
         });
     }
 
@@ -168,7 +429,26 @@ impl VerifyReg {
     }
 }
 
-fn gender_to_str(gender: &Gender) -> &str {
+pub fn payment_mode_to_str(mode: PaymentMode) -> &'static str {
+    match mode {
+        PaymentMode::Cash => "Cash",
+        PaymentMode::Upi => "UPI",
+        PaymentMode::Card => "Card",
+    }
+}
+
+pub fn payment_error_to_str(err: &PaymentError) -> String {
+    match err {
+        PaymentError::InvalidAmount => String::from("The amount must be more than zero"),
+        PaymentError::ReferenceRequired => {
+            String::from("Enter the UPI transaction id or card slip number")
+        }
+        PaymentError::Overpaid(due) => format!("Only \u{20b9}{} is due", due),
+        PaymentError::UnknownParticipant => String::from("Participant not found"),
+    }
+}
+
+pub fn gender_to_str(gender: &Gender) -> &str {
     match gender {
         Gender::Male => "Male",
         Gender::Female => "Female",
@@ -180,4 +460,27 @@ impl View for VerifyReg {
     fn get_root_widget(&self) -> &gtk::Widget {
         &self.ui.root
     }
+
+    fn enter(&self, route: Route, reg_desk: Option<Box<dyn IRegDesk>>) {
+        if let (Route::VerifyReg(participant), Some(reg_desk)) = (route, reg_desk) {
+            self.set_participant_and_reg_desk(participant, reg_desk);
+        }
+    }
+
+    fn take_reg_desk(&self) -> Option<Box<dyn IRegDesk>> {
+        self.reg_desk.take()
+    }
+
+    fn put_reg_desk(&self, reg_desk: Box<dyn IRegDesk>) {
+        self.reg_desk.set(Some(reg_desk));
+    }
+
+    fn shortcut(&self, shortcut: Shortcut) -> bool {
+        match shortcut {
+            Shortcut::Verify => shortcuts::click(&self.ui.verify_reg),
+            Shortcut::Save => shortcuts::click(&self.ui.save_events),
+            Shortcut::Back => shortcuts::click(&self.ui.back),
+            _ => false,
+        }
+    }
 }